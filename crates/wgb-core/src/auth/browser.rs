@@ -0,0 +1,41 @@
+//! Authenticator opening the 2FA page in the browser.
+
+use super::{AuthError, Authenticator, Interaction};
+
+/// Opens the 2FA page, where the user enters the PIN.
+#[derive(Debug, Clone)]
+pub struct BrowserAuthenticator {
+    uri: String,
+}
+
+impl BrowserAuthenticator {
+    /// Creates an authenticator opening `uri`.
+    pub fn new(uri: impl Into<String>) -> Self {
+        Self { uri: uri.into() }
+    }
+}
+
+impl Authenticator for BrowserAuthenticator {
+    fn authenticate(&self, ui: &mut dyn Interaction) -> Result<(), AuthError> {
+        ui.open(&self.uri);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::stand_in::{StandIn, User};
+
+    #[test]
+    fn opens_the_page_without_requesting_it() {
+        let server = StandIn::start(vec![(200, "{}")]);
+        let uri = server.uri("/2fa?user=alice");
+        let mut user = User::default();
+        BrowserAuthenticator::new(&uri)
+            .authenticate(&mut user)
+            .unwrap();
+        assert_eq!(user.opened, [uri]);
+        assert!(server.requests().is_empty());
+    }
+}
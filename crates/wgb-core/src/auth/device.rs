@@ -0,0 +1,278 @@
+//! Authenticator running the OAuth 2.0 device authorization grant.
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+use zeroize::Zeroizing;
+
+use super::{http_error, AuthError, Authenticator, Interaction, HTTP_TIMEOUT};
+
+/// Grant type of the token requests, RFC 8628 section 3.4.
+const GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+/// Polling interval when the server gives none, RFC 8628 section 3.2.
+const DEFAULT_INTERVAL: u64 = 5;
+
+/// Runs the OAuth 2.0 device authorization grant (RFC 8628): shows the user
+/// a code to enter on the verification page, then polls the token endpoint
+/// until the user approved the connection.
+#[derive(Debug, Clone)]
+pub struct DeviceAuthenticator {
+    pub device_authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub client_id: String,
+    pub scope: Option<String>,
+    /// Page the access token is posted to, as a bearer token, once granted.
+    pub uri: Option<String>,
+}
+
+/// Answer of the device authorization endpoint.
+#[derive(Deserialize)]
+struct DeviceAuthorization {
+    device_code: String,
+    user_code: String,
+    verification_uri: String,
+    verification_uri_complete: Option<String>,
+    expires_in: u64,
+    interval: Option<u64>,
+}
+
+/// Answer of the token endpoint, either a token or an error.
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: Option<String>,
+    error: Option<String>,
+    error_description: Option<String>,
+}
+
+impl DeviceAuthenticator {
+    /// Asks the authorization server for a device code.
+    fn authorize(&self) -> Result<DeviceAuthorization, AuthError> {
+        let uri = &self.device_authorization_endpoint;
+        let mut form = vec![("client_id", self.client_id.as_str())];
+        if let Some(scope) = &self.scope {
+            form.push(("scope", scope));
+        }
+        let response = ureq::post(uri)
+            .timeout(HTTP_TIMEOUT)
+            .set("Accept", "application/json")
+            .send_form(&form)
+            .map_err(|err| http_error(uri, err))?;
+        parse(uri, response)
+    }
+
+    /// Asks the token endpoint whether `device_code` was approved.
+    fn poll(&self, device_code: &str) -> Result<TokenResponse, AuthError> {
+        let uri = &self.token_endpoint;
+        let result = ureq::post(uri)
+            .timeout(HTTP_TIMEOUT)
+            .set("Accept", "application/json")
+            .send_form(&[
+                ("grant_type", GRANT_TYPE),
+                ("device_code", device_code),
+                ("client_id", &self.client_id),
+            ]);
+        match result {
+            Ok(response) => parse(uri, response),
+            // Pending, slowed down, denied and expired requests are answered
+            // with 400 and an error code.
+            Err(ureq::Error::Status(400, response)) => parse(uri, response),
+            Err(err) => Err(http_error(uri, err)),
+        }
+    }
+}
+
+impl Authenticator for DeviceAuthenticator {
+    fn authenticate(&self, ui: &mut dyn Interaction) -> Result<(), AuthError> {
+        let authorization = self.authorize()?;
+        ui.notify(&format!(
+            "Enter the code {} at {}",
+            authorization.user_code, authorization.verification_uri
+        ));
+        ui.open(
+            authorization
+                .verification_uri_complete
+                .as_deref()
+                .unwrap_or(&authorization.verification_uri),
+        );
+
+        let deadline = Instant::now() + Duration::from_secs(authorization.expires_in);
+        let mut interval = authorization.interval.unwrap_or(DEFAULT_INTERVAL);
+        let token = loop {
+            if Instant::now() >= deadline {
+                return Err(AuthError::Expired);
+            }
+            thread::sleep(Duration::from_secs(interval));
+            let response = self.poll(&authorization.device_code)?;
+            if let Some(token) = response.access_token {
+                break Zeroizing::new(token);
+            }
+            match response.error.as_deref() {
+                Some("authorization_pending") => {}
+                Some("slow_down") => interval += 5,
+                Some("access_denied") => return Err(AuthError::Denied),
+                Some("expired_token") => return Err(AuthError::Expired),
+                error => {
+                    return Err(AuthError::Protocol {
+                        uri: self.token_endpoint.clone(),
+                        message: response
+                            .error_description
+                            .or(error.map(str::to_owned))
+                            .unwrap_or_else(|| "neither a token nor an error".to_owned()),
+                    })
+                }
+            }
+        };
+
+        if let Some(uri) = &self.uri {
+            ureq::post(uri)
+                .timeout(HTTP_TIMEOUT)
+                .set("Authorization", &format!("Bearer {}", token.as_str()))
+                .call()
+                .map_err(|err| http_error(uri, err))?;
+        }
+        ui.notify("2FA authorization granted");
+        Ok(())
+    }
+}
+
+/// Reads the JSON body of `response`, from `uri`.
+fn parse<T: for<'de> Deserialize<'de>>(
+    uri: &str,
+    response: ureq::Response,
+) -> Result<T, AuthError> {
+    let protocol = |message: String| AuthError::Protocol {
+        uri: uri.to_owned(),
+        message,
+    };
+    let text = response
+        .into_string()
+        .map_err(|err| protocol(err.to_string()))?;
+    serde_json::from_str(&text).map_err(|err| protocol(err.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::stand_in::{StandIn, User};
+
+    const AUTHORIZATION: &str = r#"{"device_code": "dev-42", "user_code": "WDJB-MJHT",
+        "verification_uri": "https://example.com/device",
+        "verification_uri_complete": "https://example.com/device?user_code=WDJB-MJHT",
+        "expires_in": 60, "interval": 0}"#;
+
+    fn authenticator(server: &StandIn, uri: Option<String>) -> DeviceAuthenticator {
+        DeviceAuthenticator {
+            device_authorization_endpoint: server.uri("/device"),
+            token_endpoint: server.uri("/token"),
+            client_id: "wgb".to_owned(),
+            scope: Some("vpn".to_owned()),
+            uri,
+        }
+    }
+
+    #[test]
+    fn polls_until_the_token_is_granted() {
+        let server = StandIn::start(vec![
+            (200, AUTHORIZATION),
+            (400, r#"{"error": "authorization_pending"}"#),
+            (400, r#"{"error": "authorization_pending"}"#),
+            (
+                200,
+                r#"{"access_token": "secret-token", "token_type": "Bearer"}"#,
+            ),
+            (200, "{}"),
+        ]);
+        let uri = server.uri("/2fa");
+        let mut user = User::default();
+        authenticator(&server, Some(uri))
+            .authenticate(&mut user)
+            .unwrap();
+
+        assert_eq!(
+            user.notified,
+            [
+                "Enter the code WDJB-MJHT at https://example.com/device",
+                "2FA authorization granted"
+            ]
+        );
+        assert_eq!(
+            user.opened,
+            ["https://example.com/device?user_code=WDJB-MJHT"]
+        );
+
+        let requests = server.requests();
+        let paths: Vec<&str> = requests.iter().map(|r| r.path.as_str()).collect();
+        assert_eq!(paths, ["/device", "/token", "/token", "/token", "/2fa"]);
+        assert_eq!(requests[0].body, "client_id=wgb&scope=vpn");
+        assert_eq!(
+            requests[1].body,
+            "grant_type=urn%3Aietf%3Aparams%3Aoauth%3Agrant-type%3Adevice_code\
+             &device_code=dev-42&client_id=wgb"
+        );
+        assert_eq!(
+            requests[4].header("Authorization"),
+            Some("Bearer secret-token")
+        );
+    }
+
+    #[test]
+    fn slows_down_when_asked() {
+        let server = StandIn::start(vec![
+            (200, AUTHORIZATION),
+            (400, r#"{"error": "slow_down"}"#),
+            (200, r#"{"access_token": "secret-token"}"#),
+        ]);
+        let start = Instant::now();
+        authenticator(&server, None)
+            .authenticate(&mut User::default())
+            .unwrap();
+        assert!(start.elapsed() >= Duration::from_secs(5));
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[test]
+    fn stops_when_the_code_expired() {
+        let server = StandIn::start(vec![
+            (200, AUTHORIZATION),
+            (400, r#"{"error": "authorization_pending"}"#),
+            (400, r#"{"error": "expired_token"}"#),
+        ]);
+        let err = authenticator(&server, Some(server.uri("/2fa")))
+            .authenticate(&mut User::default())
+            .unwrap_err();
+        assert!(matches!(err, AuthError::Expired), "{err}");
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[test]
+    fn stops_when_the_user_refused() {
+        let server = StandIn::start(vec![
+            (200, AUTHORIZATION),
+            (400, r#"{"error": "access_denied"}"#),
+        ]);
+        let err = authenticator(&server, None)
+            .authenticate(&mut User::default())
+            .unwrap_err();
+        assert!(matches!(err, AuthError::Denied), "{err}");
+    }
+
+    #[test]
+    fn reports_an_unknown_error() {
+        let server = StandIn::start(vec![
+            (200, AUTHORIZATION),
+            (
+                400,
+                r#"{"error": "invalid_client", "error_description": "unknown client wgb"}"#,
+            ),
+        ]);
+        match authenticator(&server, None).authenticate(&mut User::default()) {
+            Err(AuthError::Protocol { uri, message }) => {
+                assert_eq!(uri, server.uri("/token"));
+                assert_eq!(message, "unknown client wgb");
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+}
@@ -0,0 +1,148 @@
+//! Authenticator submitting the PIN to the 2FA page as an HTML form.
+
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use zeroize::Zeroizing;
+
+use super::{http_error, AuthError, Authenticator, Interaction, HTTP_TIMEOUT};
+use crate::totp::{Seed, Settings};
+
+/// Shortest validity of a TOTP code worth submitting; closer to the end of
+/// its period, the next code is awaited.
+const TOTP_MARGIN: Duration = Duration::from_secs(3);
+
+/// Submits the PIN to the 2FA page as an `application/x-www-form-urlencoded`
+/// POST request.
+#[derive(Debug)]
+pub struct FormAuthenticator {
+    uri: String,
+    field: String,
+    totp: Option<(Settings, Seed)>,
+}
+
+impl FormAuthenticator {
+    /// Creates an authenticator posting the PIN to `uri` as the `field`
+    /// field. The PIN is computed from `totp` when given, asked to the user
+    /// otherwise.
+    pub fn new(
+        uri: impl Into<String>,
+        field: impl Into<String>,
+        totp: Option<(Settings, Seed)>,
+    ) -> Self {
+        Self {
+            uri: uri.into(),
+            field: field.into(),
+            totp,
+        }
+    }
+
+    /// Returns the PIN to submit.
+    fn pin(&self, ui: &mut dyn Interaction) -> Result<Zeroizing<String>, AuthError> {
+        let Some((settings, seed)) = &self.totp else {
+            let pin = ui.ask_secret("2FA PIN: ")?;
+            if pin.trim().is_empty() {
+                return Err(AuthError::NoPin);
+            }
+            return Ok(pin);
+        };
+        // A code about to expire could be refused by the time it arrives.
+        let mut now = SystemTime::now();
+        let remaining = settings.remaining(now);
+        if remaining < TOTP_MARGIN {
+            thread::sleep(remaining);
+            now = SystemTime::now();
+        }
+        Ok(Zeroizing::new(settings.code(seed, now)))
+    }
+}
+
+impl Authenticator for FormAuthenticator {
+    fn authenticate(&self, ui: &mut dyn Interaction) -> Result<(), AuthError> {
+        let pin = self.pin(ui)?;
+        ureq::post(&self.uri)
+            .timeout(HTTP_TIMEOUT)
+            .send_form(&[(&self.field, pin.trim())])
+            .map_err(|err| http_error(&self.uri, err))?;
+        ui.notify("2FA code submitted");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::stand_in::{StandIn, User};
+
+    const SEED: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+    #[test]
+    fn posts_the_pin_of_the_user() {
+        let server = StandIn::start(vec![(200, "{}")]);
+        let authenticator = FormAuthenticator::new(server.uri("/2fa"), "token", None);
+        let mut user = User {
+            pin: " 123456\n".to_owned(),
+            ..User::default()
+        };
+        authenticator.authenticate(&mut user).unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].method, "POST");
+        assert_eq!(requests[0].path, "/2fa");
+        assert_eq!(
+            requests[0].header("Content-Type"),
+            Some("application/x-www-form-urlencoded")
+        );
+        assert_eq!(requests[0].body, "token=123456");
+        assert_eq!(user.notified, ["2FA code submitted"]);
+        assert!(user.opened.is_empty());
+    }
+
+    #[test]
+    fn posts_the_code_of_the_totp_seed() {
+        let server = StandIn::start(vec![(200, "{}")]);
+        let settings = Settings::default();
+        let seed = Seed::from_base32(SEED).unwrap();
+        let authenticator =
+            FormAuthenticator::new(server.uri("/2fa"), "otp", Some((settings.clone(), seed)));
+        authenticator.authenticate(&mut User::default()).unwrap();
+
+        // The code was submitted with at least TOTP_MARGIN left.
+        let code = settings.code(&Seed::from_base32(SEED).unwrap(), SystemTime::now());
+        assert_eq!(server.requests()[0].body, format!("otp={code}"));
+    }
+
+    #[test]
+    fn refuses_an_empty_pin() {
+        let server = StandIn::start(vec![(200, "{}")]);
+        let authenticator = FormAuthenticator::new(server.uri("/2fa"), "token", None);
+        let err = authenticator
+            .authenticate(&mut User::default())
+            .unwrap_err();
+        assert!(matches!(err, AuthError::NoPin), "{err}");
+        assert!(server.requests().is_empty());
+    }
+
+    #[test]
+    fn reports_the_status_of_a_refused_pin() {
+        let server = StandIn::start(vec![(500, "{}")]);
+        let uri = server.uri("/2fa");
+        let authenticator = FormAuthenticator::new(&uri, "token", None);
+        let mut user = User {
+            pin: "000000".to_owned(),
+            ..User::default()
+        };
+        match authenticator.authenticate(&mut user) {
+            Err(AuthError::Http {
+                uri: failed,
+                message,
+            }) => {
+                assert_eq!(failed, uri);
+                assert_eq!(message, "500 Internal Server Error");
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+        assert!(user.notified.is_empty());
+    }
+}
@@ -0,0 +1,219 @@
+//! Authenticators answering the 2FA step of token-protected profiles.
+//!
+//! Every authenticator implements [`Authenticator`]; the one used by a
+//! profile is chosen by `confs[].auth`, see [`AuthMethod`]:
+//!
+//! - [`BrowserAuthenticator`] opens the 2FA page, where the user enters the
+//!   PIN, like the original scripts;
+//! - [`FormAuthenticator`] submits the PIN, typed by the user or computed
+//!   from the TOTP seed, as an HTML form;
+//! - [`DeviceAuthenticator`] runs the OAuth 2.0 device authorization grant
+//!   (RFC 8628), the user approving the connection on another device.
+//!
+//! The endpoints are plain URLs taken from the configuration, so every
+//! authenticator can be exercised against a local HTTP server.
+
+mod browser;
+mod device;
+mod form;
+#[cfg(test)]
+mod stand_in;
+
+use std::io;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+use crate::totp::{self, Seed, TotpError};
+
+pub use browser::BrowserAuthenticator;
+pub use device::DeviceAuthenticator;
+pub use form::FormAuthenticator;
+
+/// How long an HTTP endpoint may take to answer.
+const HTTP_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Errors raised while answering the 2FA step.
+#[derive(Debug, Error)]
+pub enum AuthError {
+    /// The TOTP seed could not be read.
+    #[error(transparent)]
+    Totp(#[from] TotpError),
+
+    /// The user could not be asked for the PIN.
+    #[error("unable to read the PIN: {0}")]
+    Io(#[from] io::Error),
+
+    /// The user gave no PIN.
+    #[error("no PIN given")]
+    NoPin,
+
+    /// The endpoint could not be reached or answered with an error.
+    #[error("the 2FA endpoint '{uri}' failed: {message}")]
+    Http { uri: String, message: String },
+
+    /// The endpoint answered something that does not follow the protocol.
+    #[error("unexpected answer from '{uri}': {message}")]
+    Protocol { uri: String, message: String },
+
+    /// The user refused the connection.
+    #[error("the authorization was denied")]
+    Denied,
+
+    /// The user did not approve the connection in time.
+    #[error("the authorization request expired")]
+    Expired,
+}
+
+/// What an authenticator needs from the user.
+pub trait Interaction {
+    /// Shows the page at `uri` to the user, in a browser when possible.
+    fn open(&mut self, uri: &str);
+
+    /// Asks the user for a secret, such as a PIN.
+    fn ask_secret(&mut self, prompt: &str) -> io::Result<Zeroizing<String>>;
+
+    /// Tells something to the user.
+    fn notify(&mut self, message: &str);
+}
+
+/// A way to answer the 2FA step of a profile.
+pub trait Authenticator {
+    /// Answers the 2FA step, returning once the server accepted it.
+    fn authenticate(&self, ui: &mut dyn Interaction) -> Result<(), AuthError>;
+}
+
+/// Settings of the authenticator of a profile, stored in `confs[].auth`.
+///
+/// `uri`, the 2FA page of the profile, is kept in `confs[].uri`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(tag = "method", rename_all = "lowercase", deny_unknown_fields)]
+pub enum AuthMethod {
+    /// Open `uri` in the browser.
+    #[default]
+    Browser,
+
+    /// Submit the PIN to `uri` as the `field` field of a form.
+    Form {
+        /// Name of the form field holding the PIN, `token` when not set.
+        #[serde(default = "default_field")]
+        field: String,
+
+        /// How the PIN is computed when the TOTP seed is stored; the user
+        /// is asked for it otherwise.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        totp: Option<totp::Settings>,
+    },
+
+    /// Run the OAuth 2.0 device authorization grant; the access token, if
+    /// `uri` is set, is then posted to it as a bearer token.
+    Device {
+        /// Where the device and user codes are requested.
+        device_authorization_endpoint: String,
+        /// Where the access token is polled for.
+        token_endpoint: String,
+        /// Identifier of wgb at the authorization server.
+        client_id: String,
+        /// Scope of the access requested.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        scope: Option<String>,
+    },
+}
+
+fn default_field() -> String {
+    "token".to_owned()
+}
+
+impl AuthMethod {
+    /// Returns the name used in the configuration.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Browser => "browser",
+            Self::Form { .. } => "form",
+            Self::Device { .. } => "device",
+        }
+    }
+
+    /// Returns whether the method needs the 2FA page of the profile.
+    pub fn needs_uri(&self) -> bool {
+        !matches!(self, Self::Device { .. })
+    }
+
+    /// Returns the TOTP settings, for the methods that compute the PIN.
+    pub fn totp(&self) -> Option<&totp::Settings> {
+        match self {
+            Self::Form { totp, .. } => totp.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Returns why the settings cannot be used, if they cannot.
+    pub fn check(&self) -> Result<(), String> {
+        match self {
+            Self::Browser => Ok(()),
+            Self::Form { field, totp } => {
+                if field.trim().is_empty() {
+                    return Err("field is empty".to_owned());
+                }
+                totp.as_ref().map_or(Ok(()), |totp| totp.check())
+            }
+            Self::Device {
+                device_authorization_endpoint,
+                token_endpoint,
+                client_id,
+                ..
+            } => [
+                (
+                    "device_authorization_endpoint",
+                    device_authorization_endpoint,
+                ),
+                ("token_endpoint", token_endpoint),
+                ("client_id", client_id),
+            ]
+            .into_iter()
+            .find(|(_, value)| value.trim().is_empty())
+            .map_or(Ok(()), |(key, _)| Err(format!("{key} is empty"))),
+        }
+    }
+
+    /// Creates the authenticator for the 2FA page `uri`. `seed` is the TOTP
+    /// seed of the profile, when stored.
+    pub fn build(&self, uri: &str, seed: Option<Seed>) -> Box<dyn Authenticator> {
+        match self {
+            Self::Browser => Box::new(BrowserAuthenticator::new(uri)),
+            Self::Form { field, totp } => {
+                let totp = totp.clone().zip(seed);
+                Box::new(FormAuthenticator::new(uri, field, totp))
+            }
+            Self::Device {
+                device_authorization_endpoint,
+                token_endpoint,
+                client_id,
+                scope,
+            } => Box::new(DeviceAuthenticator {
+                device_authorization_endpoint: device_authorization_endpoint.clone(),
+                token_endpoint: token_endpoint.clone(),
+                client_id: client_id.clone(),
+                scope: scope.clone(),
+                uri: Some(uri.to_owned()).filter(|uri| !uri.trim().is_empty()),
+            }),
+        }
+    }
+}
+
+/// Maps a failed HTTP request to `uri` to an [`AuthError::Http`].
+fn http_error(uri: &str, err: ureq::Error) -> AuthError {
+    let message = match err {
+        ureq::Error::Status(status, response) => {
+            format!("{status} {}", response.status_text())
+        }
+        err => err.to_string(),
+    };
+    AuthError::Http {
+        uri: uri.to_owned(),
+        message,
+    }
+}
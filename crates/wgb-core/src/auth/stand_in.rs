@@ -0,0 +1,139 @@
+//! A local HTTP server answering the requests of the authenticators with
+//! canned responses, and a user answering them, for the tests.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use zeroize::Zeroizing;
+
+use super::Interaction;
+
+/// A request received by the [`StandIn`].
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Request {
+    /// Returns the value of the header `name`.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Answers the requests, in order, with the status and JSON body of
+/// `responses`, one per connection.
+pub struct StandIn {
+    uri: String,
+    requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl StandIn {
+    pub fn start(responses: Vec<(u16, &'static str)>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let uri = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let received = Arc::clone(&requests);
+        thread::spawn(move || {
+            for (status, body) in responses {
+                let Ok((stream, _)) = listener.accept() else {
+                    return;
+                };
+                if let Err(err) = answer(stream, &received, status, body) {
+                    eprintln!("stand-in failed: {err}");
+                }
+            }
+        });
+        Self { uri, requests }
+    }
+
+    /// Returns the URI of `path` on the server.
+    pub fn uri(&self, path: &str) -> String {
+        format!("{}{path}", self.uri)
+    }
+
+    /// Returns the requests received so far.
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+fn answer(
+    stream: TcpStream,
+    requests: &Mutex<Vec<Request>>,
+    status: u16,
+    body: &str,
+) -> io::Result<()> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut words = line.split_whitespace();
+    let method = words.next().unwrap_or_default().to_owned();
+    let path = words.next().unwrap_or_default().to_owned();
+    let mut headers = Vec::new();
+    loop {
+        line.clear();
+        reader.read_line(&mut line)?;
+        let Some((key, value)) = line.trim_end().split_once(':') else {
+            break;
+        };
+        headers.push((key.to_owned(), value.trim().to_owned()));
+    }
+    let length = headers
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.parse().ok())
+        .unwrap_or(0);
+    let mut content = vec![0; length];
+    reader.read_exact(&mut content)?;
+    requests.lock().unwrap().push(Request {
+        method,
+        path,
+        headers,
+        body: String::from_utf8_lossy(&content).into_owned(),
+    });
+
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        _ => "Internal Server Error",
+    };
+    write!(
+        reader.get_mut(),
+        "HTTP/1.1 {status} {reason}\r\n\
+         Content-Type: application/json\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
+
+/// A user typing `pin` when asked, and remembering what they were shown.
+#[derive(Debug, Default)]
+pub struct User {
+    pub pin: String,
+    pub opened: Vec<String>,
+    pub notified: Vec<String>,
+}
+
+impl Interaction for User {
+    fn open(&mut self, uri: &str) {
+        self.opened.push(uri.to_owned());
+    }
+
+    fn ask_secret(&mut self, _prompt: &str) -> io::Result<Zeroizing<String>> {
+        Ok(Zeroizing::new(self.pin.clone()))
+    }
+
+    fn notify(&mut self, message: &str) {
+        self.notified.push(message.to_owned());
+    }
+}
@@ -0,0 +1,69 @@
+//! Backend forwarding every operation to the `wgbd` daemon.
+
+use std::path::PathBuf;
+
+use super::{BackendError, Device, Tunnel, TunnelBackend};
+use crate::ipc::{Client, IpcError, Request, Response};
+
+/// Backend for unprivileged users: the daemon, running as root, performs
+/// the operations on their behalf.
+#[derive(Debug, Clone)]
+pub struct DaemonBackend {
+    socket: PathBuf,
+}
+
+impl DaemonBackend {
+    /// Creates a backend talking to the daemon listening on `socket`.
+    pub fn new(socket: impl Into<PathBuf>) -> Self {
+        Self {
+            socket: socket.into(),
+        }
+    }
+
+    fn call(&self, request: &Request) -> Result<Response, BackendError> {
+        Ok(Client::connect(&self.socket)?.call(request)?)
+    }
+
+    fn expect_done(&self, request: &Request) -> Result<(), BackendError> {
+        match self.call(request)? {
+            Response::Done => Ok(()),
+            other => Err(unexpected(&other)),
+        }
+    }
+}
+
+impl TunnelBackend for DaemonBackend {
+    fn up(&self, tunnel: &Tunnel) -> Result<(), BackendError> {
+        self.expect_done(&Request::Connect {
+            path: tunnel.path().to_path_buf(),
+            secrets: tunnel.secrets().clone(),
+            plaintext: tunnel.plaintext().cloned(),
+        })
+    }
+
+    fn down(&self, tunnel: &Tunnel) -> Result<(), BackendError> {
+        self.expect_done(&Request::Disconnect {
+            path: tunnel.path().to_path_buf(),
+            plaintext: tunnel.plaintext().cloned(),
+        })
+    }
+
+    fn show(&self) -> Result<Vec<Device>, BackendError> {
+        match self.call(&Request::Status)? {
+            Response::Devices { devices } => Ok(devices),
+            other => Err(unexpected(&other)),
+        }
+    }
+
+    fn sync(&self, tunnel: &Tunnel) -> Result<(), BackendError> {
+        self.expect_done(&Request::Sync {
+            path: tunnel.path().to_path_buf(),
+            secrets: tunnel.secrets().clone(),
+            plaintext: tunnel.plaintext().cloned(),
+        })
+    }
+}
+
+fn unexpected(response: &Response) -> BackendError {
+    IpcError::Protocol(format!("unexpected answer {response:?}")).into()
+}
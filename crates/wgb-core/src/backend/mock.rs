@@ -0,0 +1,140 @@
+//! In-memory backend, to run the tool without the WireGuard kernel module.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use super::{BackendError, Device, PeerState, Tunnel, TunnelBackend};
+use crate::profile::Profile;
+
+/// Environment variable naming the file where [`MockBackend::from_env`]
+/// keeps its tunnels, so they survive across invocations of the tool.
+pub const MOCK_STATE_ENV: &str = "WGB_MOCK_STATE";
+
+/// Backend keeping the tunnels in memory, optionally mirrored in a JSON
+/// file.
+#[derive(Debug, Default)]
+pub struct MockBackend {
+    devices: Mutex<BTreeMap<String, Device>>,
+    state_file: Option<PathBuf>,
+}
+
+impl MockBackend {
+    /// Creates a backend without tunnels.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a backend whose tunnels are stored in `path`.
+    pub fn with_state_file(path: impl Into<PathBuf>) -> Self {
+        Self {
+            devices: Mutex::default(),
+            state_file: Some(path.into()),
+        }
+    }
+
+    /// Creates a backend stored in the file named by `WGB_MOCK_STATE`, or
+    /// in memory only if the variable is not set.
+    pub fn from_env() -> Self {
+        match std::env::var_os(MOCK_STATE_ENV) {
+            Some(path) if !path.is_empty() => Self::with_state_file(path),
+            _ => Self::new(),
+        }
+    }
+
+    /// Runs `f` on the tunnels, loading them from and saving them to the
+    /// state file if any.
+    fn with_devices<T>(
+        &self,
+        f: impl FnOnce(&mut BTreeMap<String, Device>) -> Result<T, BackendError>,
+    ) -> Result<T, BackendError> {
+        let mut devices = self.devices.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(path) = &self.state_file else {
+            return f(&mut devices);
+        };
+        let state_err = |source: io::Error| BackendError::Io {
+            path: path.clone(),
+            source,
+        };
+        *devices = match fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text).map_err(|e| state_err(e.into()))?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(err) => return Err(state_err(err)),
+        };
+        let result = f(&mut devices)?;
+        let text = serde_json::to_string_pretty(&*devices).map_err(|e| state_err(e.into()))?;
+        fs::write(path, text).map_err(state_err)?;
+        Ok(result)
+    }
+}
+
+impl TunnelBackend for MockBackend {
+    fn up(&self, tunnel: &Tunnel) -> Result<(), BackendError> {
+        let profile = tunnel.profile()?;
+        self.with_devices(|devices| {
+            if devices.contains_key(tunnel.interface()) {
+                return Err(BackendError::AlreadyUp(tunnel.interface().to_owned()));
+            }
+            devices.insert(
+                tunnel.interface().to_owned(),
+                device(tunnel.interface(), &profile, &[]),
+            );
+            Ok(())
+        })
+    }
+
+    fn down(&self, tunnel: &Tunnel) -> Result<(), BackendError> {
+        self.with_devices(|devices| {
+            devices
+                .remove(tunnel.interface())
+                .map(|_| ())
+                .ok_or_else(|| BackendError::NotUp(tunnel.interface().to_owned()))
+        })
+    }
+
+    fn show(&self) -> Result<Vec<Device>, BackendError> {
+        self.with_devices(|devices| Ok(devices.values().cloned().collect()))
+    }
+
+    fn sync(&self, tunnel: &Tunnel) -> Result<(), BackendError> {
+        let profile = tunnel.profile()?;
+        self.with_devices(|devices| {
+            let current = devices
+                .get_mut(tunnel.interface())
+                .ok_or_else(|| BackendError::NotUp(tunnel.interface().to_owned()))?;
+            *current = device(tunnel.interface(), &profile, &current.peers);
+            Ok(())
+        })
+    }
+}
+
+/// Builds the state of an interface configured with `profile`, keeping the
+/// counters of the peers in `previous` that are still there.
+fn device(name: &str, profile: &Profile, previous: &[PeerState]) -> Device {
+    let peers = profile
+        .peers()
+        .filter_map(|peer| {
+            let public_key = peer.public_key()?.to_owned();
+            let old = previous.iter().find(|p| p.public_key == public_key);
+            Some(PeerState {
+                endpoint: peer.endpoint().and_then(|e| e.parse::<SocketAddr>().ok()),
+                allowed_ips: peer.allowed_ips(),
+                latest_handshake: old.and_then(|p| p.latest_handshake),
+                rx_bytes: old.map_or(0, |p| p.rx_bytes),
+                tx_bytes: old.map_or(0, |p| p.tx_bytes),
+                persistent_keepalive: peer.persistent_keepalive(),
+                public_key,
+            })
+        })
+        .collect();
+    Device {
+        name: name.to_owned(),
+        public_key: None,
+        listen_port: profile.interface().listen_port(),
+        fwmark: None,
+        peers,
+    }
+}
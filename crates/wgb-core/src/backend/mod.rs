@@ -0,0 +1,314 @@
+//! Backends that bring WireGuard tunnels up and down and report their state.
+//!
+//! Every backend implements [`TunnelBackend`]:
+//!
+//! - [`WgQuickBackend`] runs `wg-quick` and `wg`, like the original scripts;
+//! - [`NetlinkBackend`] configures the interface, its addresses and routes
+//!   directly through netlink;
+//! - [`MockBackend`] keeps the tunnels in memory, to exercise the tool on a
+//!   machine without the WireGuard kernel module;
+//! - [`DaemonBackend`] asks the `wgbd` daemon to run one of the above on
+//!   behalf of an unprivileged user.
+
+mod daemon;
+mod mock;
+mod netlink;
+mod wg_quick;
+
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::discovery;
+use crate::encrypted::{self, Plaintext};
+use crate::exec::ExecError;
+use crate::ipc::IpcError;
+use crate::profile::{Cidr, Profile, ProfileError};
+use crate::secret::{self, SecretError, Secrets};
+
+pub use daemon::DaemonBackend;
+pub use mock::MockBackend;
+pub use netlink::NetlinkBackend;
+pub use wg_quick::WgQuickBackend;
+
+/// Maximum length of a network interface name.
+const IFNAME_MAX_LEN: usize = 15;
+
+/// Errors raised by a tunnel backend.
+#[derive(Debug, Error)]
+pub enum BackendError {
+    /// An external command failed.
+    #[error(transparent)]
+    Exec(#[from] ExecError),
+
+    /// The daemon could not be reached or refused the request.
+    #[error(transparent)]
+    Ipc(#[from] IpcError),
+
+    /// The profile could not be read or is not valid.
+    #[error(transparent)]
+    Profile(#[from] ProfileError),
+
+    /// The keys of the profile could not be fetched or put back.
+    #[error(transparent)]
+    Secret(#[from] SecretError),
+
+    /// A netlink request was rejected.
+    #[error("{op} failed: {source}")]
+    Netlink {
+        op: String,
+        #[source]
+        source: io::Error,
+    },
+
+    /// Reading or writing a state file failed.
+    #[error("unable to access '{}': {source}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    /// The output of `wg` could not be understood.
+    #[error("unexpected output from wg: {0}")]
+    Output(String),
+
+    /// The profile file name cannot be used as an interface name.
+    #[error("'{}' is not a valid interface name, use at most {IFNAME_MAX_LEN} letters, digits or '_=+.-'", .0.display())]
+    InterfaceName(PathBuf),
+
+    /// The profile holds a value the backend cannot apply.
+    #[error("{0}")]
+    Unsupported(String),
+
+    /// The interface is already up.
+    #[error("interface '{0}' already exists")]
+    AlreadyUp(String),
+
+    /// The interface is not up.
+    #[error("interface '{0}' is not up")]
+    NotUp(String),
+}
+
+/// Identifies a backend implementation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BackendKind {
+    #[default]
+    WgQuick,
+    Netlink,
+    Mock,
+}
+
+impl BackendKind {
+    /// Every backend, in the order they are documented.
+    pub const ALL: [BackendKind; 3] = [Self::WgQuick, Self::Netlink, Self::Mock];
+
+    /// Returns the name used on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::WgQuick => "wg-quick",
+            Self::Netlink => "netlink",
+            Self::Mock => "mock",
+        }
+    }
+
+    /// Creates the backend with its default settings.
+    pub fn build(self) -> Box<dyn TunnelBackend> {
+        match self {
+            Self::WgQuick => Box::new(WgQuickBackend::new()),
+            Self::Netlink => Box::new(NetlinkBackend::new()),
+            Self::Mock => Box::new(MockBackend::from_env()),
+        }
+    }
+}
+
+impl fmt::Display for BackendKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for BackendKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.name() == s)
+            .ok_or_else(|| format!("unknown backend '{s}'"))
+    }
+}
+
+/// A WireGuard profile together with the interface it brings up, the
+/// secrets its keys reference, if sealed, and its text, if encrypted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tunnel {
+    interface: String,
+    path: PathBuf,
+    secrets: Secrets,
+    plaintext: Option<Plaintext>,
+}
+
+impl Tunnel {
+    /// Creates the tunnel described by the profile at `path`. Like
+    /// `wg-quick`, the interface is named after the file.
+    pub fn new(path: impl Into<PathBuf>) -> Result<Self, BackendError> {
+        let path = path.into();
+        let interface = interface_name(&path)?;
+        Ok(Self {
+            interface,
+            path,
+            secrets: Secrets::default(),
+            plaintext: None,
+        })
+    }
+
+    /// Sets the secrets referenced by the profile, fetched by the user.
+    pub fn with_secrets(mut self, secrets: Secrets) -> Self {
+        self.secrets = secrets;
+        self
+    }
+
+    /// Sets the text of the profile, decrypted by the user or read by the
+    /// daemon, used instead of the file.
+    pub fn with_plaintext(mut self, plaintext: Option<Plaintext>) -> Self {
+        self.plaintext = plaintext;
+        self
+    }
+
+    /// Decrypts, as the current user, the profile if it is encrypted.
+    pub fn decrypt(&mut self) -> Result<(), BackendError> {
+        if self.plaintext.is_none() && self.is_encrypted() {
+            self.plaintext = Some(encrypted::decrypt(&self.path).map_err(ProfileError::from)?);
+        }
+        Ok(())
+    }
+
+    /// Decrypts the profile if needed then fetches, as the current user, the
+    /// secrets it references.
+    pub fn load_secrets(&mut self) -> Result<(), BackendError> {
+        self.decrypt()?;
+        self.secrets = match &self.plaintext {
+            Some(text) => secret::fetch(&Profile::parse(text.as_str(), &self.path)?)?,
+            None => secret::resolve(&self.path)?,
+        };
+        Ok(())
+    }
+
+    /// Returns the name of the network interface.
+    pub fn interface(&self) -> &str {
+        &self.interface
+    }
+
+    /// Returns the path of the profile.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns whether the profile is encrypted.
+    pub fn is_encrypted(&self) -> bool {
+        encrypted::is_encrypted(&self.path)
+    }
+
+    /// Returns the secrets referenced by the profile, empty unless loaded.
+    pub fn secrets(&self) -> &Secrets {
+        &self.secrets
+    }
+
+    /// Returns the text of the profile, unless the file is to be read.
+    pub fn plaintext(&self) -> Option<&Plaintext> {
+        self.plaintext.as_ref()
+    }
+
+    /// Reads and parses the profile, its references replaced by the
+    /// secrets, in memory only.
+    pub fn profile(&self) -> Result<Profile, BackendError> {
+        let mut profile = self.profile_without_secrets()?;
+        secret::materialize(&mut profile, &self.secrets)?;
+        Ok(profile)
+    }
+
+    /// Reads and parses the profile, its references left as they are.
+    pub fn profile_without_secrets(&self) -> Result<Profile, BackendError> {
+        Ok(match &self.plaintext {
+            Some(text) => Profile::parse(text.as_str(), &self.path)?,
+            None => Profile::load(&self.path)?,
+        })
+    }
+}
+
+/// Returns the interface brought up by the profile at `path`.
+pub fn interface_name(path: &Path) -> Result<String, BackendError> {
+    let invalid = || BackendError::InterfaceName(path.to_path_buf());
+    let name = discovery::profile_name(path).ok_or_else(invalid)?;
+    let valid = !name.is_empty()
+        && name.len() <= IFNAME_MAX_LEN
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"_=+.-".contains(&b));
+    if valid {
+        Ok(name.to_owned())
+    } else {
+        Err(invalid())
+    }
+}
+
+/// Parses a firewall mark, in decimal or `0x` prefixed hexadecimal; `off`
+/// is the same as 0.
+pub(crate) fn parse_fwmark(value: &str) -> Option<u32> {
+    match value.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None if value.eq_ignore_ascii_case("off") => Some(0),
+        None => value.parse().ok(),
+    }
+}
+
+/// State of a WireGuard interface.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Device {
+    /// Interface name.
+    pub name: String,
+    /// Public key of the interface, base64 encoded.
+    pub public_key: Option<String>,
+    pub listen_port: Option<u16>,
+    pub fwmark: Option<u32>,
+    pub peers: Vec<PeerState>,
+}
+
+/// State of a peer of a WireGuard interface.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerState {
+    /// Public key of the peer, base64 encoded.
+    pub public_key: String,
+    pub endpoint: Option<SocketAddr>,
+    pub allowed_ips: Vec<Cidr>,
+    /// Time of the latest handshake, `None` if none happened yet.
+    pub latest_handshake: Option<SystemTime>,
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    /// Keepalive interval in seconds, `None` when disabled.
+    pub persistent_keepalive: Option<u16>,
+}
+
+/// Operations on WireGuard tunnels.
+pub trait TunnelBackend: Send + Sync {
+    /// Brings the tunnel up: creates the interface, configures it with the
+    /// profile, assigns its addresses and adds its routes.
+    fn up(&self, tunnel: &Tunnel) -> Result<(), BackendError>;
+
+    /// Tears the tunnel down, removing the interface.
+    fn down(&self, tunnel: &Tunnel) -> Result<(), BackendError>;
+
+    /// Returns the state of every WireGuard interface.
+    fn show(&self) -> Result<Vec<Device>, BackendError>;
+
+    /// Applies the current content of the profile to a tunnel already up,
+    /// without disrupting the sessions of unchanged peers.
+    fn sync(&self, tunnel: &Tunnel) -> Result<(), BackendError>;
+}
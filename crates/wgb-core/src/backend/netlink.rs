@@ -0,0 +1,1119 @@
+//! Native backend configuring WireGuard interfaces through netlink.
+//!
+//! It follows what `wg-quick` does: create the interface, configure it,
+//! assign the addresses, set the MTU, bring the link up, set DNS servers
+//! through `resolvconf` and add a route for every allowed IP. A default route
+//! is handled like `wg-quick` does, with a dedicated routing table selected
+//! by a firewall mark. When a step or `PostUp` fails, the tunnel is torn
+//! down as by `down`.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs};
+use std::process::Command;
+use std::time::{Duration, UNIX_EPOCH};
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+
+use super::{parse_fwmark, BackendError, Device, PeerState, Tunnel, TunnelBackend};
+use crate::exec;
+use crate::netlink::{self as nl, Attrs, Message, Socket};
+use crate::profile::{Cidr, Profile};
+
+const WG_GENL_NAME: &str = "wireguard";
+const WG_GENL_VERSION: u8 = 1;
+const WG_CMD_GET_DEVICE: u8 = 0;
+const WG_CMD_SET_DEVICE: u8 = 1;
+
+const WGDEVICE_A_IFNAME: u16 = 2;
+const WGDEVICE_A_PRIVATE_KEY: u16 = 3;
+const WGDEVICE_A_PUBLIC_KEY: u16 = 4;
+const WGDEVICE_A_FLAGS: u16 = 5;
+const WGDEVICE_A_LISTEN_PORT: u16 = 6;
+const WGDEVICE_A_FWMARK: u16 = 7;
+const WGDEVICE_A_PEERS: u16 = 8;
+const WGDEVICE_F_REPLACE_PEERS: u32 = 1;
+
+const WGPEER_A_PUBLIC_KEY: u16 = 1;
+const WGPEER_A_PRESHARED_KEY: u16 = 2;
+const WGPEER_A_FLAGS: u16 = 3;
+const WGPEER_A_ENDPOINT: u16 = 4;
+const WGPEER_A_PERSISTENT_KEEPALIVE_INTERVAL: u16 = 5;
+const WGPEER_A_LAST_HANDSHAKE_TIME: u16 = 6;
+const WGPEER_A_RX_BYTES: u16 = 7;
+const WGPEER_A_TX_BYTES: u16 = 8;
+const WGPEER_A_ALLOWEDIPS: u16 = 9;
+const WGPEER_F_REMOVE_ME: u32 = 1;
+const WGPEER_F_REPLACE_ALLOWEDIPS: u32 = 2;
+
+const WGALLOWEDIP_A_FAMILY: u16 = 1;
+const WGALLOWEDIP_A_IPADDR: u16 = 2;
+const WGALLOWEDIP_A_CIDR_MASK: u16 = 3;
+
+const FRA_FWMARK: u16 = 10;
+const FRA_SUPPRESS_PREFIXLEN: u16 = 14;
+const FRA_TABLE: u16 = 15;
+const FR_ACT_TO_TBL: u8 = 1;
+const FIB_RULE_INVERT: u32 = 2;
+
+/// Size of `struct ifinfomsg`.
+const IFINFOMSG_LEN: usize = 16;
+/// Size of `struct genlmsghdr`.
+const GENLMSGHDR_LEN: usize = 4;
+/// MTU used when the profile does not set one.
+const DEFAULT_MTU: u32 = 1420;
+/// Routing table and firewall mark used for a default route, as `wg-quick`.
+const DEFAULT_TABLE: u32 = 51820;
+
+/// Backend talking to the kernel through netlink. It requires root
+/// privileges or `CAP_NET_ADMIN`.
+#[derive(Debug, Clone, Default)]
+pub struct NetlinkBackend;
+
+impl NetlinkBackend {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl TunnelBackend for NetlinkBackend {
+    fn up(&self, tunnel: &Tunnel) -> Result<(), BackendError> {
+        let profile = tunnel.profile()?;
+        let name = tunnel.interface();
+        let mut kernel = Kernel::open()?;
+        if kernel.link_index(name)?.is_some() {
+            return Err(BackendError::AlreadyUp(name.to_owned()));
+        }
+        let routing = Routing::of(&profile)?;
+        bring_up(&mut kernel, name, &profile, &routing)
+    }
+
+    fn down(&self, tunnel: &Tunnel) -> Result<(), BackendError> {
+        let name = tunnel.interface();
+        let mut kernel = Kernel::open()?;
+        if kernel.link_index(name)?.is_none() {
+            return Err(BackendError::NotUp(name.to_owned()));
+        }
+        // The keys are not needed to tear the tunnel down.
+        let profile = tunnel.profile_without_secrets()?;
+        let routing = Routing::of(&profile)?;
+
+        kernel.run_hooks(name, &profile.interface().pre_down())?;
+        tear_down(&mut kernel, name, &profile, &routing)?;
+        kernel.run_hooks(name, &profile.interface().post_down())
+    }
+
+    fn show(&self) -> Result<Vec<Device>, BackendError> {
+        let mut route = Socket::open(libc::NETLINK_ROUTE).map_err(netlink_err("open socket"))?;
+        let links = route
+            .request(
+                Message::new(libc::RTM_GETLINK, libc::NLM_F_DUMP as u16)
+                    .header(&ifinfomsg(0, 0, 0)),
+            )
+            .map_err(netlink_err("list interfaces"))?;
+        let names: Vec<String> = links
+            .iter()
+            .filter(|link| is_wireguard(link))
+            .filter_map(|link| {
+                Attrs::new(link, IFINFOMSG_LEN)
+                    .find(|(ty, _)| *ty == libc::IFLA_IFNAME)
+                    .and_then(|(_, payload)| nl::read_str(payload))
+                    .map(str::to_owned)
+            })
+            .collect();
+        if names.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut genl = Genl::open()?;
+        names.iter().map(|name| genl.device(name)).collect()
+    }
+
+    fn sync(&self, tunnel: &Tunnel) -> Result<(), BackendError> {
+        let profile = tunnel.profile()?;
+        let name = tunnel.interface();
+        let mut genl = Genl::open()?;
+        let current = genl.device(name).map_err(|err| match err {
+            BackendError::Netlink { source, .. } if source.raw_os_error() == Some(libc::ENODEV) => {
+                BackendError::NotUp(name.to_owned())
+            }
+            err => err,
+        })?;
+        let wanted: BTreeSet<&str> = profile.peers().filter_map(|p| p.public_key()).collect();
+        let removed: Vec<&str> = current
+            .peers
+            .iter()
+            .map(|p| p.public_key.as_str())
+            .filter(|key| !wanted.contains(key))
+            .collect();
+        genl.set_device(name, &profile, current.fwmark, &removed, false)
+    }
+}
+
+/// Policy routing needed by a profile.
+#[derive(Debug)]
+struct Routing {
+    /// Table of the routes, `None` when routes are disabled.
+    table: Option<u32>,
+    /// Table used for a default route, when one is handled with a firewall
+    /// mark.
+    default_table: Option<u32>,
+    /// Address families with a default route.
+    default_families: Vec<u8>,
+}
+
+impl Routing {
+    fn of(profile: &Profile) -> Result<Self, BackendError> {
+        let interface = profile.interface();
+        let auto = match interface.table() {
+            None => true,
+            Some(t) if t.eq_ignore_ascii_case("auto") => true,
+            Some(t) if t.eq_ignore_ascii_case("off") => {
+                return Ok(Self {
+                    table: None,
+                    default_table: None,
+                    default_families: Vec::new(),
+                })
+            }
+            Some(_) => false,
+        };
+        let table = match interface.table() {
+            Some(t) if !auto => Some(if t.eq_ignore_ascii_case("main") {
+                libc::RT_TABLE_MAIN as u32
+            } else {
+                t.parse().map_err(|_| {
+                    BackendError::Unsupported(format!("routing table '{t}' is not a number"))
+                })?
+            }),
+            _ => Some(libc::RT_TABLE_MAIN as u32),
+        };
+
+        let mut default_families: Vec<u8> = profile
+            .peers()
+            .flat_map(|p| p.allowed_ips())
+            .filter(|ip| ip.prefix == 0)
+            .map(|ip| family(&ip.addr))
+            .collect();
+        default_families.sort_unstable();
+        default_families.dedup();
+        let default_table = (auto && !default_families.is_empty()).then(|| {
+            interface
+                .fwmark()
+                .and_then(parse_fwmark)
+                .filter(|m| *m != 0)
+                .unwrap_or(DEFAULT_TABLE)
+        });
+        Ok(Self {
+            table,
+            default_table,
+            default_families,
+        })
+    }
+}
+
+/// Changes made to the host to bring a tunnel up and down, through netlink
+/// and `resolvconf` by [`Kernel`].
+trait Host {
+    /// Runs `PreUp`, `PostUp`, `PreDown` or `PostDown` commands.
+    fn run_hooks(&mut self, name: &str, hooks: &[&str]) -> Result<(), BackendError>;
+    /// Returns the index of the interface `name`, if it exists.
+    fn link_index(&mut self, name: &str) -> Result<Option<u32>, BackendError>;
+    fn create_link(&mut self, name: &str) -> Result<(), BackendError>;
+    fn delete_link(&mut self, name: &str) -> Result<(), BackendError>;
+    /// Sends the keys and peers of `profile` to the interface `name`.
+    fn set_device(
+        &mut self,
+        name: &str,
+        profile: &Profile,
+        fwmark: Option<u32>,
+    ) -> Result<(), BackendError>;
+    fn add_address(&mut self, index: u32, addr: &Cidr) -> Result<(), BackendError>;
+    /// Brings the interface up with `mtu`.
+    fn set_link_up(&mut self, index: u32, mtu: u32) -> Result<(), BackendError>;
+    fn add_route(&mut self, index: u32, dst: &Cidr, table: u32) -> Result<(), BackendError>;
+    fn add_rule(
+        &mut self,
+        family: u8,
+        flags: u32,
+        attrs: &[(u16, u32)],
+    ) -> Result<(), BackendError>;
+    fn delete_rule(
+        &mut self,
+        family: u8,
+        flags: u32,
+        attrs: &[(u16, u32)],
+    ) -> Result<(), BackendError>;
+    /// Registers the `resolv.conf` lines `conf` for the interface `name`.
+    fn add_dns(&mut self, name: &str, conf: &str) -> Result<(), BackendError>;
+    fn delete_dns(&mut self, name: &str) -> Result<(), BackendError>;
+}
+
+/// Creates and configures the interface `name`, then runs `PostUp`. When a
+/// step fails once the interface exists, everything is torn down, as
+/// `wg-quick` does, so that no DNS entry nor routing rule is left behind.
+fn bring_up(
+    host: &mut dyn Host,
+    name: &str,
+    profile: &Profile,
+    routing: &Routing,
+) -> Result<(), BackendError> {
+    host.run_hooks(name, &profile.interface().pre_up())?;
+    host.create_link(name)?;
+    let result = configure(host, name, profile, routing)
+        .and_then(|()| host.run_hooks(name, &profile.interface().post_up()));
+    if result.is_err() {
+        let _ = tear_down(host, name, profile, routing);
+    }
+    result
+}
+
+/// Configures an interface just created: device settings, addresses, link
+/// state, DNS and routes.
+fn configure(
+    host: &mut dyn Host,
+    name: &str,
+    profile: &Profile,
+    routing: &Routing,
+) -> Result<(), BackendError> {
+    host.set_device(name, profile, routing.default_table)?;
+
+    let index = host
+        .link_index(name)?
+        .ok_or_else(|| BackendError::NotUp(name.to_owned()))?;
+    let interface = profile.interface();
+    for addr in interface.addresses() {
+        host.add_address(index, &addr)?;
+    }
+    host.set_link_up(index, interface.mtu().map_or(DEFAULT_MTU, u32::from))?;
+
+    let dns = interface.dns();
+    if !dns.is_empty() {
+        let mut conf = String::new();
+        for entry in &dns {
+            match entry.parse::<IpAddr>() {
+                Ok(_) => conf.push_str(&format!("nameserver {entry}\n")),
+                Err(_) => conf.push_str(&format!("search {entry}\n")),
+            }
+        }
+        host.add_dns(name, &conf)?;
+    }
+
+    let Some(table) = routing.table else {
+        return Ok(());
+    };
+    let mut routes: Vec<Cidr> = profile.peers().flat_map(|p| p.allowed_ips()).collect();
+    routes.sort_by_key(|r| std::cmp::Reverse(r.prefix));
+    routes.dedup();
+    for dst in routes {
+        let table = match routing.default_table {
+            Some(default) if dst.prefix == 0 => default,
+            _ => table,
+        };
+        host.add_route(index, &dst, table)?;
+    }
+
+    if let Some(table) = routing.default_table {
+        for family in &routing.default_families {
+            for (flags, attrs) in default_rules(table) {
+                host.add_rule(*family, flags, &attrs)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Removes what [`configure`] set up: the rules of a default route, the
+/// interface with its addresses and routes, and the DNS servers.
+fn tear_down(
+    host: &mut dyn Host,
+    name: &str,
+    profile: &Profile,
+    routing: &Routing,
+) -> Result<(), BackendError> {
+    if let Some(table) = routing.default_table {
+        for family in &routing.default_families {
+            for (flags, attrs) in default_rules(table) {
+                // The rules may have been removed by hand, or not added yet.
+                let _ = host.delete_rule(*family, flags, &attrs);
+            }
+        }
+    }
+    let deleted = host.delete_link(name);
+    if !profile.interface().dns().is_empty() {
+        let _ = host.delete_dns(name);
+    }
+    deleted
+}
+
+/// Rules sending the traffic without the firewall mark through `table`
+/// while keeping the more specific routes of the main table, as in
+/// `ip rule add not fwmark T table T` and
+/// `ip rule add table main suppress_prefixlength 0`.
+fn default_rules(table: u32) -> [(u32, Vec<(u16, u32)>); 2] {
+    [
+        (
+            FIB_RULE_INVERT,
+            vec![(FRA_FWMARK, table), (FRA_TABLE, table)],
+        ),
+        (
+            0,
+            vec![
+                (FRA_TABLE, libc::RT_TABLE_MAIN as u32),
+                (FRA_SUPPRESS_PREFIXLEN, 0),
+            ],
+        ),
+    ]
+}
+
+fn rule_message(ty: u16, flags: u16, family: u8, rule_flags: u32, attrs: &[(u16, u32)]) -> Message {
+    let mut header = [family, 0, 0, 0, 0, 0, 0, FR_ACT_TO_TBL, 0, 0, 0, 0];
+    header[8..12].copy_from_slice(&rule_flags.to_ne_bytes());
+    attrs.iter().fold(
+        Message::new(ty, flags).header(&header),
+        |msg, (ty, value)| msg.attr_u32(*ty, *value),
+    )
+}
+
+/// The host, changed through a `NETLINK_ROUTE` socket.
+struct Kernel {
+    route: Socket,
+}
+
+impl Kernel {
+    fn open() -> Result<Self, BackendError> {
+        let route = Socket::open(libc::NETLINK_ROUTE).map_err(netlink_err("open socket"))?;
+        Ok(Self { route })
+    }
+}
+
+impl Host for Kernel {
+    /// Runs the hooks with `bash`, replacing `%i` with the interface name as
+    /// `wg-quick` does.
+    fn run_hooks(&mut self, name: &str, hooks: &[&str]) -> Result<(), BackendError> {
+        for hook in hooks {
+            exec::run(Command::new("bash").arg("-c").arg(hook.replace("%i", name)))?;
+        }
+        Ok(())
+    }
+
+    fn link_index(&mut self, name: &str) -> Result<Option<u32>, BackendError> {
+        let result = self.route.request(
+            Message::new(libc::RTM_GETLINK, 0)
+                .header(&ifinfomsg(0, 0, 0))
+                .attr_str(libc::IFLA_IFNAME, name),
+        );
+        match result {
+            Ok(replies) => Ok(replies
+                .first()
+                .and_then(|link| link.get(4..8))
+                .map(|index| i32::from_ne_bytes(index.try_into().unwrap()) as u32)),
+            Err(err) if err.raw_os_error() == Some(libc::ENODEV) => Ok(None),
+            Err(err) => Err(BackendError::Netlink {
+                op: format!("look up interface {name}"),
+                source: err,
+            }),
+        }
+    }
+
+    fn create_link(&mut self, name: &str) -> Result<(), BackendError> {
+        self.route
+            .request(
+                Message::new(
+                    libc::RTM_NEWLINK,
+                    (libc::NLM_F_CREATE | libc::NLM_F_EXCL) as u16,
+                )
+                .header(&ifinfomsg(0, 0, 0))
+                .attr_str(libc::IFLA_IFNAME, name)
+                .begin_nested(libc::IFLA_LINKINFO)
+                .attr_str(libc::IFLA_INFO_KIND, WG_GENL_NAME)
+                .end_nested(),
+            )
+            .map(|_| ())
+            .map_err(netlink_err("create interface"))
+    }
+
+    fn delete_link(&mut self, name: &str) -> Result<(), BackendError> {
+        self.route
+            .request(
+                Message::new(libc::RTM_DELLINK, 0)
+                    .header(&ifinfomsg(0, 0, 0))
+                    .attr_str(libc::IFLA_IFNAME, name),
+            )
+            .map(|_| ())
+            .map_err(netlink_err("delete interface"))
+    }
+
+    fn set_device(
+        &mut self,
+        name: &str,
+        profile: &Profile,
+        fwmark: Option<u32>,
+    ) -> Result<(), BackendError> {
+        Genl::open()?.set_device(name, profile, fwmark, &[], true)
+    }
+
+    fn add_address(&mut self, index: u32, addr: &Cidr) -> Result<(), BackendError> {
+        let bytes = ip_bytes(&addr.addr);
+        self.route
+            .request(
+                Message::new(
+                    libc::RTM_NEWADDR,
+                    (libc::NLM_F_CREATE | libc::NLM_F_EXCL) as u16,
+                )
+                .header(&[family(&addr.addr), addr.prefix, 0, 0])
+                .header(&index.to_ne_bytes())
+                .attr(libc::IFA_LOCAL, &bytes)
+                .attr(libc::IFA_ADDRESS, &bytes),
+            )
+            .map(|_| ())
+            .map_err(netlink_err(&format!("add address {addr}")))
+    }
+
+    fn set_link_up(&mut self, index: u32, mtu: u32) -> Result<(), BackendError> {
+        self.route
+            .request(
+                Message::new(libc::RTM_NEWLINK, 0)
+                    .header(&ifinfomsg(index, libc::IFF_UP as u32, libc::IFF_UP as u32))
+                    .attr_u32(libc::IFLA_MTU, mtu),
+            )
+            .map(|_| ())
+            .map_err(netlink_err("bring interface up"))
+    }
+
+    fn add_route(&mut self, index: u32, dst: &Cidr, table: u32) -> Result<(), BackendError> {
+        let mut header = [0u8; 12];
+        header[0] = family(&dst.addr);
+        header[1] = dst.prefix;
+        header[4] = if table < 256 { table as u8 } else { 0 };
+        header[5] = libc::RTPROT_BOOT;
+        header[6] = libc::RT_SCOPE_LINK;
+        header[7] = libc::RTN_UNICAST;
+        let result = self.route.request(
+            Message::new(
+                libc::RTM_NEWROUTE,
+                (libc::NLM_F_CREATE | libc::NLM_F_EXCL) as u16,
+            )
+            .header(&header)
+            .attr(libc::RTA_DST, &ip_bytes(&dst.addr))
+            .attr_u32(libc::RTA_OIF, index)
+            .attr_u32(libc::RTA_TABLE, table),
+        );
+        match result {
+            Err(err) if err.raw_os_error() == Some(libc::EEXIST) => Ok(()),
+            result => result
+                .map(|_| ())
+                .map_err(netlink_err(&format!("add route {dst}"))),
+        }
+    }
+
+    fn add_rule(
+        &mut self,
+        family: u8,
+        flags: u32,
+        attrs: &[(u16, u32)],
+    ) -> Result<(), BackendError> {
+        self.route
+            .request(rule_message(
+                libc::RTM_NEWRULE,
+                (libc::NLM_F_CREATE | libc::NLM_F_EXCL) as u16,
+                family,
+                flags,
+                attrs,
+            ))
+            .map_err(netlink_err("add routing rule"))?;
+        if family == libc::AF_INET as u8 {
+            // Needed for the replies to be accepted on the marked path.
+            let _ = fs::write("/proc/sys/net/ipv4/conf/all/src_valid_mark", "1");
+        }
+        Ok(())
+    }
+
+    fn delete_rule(
+        &mut self,
+        family: u8,
+        flags: u32,
+        attrs: &[(u16, u32)],
+    ) -> Result<(), BackendError> {
+        self.route
+            .request(rule_message(libc::RTM_DELRULE, 0, family, flags, attrs))
+            .map(|_| ())
+            .map_err(netlink_err("delete routing rule"))
+    }
+
+    fn add_dns(&mut self, name: &str, conf: &str) -> Result<(), BackendError> {
+        exec::run_with_input(
+            Command::new("resolvconf").args(["-a", &format!("tun.{name}"), "-m", "0", "-x"]),
+            conf.as_bytes(),
+        )?;
+        Ok(())
+    }
+
+    fn delete_dns(&mut self, name: &str) -> Result<(), BackendError> {
+        exec::run(Command::new("resolvconf").args(["-d", &format!("tun.{name}"), "-f"]))?;
+        Ok(())
+    }
+}
+
+fn is_wireguard(link: &[u8]) -> bool {
+    Attrs::new(link, IFINFOMSG_LEN)
+        .filter(|(ty, _)| *ty == libc::IFLA_LINKINFO)
+        .flat_map(|(_, info)| Attrs::new(info, 0))
+        .any(|(ty, kind)| ty == libc::IFLA_INFO_KIND && nl::read_str(kind) == Some(WG_GENL_NAME))
+}
+
+/// Connection to the WireGuard generic netlink family.
+struct Genl {
+    socket: Socket,
+    family: u16,
+}
+
+impl Genl {
+    fn open() -> Result<Self, BackendError> {
+        let family = nl::resolve_family(WG_GENL_NAME).map_err(|source| BackendError::Netlink {
+            op: "find the WireGuard kernel module".to_owned(),
+            source,
+        })?;
+        let socket = Socket::open(libc::NETLINK_GENERIC).map_err(netlink_err("open socket"))?;
+        Ok(Self { socket, family })
+    }
+
+    /// Sends the settings of `profile` to the interface `name`.
+    ///
+    /// With `replace`, every existing peer is dropped first; otherwise the
+    /// peers in `removed` are removed and the others are updated in place.
+    fn set_device(
+        &mut self,
+        name: &str,
+        profile: &Profile,
+        fwmark: Option<u32>,
+        removed: &[&str],
+        replace: bool,
+    ) -> Result<(), BackendError> {
+        let interface = profile.interface();
+        let private_key = interface
+            .private_key()
+            .ok_or_else(|| BackendError::Unsupported("the profile has no PrivateKey".to_owned()))?;
+
+        let mut msg = Message::new(self.family, 0)
+            .header(&[WG_CMD_SET_DEVICE, WG_GENL_VERSION, 0, 0])
+            .attr_str(WGDEVICE_A_IFNAME, name)
+            .attr(WGDEVICE_A_PRIVATE_KEY, &decode_key(private_key)?)
+            .attr_u16(WGDEVICE_A_LISTEN_PORT, interface.listen_port().unwrap_or(0))
+            .attr_u32(
+                WGDEVICE_A_FWMARK,
+                fwmark
+                    .or_else(|| interface.fwmark().and_then(parse_fwmark))
+                    .unwrap_or(0),
+            );
+        if replace {
+            msg = msg.attr_u32(WGDEVICE_A_FLAGS, WGDEVICE_F_REPLACE_PEERS);
+        }
+
+        msg = msg.begin_nested(WGDEVICE_A_PEERS);
+        let mut index = 0;
+        for key in removed {
+            msg = msg
+                .begin_nested(index)
+                .attr(WGPEER_A_PUBLIC_KEY, &decode_key(key)?)
+                .attr_u32(WGPEER_A_FLAGS, WGPEER_F_REMOVE_ME)
+                .end_nested();
+            index += 1;
+        }
+        for peer in profile.peers() {
+            let public_key = peer
+                .public_key()
+                .ok_or_else(|| BackendError::Unsupported("a peer has no PublicKey".to_owned()))?;
+            msg = msg
+                .begin_nested(index)
+                .attr(WGPEER_A_PUBLIC_KEY, &decode_key(public_key)?)
+                .attr_u32(WGPEER_A_FLAGS, WGPEER_F_REPLACE_ALLOWEDIPS)
+                .attr_u16(
+                    WGPEER_A_PERSISTENT_KEEPALIVE_INTERVAL,
+                    peer.persistent_keepalive().unwrap_or(0),
+                );
+            if let Some(psk) = peer.preshared_key() {
+                msg = msg.attr(WGPEER_A_PRESHARED_KEY, &decode_key(psk)?);
+            }
+            if let Some(endpoint) = peer.endpoint() {
+                msg = msg.attr(WGPEER_A_ENDPOINT, &sockaddr(resolve(endpoint)?));
+            }
+            msg = msg.begin_nested(WGPEER_A_ALLOWEDIPS);
+            for (i, ip) in peer.allowed_ips().iter().enumerate() {
+                msg = msg
+                    .begin_nested(i as u16)
+                    .attr_u16(WGALLOWEDIP_A_FAMILY, u16::from(family(&ip.addr)))
+                    .attr(WGALLOWEDIP_A_IPADDR, &ip_bytes(&ip.addr))
+                    .attr_u8(WGALLOWEDIP_A_CIDR_MASK, ip.prefix)
+                    .end_nested();
+            }
+            msg = msg.end_nested().end_nested();
+            index += 1;
+        }
+        msg = msg.end_nested();
+
+        self.socket
+            .request(msg)
+            .map(|_| ())
+            .map_err(netlink_err("configure interface"))
+    }
+
+    /// Reads the state of the interface `name`.
+    fn device(&mut self, name: &str) -> Result<Device, BackendError> {
+        let replies = self
+            .socket
+            .request(
+                Message::new(self.family, libc::NLM_F_DUMP as u16)
+                    .header(&[WG_CMD_GET_DEVICE, WG_GENL_VERSION, 0, 0])
+                    .attr_str(WGDEVICE_A_IFNAME, name),
+            )
+            .map_err(netlink_err(&format!("read interface {name}")))?;
+
+        let mut device = Device {
+            name: name.to_owned(),
+            public_key: None,
+            listen_port: None,
+            fwmark: None,
+            peers: Vec::new(),
+        };
+        // Large devices are split over several messages, a peer may span
+        // two of them.
+        for reply in &replies {
+            for (ty, payload) in Attrs::new(reply, GENLMSGHDR_LEN) {
+                match ty {
+                    WGDEVICE_A_PUBLIC_KEY => device.public_key = Some(BASE64.encode(payload)),
+                    WGDEVICE_A_LISTEN_PORT => {
+                        device.listen_port = nl::read_u16(payload).filter(|p| *p != 0)
+                    }
+                    WGDEVICE_A_FWMARK => device.fwmark = nl::read_u32(payload).filter(|m| *m != 0),
+                    WGDEVICE_A_PEERS => {
+                        for (_, peer) in Attrs::new(payload, 0) {
+                            merge_peer(&mut device.peers, parse_peer(peer));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(device)
+    }
+}
+
+fn parse_peer(payload: &[u8]) -> PeerState {
+    let mut peer = PeerState {
+        public_key: String::new(),
+        endpoint: None,
+        allowed_ips: Vec::new(),
+        latest_handshake: None,
+        rx_bytes: 0,
+        tx_bytes: 0,
+        persistent_keepalive: None,
+    };
+    for (ty, value) in Attrs::new(payload, 0) {
+        match ty {
+            WGPEER_A_PUBLIC_KEY => peer.public_key = BASE64.encode(value),
+            WGPEER_A_ENDPOINT => peer.endpoint = parse_sockaddr(value),
+            WGPEER_A_PERSISTENT_KEEPALIVE_INTERVAL => {
+                peer.persistent_keepalive = nl::read_u16(value).filter(|k| *k != 0)
+            }
+            WGPEER_A_LAST_HANDSHAKE_TIME => {
+                let secs = nl::read_u64(value).unwrap_or(0);
+                let nanos = value.get(8..).and_then(nl::read_u64).unwrap_or(0);
+                if secs != 0 || nanos != 0 {
+                    peer.latest_handshake = Some(UNIX_EPOCH + Duration::new(secs, nanos as u32));
+                }
+            }
+            WGPEER_A_RX_BYTES => peer.rx_bytes = nl::read_u64(value).unwrap_or(0),
+            WGPEER_A_TX_BYTES => peer.tx_bytes = nl::read_u64(value).unwrap_or(0),
+            WGPEER_A_ALLOWEDIPS => {
+                peer.allowed_ips
+                    .extend(Attrs::new(value, 0).filter_map(|(_, ip)| parse_allowed_ip(ip)));
+            }
+            _ => {}
+        }
+    }
+    peer
+}
+
+/// Adds `peer` to `peers`, or completes the allowed IPs of the same peer
+/// started in a previous message.
+fn merge_peer(peers: &mut Vec<PeerState>, peer: PeerState) {
+    match peers.last_mut() {
+        Some(last) if last.public_key == peer.public_key => {
+            last.allowed_ips.extend(peer.allowed_ips)
+        }
+        _ => peers.push(peer),
+    }
+}
+
+fn parse_allowed_ip(payload: &[u8]) -> Option<Cidr> {
+    let mut addr = None;
+    let mut prefix = None;
+    for (ty, value) in Attrs::new(payload, 0) {
+        match ty {
+            WGALLOWEDIP_A_IPADDR => {
+                addr = match value.len() {
+                    4 => Some(IpAddr::from(<[u8; 4]>::try_from(value).ok()?)),
+                    16 => Some(IpAddr::from(<[u8; 16]>::try_from(value).ok()?)),
+                    _ => None,
+                }
+            }
+            WGALLOWEDIP_A_CIDR_MASK => prefix = value.first().copied(),
+            _ => {}
+        }
+    }
+    Some(Cidr {
+        addr: addr?,
+        prefix: prefix?,
+    })
+}
+
+fn ifinfomsg(index: u32, flags: u32, change: u32) -> [u8; IFINFOMSG_LEN] {
+    let mut msg = [0u8; IFINFOMSG_LEN];
+    msg[4..8].copy_from_slice(&index.to_ne_bytes());
+    msg[8..12].copy_from_slice(&flags.to_ne_bytes());
+    msg[12..16].copy_from_slice(&change.to_ne_bytes());
+    msg
+}
+
+fn family(addr: &IpAddr) -> u8 {
+    match addr {
+        IpAddr::V4(_) => libc::AF_INET as u8,
+        IpAddr::V6(_) => libc::AF_INET6 as u8,
+    }
+}
+
+fn ip_bytes(addr: &IpAddr) -> Vec<u8> {
+    match addr {
+        IpAddr::V4(v4) => v4.octets().to_vec(),
+        IpAddr::V6(v6) => v6.octets().to_vec(),
+    }
+}
+
+/// Encodes `addr` as a `struct sockaddr_in` or `struct sockaddr_in6`.
+fn sockaddr(addr: SocketAddr) -> Vec<u8> {
+    match addr {
+        SocketAddr::V4(v4) => {
+            let mut buf = vec![0u8; 16];
+            buf[0..2].copy_from_slice(&(libc::AF_INET as u16).to_ne_bytes());
+            buf[2..4].copy_from_slice(&v4.port().to_be_bytes());
+            buf[4..8].copy_from_slice(&v4.ip().octets());
+            buf
+        }
+        SocketAddr::V6(v6) => {
+            let mut buf = vec![0u8; 28];
+            buf[0..2].copy_from_slice(&(libc::AF_INET6 as u16).to_ne_bytes());
+            buf[2..4].copy_from_slice(&v6.port().to_be_bytes());
+            buf[4..8].copy_from_slice(&v6.flowinfo().to_be_bytes());
+            buf[8..24].copy_from_slice(&v6.ip().octets());
+            buf[24..28].copy_from_slice(&v6.scope_id().to_ne_bytes());
+            buf
+        }
+    }
+}
+
+fn parse_sockaddr(buf: &[u8]) -> Option<SocketAddr> {
+    let family = nl::read_u16(buf)?;
+    let port = u16::from_be_bytes(buf.get(2..4)?.try_into().ok()?);
+    if family == libc::AF_INET as u16 {
+        let ip: [u8; 4] = buf.get(4..8)?.try_into().ok()?;
+        Some(SocketAddr::new(Ipv4Addr::from(ip).into(), port))
+    } else if family == libc::AF_INET6 as u16 {
+        let ip: [u8; 16] = buf.get(8..24)?.try_into().ok()?;
+        Some(SocketAddr::new(Ipv6Addr::from(ip).into(), port))
+    } else {
+        None
+    }
+}
+
+fn resolve(endpoint: &str) -> Result<SocketAddr, BackendError> {
+    endpoint
+        .to_socket_addrs()
+        .ok()
+        .and_then(|mut addrs| addrs.next())
+        .ok_or_else(|| {
+            BackendError::Unsupported(format!("unable to resolve endpoint '{endpoint}'"))
+        })
+}
+
+fn decode_key(key: &str) -> Result<Vec<u8>, BackendError> {
+    BASE64
+        .decode(key.trim())
+        .ok()
+        .filter(|k| k.len() == 32)
+        .ok_or_else(|| {
+            BackendError::Unsupported("a key is not a valid base64 WireGuard key".to_owned())
+        })
+}
+
+fn netlink_err(op: &str) -> impl FnOnce(io::Error) -> BackendError + '_ {
+    move |source| BackendError::Netlink {
+        op: op.to_owned(),
+        source,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    const OFFICE: &str = include_str!("../../../../tests/fixtures/office.conf");
+
+    /// The office profile with DNS servers, hooks and a route besides the
+    /// default one.
+    fn profile_text() -> String {
+        OFFICE
+            .replace(
+                "Address = 10.0.0.2/32\n",
+                "Address = 10.0.0.2/32\n\
+                DNS = 10.0.0.1, corp.example.com\n\
+                PreUp = echo pre %i\n\
+                PostUp = echo post %i\n",
+            )
+            .replace(
+                "AllowedIPs = 0.0.0.0/0\n",
+                "AllowedIPs = 0.0.0.0/0, 10.0.0.0/24\n",
+            )
+    }
+
+    /// A host recording the changes, failing the first one starting with
+    /// `fail`.
+    #[derive(Default)]
+    struct Recorder {
+        calls: Vec<String>,
+        fail: Option<String>,
+    }
+
+    impl Recorder {
+        fn failing(step: impl Into<String>) -> Self {
+            Self {
+                calls: Vec::new(),
+                fail: Some(step.into()),
+            }
+        }
+
+        fn record(&mut self, call: String) -> Result<(), BackendError> {
+            let failed = self
+                .fail
+                .as_ref()
+                .is_some_and(|step| call.starts_with(step));
+            self.calls.push(call);
+            if failed {
+                self.fail = None;
+                return Err(BackendError::Unsupported("injected failure".to_owned()));
+            }
+            Ok(())
+        }
+    }
+
+    impl Host for Recorder {
+        fn run_hooks(&mut self, name: &str, hooks: &[&str]) -> Result<(), BackendError> {
+            for hook in hooks {
+                self.record(format!("hook {}", hook.replace("%i", name)))?;
+            }
+            Ok(())
+        }
+
+        fn link_index(&mut self, _name: &str) -> Result<Option<u32>, BackendError> {
+            Ok(Some(7))
+        }
+
+        fn create_link(&mut self, name: &str) -> Result<(), BackendError> {
+            self.record(format!("create link {name}"))
+        }
+
+        fn delete_link(&mut self, name: &str) -> Result<(), BackendError> {
+            self.record(format!("delete link {name}"))
+        }
+
+        fn set_device(
+            &mut self,
+            name: &str,
+            _profile: &Profile,
+            fwmark: Option<u32>,
+        ) -> Result<(), BackendError> {
+            self.record(format!("set device {name} fwmark {fwmark:?}"))
+        }
+
+        fn add_address(&mut self, index: u32, addr: &Cidr) -> Result<(), BackendError> {
+            self.record(format!("add address {addr} to {index}"))
+        }
+
+        fn set_link_up(&mut self, index: u32, mtu: u32) -> Result<(), BackendError> {
+            self.record(format!("set link {index} up mtu {mtu}"))
+        }
+
+        fn add_route(&mut self, index: u32, dst: &Cidr, table: u32) -> Result<(), BackendError> {
+            self.record(format!("add route {dst} via {index} table {table}"))
+        }
+
+        fn add_rule(
+            &mut self,
+            family: u8,
+            flags: u32,
+            attrs: &[(u16, u32)],
+        ) -> Result<(), BackendError> {
+            self.record(format!("add rule {family} {flags} {attrs:?}"))
+        }
+
+        fn delete_rule(
+            &mut self,
+            family: u8,
+            flags: u32,
+            attrs: &[(u16, u32)],
+        ) -> Result<(), BackendError> {
+            self.record(format!("delete rule {family} {flags} {attrs:?}"))
+        }
+
+        fn add_dns(&mut self, name: &str, conf: &str) -> Result<(), BackendError> {
+            self.record(format!("add dns tun.{name} {conf:?}"))
+        }
+
+        fn delete_dns(&mut self, name: &str) -> Result<(), BackendError> {
+            self.record(format!("delete dns tun.{name}"))
+        }
+    }
+
+    /// Brings the profile up on `host`, returning the calls made.
+    fn bring_up_on(mut host: Recorder) -> (Result<(), BackendError>, Vec<String>) {
+        let profile = Profile::parse(&profile_text(), Path::new("office.conf")).unwrap();
+        let routing = Routing::of(&profile).unwrap();
+        let result = bring_up(&mut host, "office", &profile, &routing);
+        (result, host.calls)
+    }
+
+    /// Returns the calls adding or deleting the default rules, `verb` being
+    /// `add` or `delete`.
+    fn rules(verb: &str) -> Vec<String> {
+        let inet = libc::AF_INET;
+        vec![
+            format!("{verb} rule {inet} 2 [(10, 51820), (15, 51820)]"),
+            format!("{verb} rule {inet} 0 [(15, 254), (14, 0)]"),
+        ]
+    }
+
+    fn teardown() -> Vec<String> {
+        let mut calls = rules("delete");
+        calls.push("delete link office".to_owned());
+        calls.push("delete dns tun.office".to_owned());
+        calls
+    }
+
+    #[test]
+    fn brings_up_a_tunnel_with_a_default_route() {
+        let (result, calls) = bring_up_on(Recorder::default());
+        result.unwrap();
+        let mut expected: Vec<String> = [
+            "hook echo pre office",
+            "create link office",
+            "set device office fwmark Some(51820)",
+            "add address 10.0.0.2/32 to 7",
+            "set link 7 up mtu 1420",
+            "add dns tun.office \"nameserver 10.0.0.1\\nsearch corp.example.com\\n\"",
+            "add route 10.0.0.0/24 via 7 table 254",
+            "add route 0.0.0.0/0 via 7 table 51820",
+        ]
+        .map(str::to_owned)
+        .into();
+        expected.extend(rules("add"));
+        expected.push("hook echo post office".to_owned());
+        assert_eq!(calls, expected);
+    }
+
+    #[test]
+    fn tears_down_everything_when_a_route_fails() {
+        let (result, calls) = bring_up_on(Recorder::failing("add route 0.0.0.0/0"));
+        assert!(result.is_err());
+        let failed = calls
+            .iter()
+            .position(|c| c.starts_with("add route 0"))
+            .unwrap();
+        assert_eq!(calls[failed + 1..], teardown());
+    }
+
+    #[test]
+    fn tears_down_the_first_rule_when_the_second_fails() {
+        let (result, calls) = bring_up_on(Recorder::failing(&rules("add")[1]));
+        assert!(result.is_err());
+        let failed = calls.iter().position(|c| *c == rules("add")[1]).unwrap();
+        assert_eq!(calls[failed - 1], rules("add")[0]);
+        assert_eq!(calls[failed + 1..], teardown());
+    }
+
+    #[test]
+    fn tears_down_the_tunnel_when_post_up_fails() {
+        let (result, calls) = bring_up_on(Recorder::failing("hook echo post"));
+        assert!(result.is_err());
+        let failed = calls
+            .iter()
+            .position(|c| c == "hook echo post office")
+            .unwrap();
+        assert_eq!(calls[failed + 1..], teardown());
+    }
+
+    #[test]
+    fn leaves_the_host_alone_when_pre_up_or_the_link_fails() {
+        let (result, calls) = bring_up_on(Recorder::failing("hook echo pre"));
+        assert!(result.is_err());
+        assert_eq!(calls, ["hook echo pre office"]);
+
+        let (result, calls) = bring_up_on(Recorder::failing("create link"));
+        assert!(result.is_err());
+        assert_eq!(calls, ["hook echo pre office", "create link office"]);
+    }
+
+    #[test]
+    fn builds_the_rules_of_a_default_route() {
+        let [invert, suppress] = default_rules(51820);
+        assert_eq!(
+            invert,
+            (
+                FIB_RULE_INVERT,
+                vec![(FRA_FWMARK, 51820), (FRA_TABLE, 51820)]
+            )
+        );
+        assert_eq!(
+            suppress,
+            (
+                0,
+                vec![
+                    (FRA_TABLE, libc::RT_TABLE_MAIN as u32),
+                    (FRA_SUPPRESS_PREFIXLEN, 0)
+                ]
+            )
+        );
+
+        let msg = rule_message(
+            libc::RTM_NEWRULE,
+            0,
+            libc::AF_INET6 as u8,
+            invert.0,
+            &invert.1,
+        );
+        let header = &msg.payload()[..12];
+        assert_eq!(header[0], libc::AF_INET6 as u8);
+        assert_eq!(header[7], FR_ACT_TO_TBL);
+        assert_eq!(header[8..12], FIB_RULE_INVERT.to_ne_bytes());
+        let attrs: Vec<(u16, Option<u32>)> = Attrs::new(msg.payload(), 12)
+            .map(|(ty, value)| (ty, nl::read_u32(value)))
+            .collect();
+        assert_eq!(attrs, [(FRA_FWMARK, Some(51820)), (FRA_TABLE, Some(51820))]);
+    }
+
+    #[test]
+    fn routes_a_default_route_through_a_marked_table() {
+        let profile = Profile::parse(&profile_text(), Path::new("office.conf")).unwrap();
+        let routing = Routing::of(&profile).unwrap();
+        assert_eq!(routing.table, Some(libc::RT_TABLE_MAIN as u32));
+        assert_eq!(routing.default_table, Some(51820));
+        assert_eq!(routing.default_families, [libc::AF_INET as u8]);
+
+        let text = profile_text().replace("PostUp", "Table = off\nPostUp");
+        let profile = Profile::parse(&text, Path::new("office.conf")).unwrap();
+        let routing = Routing::of(&profile).unwrap();
+        assert_eq!((routing.table, routing.default_table), (None, None));
+    }
+}
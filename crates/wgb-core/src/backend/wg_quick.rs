@@ -0,0 +1,297 @@
+//! Backend running `wg-quick` and `wg`, with `sudo` when needed.
+
+use std::env;
+use std::ffi::{CString, OsString};
+use std::fs;
+use std::io;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::PathBuf;
+use std::time::{Duration, UNIX_EPOCH};
+
+use zeroize::Zeroizing;
+
+use super::{parse_fwmark, BackendError, Device, PeerState, Tunnel, TunnelBackend};
+use crate::exec;
+use crate::profile::{Profile, SectionKind};
+use crate::secret;
+
+/// Backend delegating to the `wg-quick` and `wg` tools.
+#[derive(Debug, Clone, Default)]
+pub struct WgQuickBackend;
+
+impl WgQuickBackend {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl TunnelBackend for WgQuickBackend {
+    fn up(&self, tunnel: &Tunnel) -> Result<(), BackendError> {
+        if tunnel.secrets().is_empty() && tunnel.plaintext().is_none() {
+            exec::run(exec::privileged("wg-quick").arg("up").arg(tunnel.path()))?;
+            return Ok(());
+        }
+        // wg-quick only reads plain files: it brings the interface up from a
+        // copy without the keys, which are then set through a pipe.
+        let scratch = Scratch::create()?;
+        let copy = scratch.copy(tunnel)?;
+        exec::run(exec::privileged("wg-quick").arg("up").arg(&copy))?;
+        drop(scratch);
+        if let Err(err) = self.sync(tunnel) {
+            let _ = self.down(tunnel);
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    fn down(&self, tunnel: &Tunnel) -> Result<(), BackendError> {
+        if tunnel.plaintext().is_none() {
+            exec::run(exec::privileged("wg-quick").arg("down").arg(tunnel.path()))?;
+            return Ok(());
+        }
+        let scratch = Scratch::create()?;
+        let copy = scratch.copy(tunnel)?;
+        exec::run(exec::privileged("wg-quick").arg("down").arg(&copy))?;
+        Ok(())
+    }
+
+    fn show(&self) -> Result<Vec<Device>, BackendError> {
+        let output = exec::run(exec::privileged("wg").args(["show", "all", "dump"]))?;
+        parse_dump(&String::from_utf8_lossy(&output.stdout))
+    }
+
+    fn sync(&self, tunnel: &Tunnel) -> Result<(), BackendError> {
+        let config = if tunnel.plaintext().is_some() {
+            // The file is encrypted or must not be read again: strip the
+            // text here.
+            let mut config = tunnel.profile()?;
+            strip_wg_quick(&mut config);
+            Zeroizing::new(config.to_string().into_bytes())
+        } else {
+            let stripped = exec::run(exec::privileged("wg-quick").arg("strip").arg(tunnel.path()))?;
+            if tunnel.secrets().is_empty() {
+                Zeroizing::new(stripped.stdout)
+            } else {
+                let mut config =
+                    Profile::parse(&String::from_utf8_lossy(&stripped.stdout), tunnel.path())?;
+                secret::materialize(&mut config, tunnel.secrets())?;
+                Zeroizing::new(config.to_string().into_bytes())
+            }
+        };
+        exec::run_with_input(
+            exec::privileged("wg")
+                .arg("syncconf")
+                .arg(tunnel.interface())
+                .arg("/dev/stdin"),
+            &config,
+        )?;
+        Ok(())
+    }
+}
+
+/// Keys of the `[Interface]` section only `wg-quick` understands.
+const WG_QUICK_KEYS: [&str; 9] = [
+    "Address",
+    "DNS",
+    "MTU",
+    "Table",
+    "PreUp",
+    "PostUp",
+    "PreDown",
+    "PostDown",
+    "SaveConfig",
+];
+
+/// Removes from `profile` the keys `wg` does not understand, like
+/// `wg-quick strip`.
+fn strip_wg_quick(profile: &mut Profile) {
+    for section in profile.sections_mut() {
+        if *section.kind() == SectionKind::Interface {
+            for key in WG_QUICK_KEYS {
+                section.remove(key);
+            }
+        }
+    }
+}
+
+/// Private temporary directory, removed with its content when dropped.
+struct Scratch(PathBuf);
+
+impl Scratch {
+    /// Creates a directory with a random name, readable by its owner only,
+    /// with `mkdtemp(3)`.
+    fn create() -> Result<Self, BackendError> {
+        let template = env::temp_dir().join("wgb-XXXXXX");
+        let failed = |source| BackendError::Io {
+            path: template.clone(),
+            source,
+        };
+        let mut name = CString::new(template.as_os_str().as_bytes())
+            .map_err(|e| failed(io::Error::new(io::ErrorKind::InvalidInput, e)))?
+            .into_bytes_with_nul();
+        // SAFETY: `name` is a nul terminated template that mkdtemp fills in
+        // place.
+        if unsafe { libc::mkdtemp(name.as_mut_ptr().cast()) }.is_null() {
+            return Err(failed(io::Error::last_os_error()));
+        }
+        name.pop();
+        Ok(Self(OsString::from_vec(name).into()))
+    }
+
+    /// Writes a copy of the profile of `tunnel` without its keys, named
+    /// after its interface, and returns its path. `SaveConfig` is dropped:
+    /// the copy does not outlive the command.
+    fn copy(&self, tunnel: &Tunnel) -> Result<PathBuf, BackendError> {
+        let mut profile = tunnel.profile_without_secrets()?;
+        secret::strip(&mut profile);
+        for section in profile.sections_mut() {
+            if *section.kind() == SectionKind::Interface {
+                section.remove("SaveConfig");
+            }
+        }
+        let path = self.0.join(format!("{}.conf", tunnel.interface()));
+        profile.save(&path)?;
+        Ok(path)
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Parses the output of `wg show all dump`.
+///
+/// Each interface is described by a line of 5 tab separated fields, followed
+/// by a line of 9 fields for each of its peers.
+fn parse_dump(text: &str) -> Result<Vec<Device>, BackendError> {
+    let mut devices: Vec<Device> = Vec::new();
+    for line in text.lines().filter(|l| !l.is_empty()) {
+        let fields: Vec<&str> = line.split('\t').collect();
+        match fields[..] {
+            [name, _private_key, public_key, listen_port, fwmark] => devices.push(Device {
+                name: name.to_owned(),
+                public_key: none_if(public_key, "(none)").map(str::to_owned),
+                listen_port: listen_port.parse().ok().filter(|p| *p != 0),
+                fwmark: none_if(fwmark, "off").and_then(parse_fwmark),
+                peers: Vec::new(),
+            }),
+            [name, public_key, _preshared_key, endpoint, allowed_ips, handshake, rx, tx, keepalive] =>
+            {
+                let device = devices.iter_mut().find(|d| d.name == name).ok_or_else(|| {
+                    BackendError::Output(format!("peer of unknown interface '{name}'"))
+                })?;
+                let number = |value: &str| {
+                    value
+                        .parse::<u64>()
+                        .map_err(|_| BackendError::Output(format!("'{value}' is not a number")))
+                };
+                let handshake = number(handshake)?;
+                device.peers.push(PeerState {
+                    public_key: public_key.to_owned(),
+                    endpoint: none_if(endpoint, "(none)").and_then(|e| e.parse().ok()),
+                    allowed_ips: none_if(allowed_ips, "(none)")
+                        .map(|ips| ips.split(',').filter_map(|ip| ip.parse().ok()).collect())
+                        .unwrap_or_default(),
+                    latest_handshake: (handshake != 0)
+                        .then(|| UNIX_EPOCH + Duration::from_secs(handshake)),
+                    rx_bytes: number(rx)?,
+                    tx_bytes: number(tx)?,
+                    persistent_keepalive: keepalive.parse().ok().filter(|k| *k != 0),
+                });
+            }
+            _ => return Err(BackendError::Output(format!("unexpected line '{line}'"))),
+        }
+    }
+    Ok(devices)
+}
+
+fn none_if<'a>(value: &'a str, none: &str) -> Option<&'a str> {
+    (value != none && !value.is_empty()).then_some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::*;
+
+    #[test]
+    fn scratch_is_private_unique_and_removed() {
+        let first = Scratch::create().unwrap();
+        let second = Scratch::create().unwrap();
+        assert_ne!(first.0, second.0);
+        let meta = fs::metadata(&first.0).unwrap();
+        assert!(meta.is_dir());
+        assert_eq!(meta.permissions().mode() & 0o777, 0o700);
+        let path = first.0.clone();
+        drop(first);
+        assert!(!path.exists());
+    }
+
+    /// `wg show all dump` with two interfaces: `office` with a peer in
+    /// session and one never reached, `home` with a roaming peer.
+    const DUMP: &str = "office\tcHJpdmF0ZQ==\tb2ZmaWNl\t51820\toff\n\
+        office\tcGVlcjE=\t(none)\t203.0.113.5:51820\t10.0.0.0/24,0.0.0.0/0\t1700000000\t1024\t2048\t25\n\
+        office\tcGVlcjI=\tcHNr\t(none)\t(none)\t0\t0\t0\toff\n\
+        home\tcHJpdmF0ZQ==\t(none)\t0\t0xca6c\n\
+        home\tcGVlcjM=\t(none)\t[2001:db8::1]:51820\t10.1.0.0/16\t1700000100\t5\t6\toff\n";
+
+    #[test]
+    fn parses_the_dump_of_every_interface() {
+        let devices = parse_dump(DUMP).unwrap();
+        let names: Vec<&str> = devices.iter().map(|d| d.name.as_str()).collect();
+        assert_eq!(names, ["office", "home"]);
+
+        let office = &devices[0];
+        assert_eq!(office.public_key.as_deref(), Some("b2ZmaWNl"));
+        assert_eq!(office.listen_port, Some(51820));
+        assert_eq!(office.fwmark, None);
+        assert_eq!(office.peers.len(), 2);
+
+        let active = &office.peers[0];
+        assert_eq!(active.public_key, "cGVlcjE=");
+        assert_eq!(active.endpoint, Some("203.0.113.5:51820".parse().unwrap()));
+        let ips: Vec<String> = active.allowed_ips.iter().map(ToString::to_string).collect();
+        assert_eq!(ips, ["10.0.0.0/24", "0.0.0.0/0"]);
+        assert_eq!(
+            active.latest_handshake,
+            Some(UNIX_EPOCH + Duration::from_secs(1_700_000_000))
+        );
+        assert_eq!((active.rx_bytes, active.tx_bytes), (1024, 2048));
+        assert_eq!(active.persistent_keepalive, Some(25));
+
+        let idle = &office.peers[1];
+        assert_eq!(idle.endpoint, None);
+        assert!(idle.allowed_ips.is_empty());
+        assert_eq!(idle.latest_handshake, None);
+        assert_eq!(idle.persistent_keepalive, None);
+
+        let home = &devices[1];
+        assert_eq!(home.public_key, None);
+        assert_eq!(home.listen_port, None);
+        assert_eq!(home.fwmark, Some(0xca6c));
+        assert_eq!(
+            home.peers[0].endpoint,
+            Some("[2001:db8::1]:51820".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn parses_an_empty_dump() {
+        assert!(parse_dump("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn refuses_a_malformed_dump() {
+        let orphan = "home\tcGVlcjM=\t(none)\t(none)\t(none)\t0\t0\t0\toff\n";
+        assert!(matches!(parse_dump(orphan), Err(BackendError::Output(_))));
+        let counter = DUMP.replace("\t1024\t", "\tmany\t");
+        assert!(matches!(parse_dump(&counter), Err(BackendError::Output(_))));
+        assert!(matches!(
+            parse_dump("office\tshort\n"),
+            Err(BackendError::Output(_))
+        ));
+    }
+}
@@ -0,0 +1,223 @@
+//! Catalog of the errors reported by the tools.
+//!
+//! Every error belongs to an [`ErrorCode`] with a stable three digit code,
+//! a short message, a remediation hint and the exit status of the process.
+//! The messages can be replaced through the `error_codes` table of the
+//! configuration file, see [`Config::error_message`].
+//!
+//! [`Config::error_message`]: crate::config::Config::error_message
+
+use std::fmt;
+
+use serde::{Serialize, Serializer};
+
+/// Kind of an error, identified by a stable code.
+///
+/// Codes and exit statuses are never reused nor changed; new kinds get new
+/// codes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    /// The configuration file does not exist.
+    ConfigMissing,
+    /// The configuration file cannot be read or is not valid.
+    ConfigInvalid,
+    /// No profile has the given name or path.
+    ProfileNotFound,
+    /// Several profiles have the given name, or the alias is taken.
+    ProfileAmbiguous,
+    /// The profile cannot be read or is not valid.
+    ProfileInvalid,
+    /// The tunnel could not be brought up or down.
+    BackendFailure,
+    /// The 2FA step failed.
+    TwoFactorFailed,
+    /// The 2FA authorization did not complete in time.
+    TwoFactorTimeout,
+    /// The user is not allowed to perform the operation.
+    PermissionDenied,
+    /// A key of the profile could not be fetched or decrypted.
+    SecretUnavailable,
+    /// An answer or argument is missing.
+    MissingInput,
+    /// Any other error.
+    Other,
+}
+
+impl ErrorCode {
+    pub const ALL: [Self; 12] = [
+        Self::ConfigMissing,
+        Self::ConfigInvalid,
+        Self::ProfileNotFound,
+        Self::ProfileAmbiguous,
+        Self::ProfileInvalid,
+        Self::BackendFailure,
+        Self::TwoFactorFailed,
+        Self::TwoFactorTimeout,
+        Self::PermissionDenied,
+        Self::SecretUnavailable,
+        Self::MissingInput,
+        Self::Other,
+    ];
+
+    /// Returns the three digit code, the key of the `error_codes` table.
+    pub fn code(self) -> &'static str {
+        match self {
+            Self::ConfigMissing => "000",
+            Self::ConfigInvalid => "001",
+            Self::ProfileNotFound => "010",
+            Self::ProfileAmbiguous => "011",
+            Self::ProfileInvalid => "012",
+            Self::BackendFailure => "020",
+            Self::TwoFactorFailed => "030",
+            Self::TwoFactorTimeout => "031",
+            Self::PermissionDenied => "040",
+            Self::SecretUnavailable => "050",
+            Self::MissingInput => "060",
+            Self::Other => "099",
+        }
+    }
+
+    /// Returns the name of the kind, for scripts.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::ConfigMissing => "config_missing",
+            Self::ConfigInvalid => "config_invalid",
+            Self::ProfileNotFound => "profile_not_found",
+            Self::ProfileAmbiguous => "profile_ambiguous",
+            Self::ProfileInvalid => "profile_invalid",
+            Self::BackendFailure => "backend_failure",
+            Self::TwoFactorFailed => "two_factor_failed",
+            Self::TwoFactorTimeout => "two_factor_timeout",
+            Self::PermissionDenied => "permission_denied",
+            Self::SecretUnavailable => "secret_unavailable",
+            Self::MissingInput => "missing_input",
+            Self::Other => "other",
+        }
+    }
+
+    /// Returns the default message of the kind.
+    pub fn message(self) -> &'static str {
+        match self {
+            Self::ConfigMissing => "Missing wgb configuration",
+            Self::ConfigInvalid => "Invalid wgb configuration",
+            Self::ProfileNotFound => "Profile not found",
+            Self::ProfileAmbiguous => "Ambiguous profile name",
+            Self::ProfileInvalid => "Invalid profile",
+            Self::BackendFailure => "Tunnel operation failed",
+            Self::TwoFactorFailed => "2FA failed",
+            Self::TwoFactorTimeout => "2FA timed out",
+            Self::PermissionDenied => "Permission denied",
+            Self::SecretUnavailable => "Key unavailable",
+            Self::MissingInput => "Missing input",
+            Self::Other => "Error",
+        }
+    }
+
+    /// Returns what the user can do about the error, if anything.
+    pub fn hint(self) -> Option<&'static str> {
+        Some(match self {
+            Self::ConfigMissing => {
+                "create ~/.wgbconf.json with: sudo wgb self install, or give another file with --config"
+            }
+            Self::ConfigInvalid => {
+                "fix the file at the given position, an upgraded file is kept as ~/.wgbconf.json.v<version>.bak"
+            }
+            Self::ProfileNotFound => {
+                "list the profiles with: wgb list, and add their directory with: wgb path add"
+            }
+            Self::ProfileAmbiguous => {
+                "give the full path of the profile, or name it with: wgb profile set <path> --alias <alias>"
+            }
+            Self::ProfileInvalid => "fix the profile at the given position, or check it with: wg-quick strip <profile>",
+            Self::BackendFailure => {
+                "run again with -v to see the commands, the log is in /var/log/wg-bridge/wgb.log"
+            }
+            Self::TwoFactorFailed => "check the 2FA settings of the profile in ~/.wgbconf.json",
+            Self::TwoFactorTimeout => {
+                "approve the connection sooner, or give more time with --timeout"
+            }
+            Self::PermissionDenied => {
+                "join the wgbridge group to go through wgbd, or run the command with sudo"
+            }
+            Self::SecretUnavailable => {
+                "unlock the secret store, or check the age identity in ~/.config/wg-bridge"
+            }
+            Self::MissingInput => "give the missing argument, or run without --non-interactive",
+            Self::Other => return None,
+        })
+    }
+
+    /// Returns the exit status of the process.
+    pub fn exit_code(self) -> u8 {
+        match self {
+            Self::Other | Self::SecretUnavailable => 1,
+            Self::MissingInput => 2,
+            Self::ConfigMissing | Self::ConfigInvalid => 3,
+            Self::BackendFailure | Self::TwoFactorFailed => 4,
+            Self::TwoFactorTimeout => 5,
+            Self::ProfileNotFound | Self::ProfileAmbiguous | Self::ProfileInvalid => 6,
+            Self::PermissionDenied => 7,
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+impl Serialize for ErrorCode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.code())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn codes_and_names_are_unique() {
+        let codes: HashSet<_> = ErrorCode::ALL.iter().map(|kind| kind.code()).collect();
+        let names: HashSet<_> = ErrorCode::ALL.iter().map(|kind| kind.name()).collect();
+        assert_eq!(codes.len(), ErrorCode::ALL.len());
+        assert_eq!(names.len(), ErrorCode::ALL.len());
+        for kind in ErrorCode::ALL {
+            let code = kind.code();
+            assert!(
+                code.len() == 3 && code.bytes().all(|b| b.is_ascii_digit()),
+                "{code}"
+            );
+        }
+    }
+
+    /// The codes and exit statuses are part of the interface: scripts and
+    /// the `error_codes` table rely on them.
+    #[test]
+    fn codes_and_exit_statuses_never_change() {
+        let table: Vec<(&str, &str, u8)> = ErrorCode::ALL
+            .iter()
+            .map(|kind| (kind.code(), kind.name(), kind.exit_code()))
+            .collect();
+        assert_eq!(
+            table,
+            [
+                ("000", "config_missing", 3),
+                ("001", "config_invalid", 3),
+                ("010", "profile_not_found", 6),
+                ("011", "profile_ambiguous", 6),
+                ("012", "profile_invalid", 6),
+                ("020", "backend_failure", 4),
+                ("030", "two_factor_failed", 4),
+                ("031", "two_factor_timeout", 5),
+                ("040", "permission_denied", 7),
+                ("050", "secret_unavailable", 1),
+                ("060", "missing_input", 2),
+                ("099", "other", 1),
+            ]
+        );
+    }
+}
@@ -0,0 +1,648 @@
+//! Model of the `~/.wgbconf.json` configuration file.
+//!
+//! The file is read once, validated and kept in memory as a [`Config`].
+//! Every change goes through the same value and is written back with
+//! [`Config::save`], so a malformed file is reported once with its exact
+//! position instead of silently producing empty lists.
+//!
+//! Several invocations may change the file at once: [`Config::update`]
+//! reloads it under a lock before applying a change, and every write
+//! replaces the file atomically.
+//!
+//! Files written by older versions are upgraded when they are loaded, see
+//! [`migration`](crate::migration); the original is kept next to it.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use tracing::{info, warn};
+
+use crate::auth::AuthMethod;
+use crate::file;
+use crate::migration::{self, CURRENT_VERSION};
+
+/// Name of the configuration file, relative to the user's home directory.
+pub const CONFIG_FILE_NAME: &str = ".wgbconf.json";
+
+/// Directory always searched for WireGuard profiles, before the ones listed
+/// in `conf_path`.
+pub const DEFAULT_SEARCH_PATH: &str = "/etc/wireguard";
+
+/// Errors raised while loading, validating or saving the configuration.
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    /// The configuration file does not exist.
+    #[error("configuration file '{}' not found, reinstall the tool", .0.display())]
+    NotFound(PathBuf),
+
+    /// The home directory could not be determined.
+    #[error("unable to locate the home directory, HOME is not set")]
+    NoHome,
+
+    /// Reading or writing the file failed.
+    #[error("unable to access '{}': {source}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    /// The file is not valid JSON.
+    #[error("{}:{line}:{column}: malformed JSON: {message}", path.display())]
+    Syntax {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+
+    /// The file contains a key the tool does not know about.
+    #[error("{}:{line}:{column}: unknown key '{key}'", path.display())]
+    UnknownKey {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+        key: String,
+    },
+
+    /// A known key holds a value of the wrong type.
+    #[error("{}:{line}:{column}: wrong type for '{key}': {message}", path.display())]
+    WrongType {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+        key: String,
+        message: String,
+    },
+
+    /// The file was written by a newer version of the tool.
+    #[error(
+        "{}: configuration version {version} is newer than the supported {}, update the tool",
+        path.display(),
+        CURRENT_VERSION
+    )]
+    TooNew { path: PathBuf, version: u32 },
+
+    /// The file is well formed but its content is not consistent.
+    #[error("{}: invalid configuration: {message}", path.display())]
+    Invalid { path: PathBuf, message: String },
+}
+
+/// Properties of a single WireGuard profile, stored in `confs[]`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct ConfEntry {
+    /// Identifier of the profile, generated by wgb, which the TOTP seeds
+    /// refer to; it is kept when the file moves.
+    pub id: String,
+
+    /// Full path of the WireGuard configuration file, where it was last
+    /// seen.
+    pub path: PathBuf,
+
+    /// Name the profile is given on the command line, besides the name of
+    /// its file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alias: Option<String>,
+
+    /// Whether the connection requires a 2FA step.
+    #[serde(default)]
+    pub token: bool,
+
+    /// URI of the page where the 2FA PIN is entered.
+    #[serde(default)]
+    pub uri: String,
+
+    /// How the 2FA step is answered, opening `uri` in the browser when not
+    /// set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth: Option<AuthMethod>,
+
+    /// Command telling, by its exit status, whether traffic flows once the
+    /// 2FA step is done; the handshakes of the peers are checked otherwise.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub probe: Option<String>,
+
+    /// Seconds given to the 2FA authorization, 120 when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+}
+
+impl ConfEntry {
+    /// Creates an entry for `path` without 2FA, with a new identifier.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        Self {
+            id: generate_id(&path),
+            path,
+            alias: None,
+            token: false,
+            uri: String::new(),
+            auth: None,
+            probe: None,
+            timeout: None,
+        }
+    }
+}
+
+/// Content of `~/.wgbconf.json`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Version of the layout of the file, 0 when missing.
+    #[serde(default)]
+    pub version: u32,
+
+    /// Directories searched for WireGuard profiles, `*.conf` or
+    /// age-encrypted `*.conf.age`, besides `/etc/wireguard`.
+    #[serde(default)]
+    pub conf_path: Vec<PathBuf>,
+
+    /// Properties of the profiles.
+    #[serde(default)]
+    pub confs: Vec<ConfEntry>,
+
+    /// Messages replacing the default ones of the error codes, keyed by
+    /// their three digit code as listed in wgb(1).
+    #[serde(default)]
+    pub error_codes: BTreeMap<String, String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            version: CURRENT_VERSION,
+            conf_path: Vec::new(),
+            confs: Vec::new(),
+            error_codes: BTreeMap::new(),
+        }
+    }
+}
+
+/// Exclusive lock on the configuration file, see [`Config::lock`].
+#[derive(Debug)]
+pub struct ConfigLock {
+    _lock: file::Lock,
+}
+
+impl Config {
+    /// Returns the path of the configuration file of the current user.
+    pub fn default_path() -> Result<PathBuf, ConfigError> {
+        std::env::var_os("HOME")
+            .filter(|home| !home.is_empty())
+            .map(|home| PathBuf::from(home).join(CONFIG_FILE_NAME))
+            .ok_or(ConfigError::NoHome)
+    }
+
+    /// Reads, parses and validates the configuration file at `path`.
+    ///
+    /// A file written by an older version is upgraded in place under the
+    /// lock, see [`Config::upgrade`].
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let text = read(path)?;
+        if needs_upgrade(&text) {
+            let _lock = Self::lock(path)?;
+            return Self::upgrade(path);
+        }
+        let config = Self::parse(&text, path)?;
+        config.validate(path)?;
+        Ok(config)
+    }
+
+    /// Reads the configuration file at `path` like [`Config::load`], with
+    /// the lock already held.
+    ///
+    /// When the file is older than [`CURRENT_VERSION`], it is first copied
+    /// to `<path>.v<version>.bak`, then migrated and written back. If the
+    /// copy or the write fails, the migrated configuration is still returned
+    /// and the file is left untouched.
+    pub fn upgrade(path: &Path) -> Result<Self, ConfigError> {
+        let text = read(path)?;
+        let mut value = serde_json::from_str(&text).ok();
+        let Some(from) = value.as_mut().and_then(migration::migrate) else {
+            let config = Self::parse(&text, path)?;
+            config.validate(path)?;
+            return Ok(config);
+        };
+
+        let migrated = value.unwrap_or_default().to_string();
+        let config = Self::parse(&migrated, path)?;
+        config.validate(path)?;
+
+        let backup = Self::backup_path(path, from);
+        let result = fs::copy(path, &backup)
+            .map_err(|err| format!("{}: {err}", backup.display()))
+            .and_then(|_| config.save(path).map_err(|err| err.to_string()));
+        match result {
+            Ok(()) => info!(
+                "upgraded '{}' from version {from} to {CURRENT_VERSION}, previous file kept as '{}'",
+                path.display(),
+                backup.display()
+            ),
+            Err(err) => warn!(
+                "unable to upgrade '{}' from version {from}: {err}",
+                path.display()
+            ),
+        }
+        Ok(config)
+    }
+
+    /// Parses `text` as the content of the configuration file at `path`.
+    ///
+    /// `path` is only used to build error messages. The result is not
+    /// validated, see [`Config::validate`].
+    pub fn parse(text: &str, path: &Path) -> Result<Self, ConfigError> {
+        let mut de = serde_json::Deserializer::from_str(text);
+        let config = serde_path_to_error::deserialize(&mut de)
+            .map_err(|err| parse_error(path, err.inner(), err.path().to_string()))?;
+        de.end()
+            .map_err(|err| parse_error(path, &err, String::new()))?;
+        Ok(config)
+    }
+
+    /// Checks the consistency of the configuration.
+    pub fn validate(&self, path: &Path) -> Result<(), ConfigError> {
+        let invalid = |message: String| ConfigError::Invalid {
+            path: path.to_path_buf(),
+            message,
+        };
+
+        if self.version > CURRENT_VERSION {
+            return Err(ConfigError::TooNew {
+                path: path.to_path_buf(),
+                version: self.version,
+            });
+        }
+
+        for (i, dir) in self.conf_path.iter().enumerate() {
+            if !dir.is_absolute() {
+                return Err(invalid(format!(
+                    "conf_path[{i}] '{}' is not an absolute path",
+                    dir.display()
+                )));
+            }
+        }
+
+        for (i, entry) in self.confs.iter().enumerate() {
+            if !is_name(&entry.id) {
+                return Err(invalid(format!(
+                    "confs[{i}].id '{}' is not a valid identifier",
+                    entry.id
+                )));
+            }
+            if !entry.path.is_absolute() {
+                return Err(invalid(format!(
+                    "confs[{i}].path '{}' is not an absolute path",
+                    entry.path.display()
+                )));
+            }
+            if let Some(alias) = entry.alias.as_deref().filter(|alias| !is_name(alias)) {
+                return Err(invalid(format!(
+                    "confs[{i}].alias '{alias}' is empty or holds a '/' or a space"
+                )));
+            }
+            let auth = entry.auth.clone().unwrap_or_default();
+            if entry.token && auth.needs_uri() && entry.uri.trim().is_empty() {
+                return Err(invalid(format!(
+                    "confs[{i}] '{}' requires a token but has no 2FA uri",
+                    entry.path.display()
+                )));
+            }
+            if entry.auth.is_some() && !entry.token {
+                return Err(invalid(format!(
+                    "confs[{i}] '{}' has 2FA settings but does not require a token",
+                    entry.path.display()
+                )));
+            }
+            auth.check()
+                .map_err(|message| invalid(format!("confs[{i}].auth: {message}")))?;
+            if entry
+                .probe
+                .as_ref()
+                .is_some_and(|probe| probe.trim().is_empty())
+            {
+                return Err(invalid(format!("confs[{i}].probe is empty")));
+            }
+            if entry.timeout == Some(0) {
+                return Err(invalid(format!("confs[{i}].timeout must be at least 1")));
+            }
+            if let Some(j) = self.confs[..i].iter().position(|e| e.path == entry.path) {
+                return Err(invalid(format!(
+                    "confs[{i}] and confs[{j}] both describe '{}'",
+                    entry.path.display()
+                )));
+            }
+            if let Some(j) = self.confs[..i].iter().position(|e| e.id == entry.id) {
+                return Err(invalid(format!(
+                    "confs[{i}] and confs[{j}] have the same id '{}'",
+                    entry.id
+                )));
+            }
+            if let Some(alias) = &entry.alias {
+                let taken = self.confs[..i]
+                    .iter()
+                    .position(|e| e.alias.as_ref() == Some(alias) || e.id == *alias);
+                if let Some(j) = taken {
+                    return Err(invalid(format!(
+                        "confs[{i}].alias '{alias}' already names confs[{j}]"
+                    )));
+                }
+            }
+        }
+
+        for code in self.error_codes.keys() {
+            if code.len() != 3 || !code.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(invalid(format!(
+                    "error_codes key '{code}' is not a three digit code"
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the lock file of the configuration file at `path`.
+    pub fn lock_path(path: &Path) -> PathBuf {
+        file::with_suffix(path, ".lock")
+    }
+
+    /// Returns the copy of the configuration file at `path` kept when it is
+    /// upgraded from `version`.
+    pub fn backup_path(path: &Path, version: u32) -> PathBuf {
+        file::with_suffix(path, &format!(".v{version}.bak"))
+    }
+
+    /// Waits for the exclusive lock on the configuration file at `path`,
+    /// held until the returned guard is dropped, and recovers the temporary
+    /// file left by a writer that crashed.
+    pub fn lock(path: &Path) -> Result<ConfigLock, ConfigError> {
+        let lock_path = Self::lock_path(path);
+        let lock = file::Lock::acquire(&lock_path).map_err(|source| ConfigError::Io {
+            path: lock_path,
+            source,
+        })?;
+        recover_temp(path);
+        Ok(ConfigLock { _lock: lock })
+    }
+
+    /// Applies `change` to the configuration file at `path`: locks it,
+    /// reads it again, applies the change and writes the result, so that
+    /// concurrent updates are not lost. Returns the updated configuration.
+    pub fn update(path: &Path, change: impl FnOnce(&mut Self)) -> Result<Self, ConfigError> {
+        let _lock = Self::lock(path)?;
+        let mut config = Self::upgrade(path)?;
+        change(&mut config);
+        config.save(path)?;
+        Ok(config)
+    }
+
+    /// Writes the configuration to `path`.
+    ///
+    /// The content is written to a temporary file next to `path`, flushed
+    /// and renamed over it. The caller should hold the lock, see
+    /// [`Config::update`].
+    pub fn save(&self, path: &Path) -> Result<(), ConfigError> {
+        let io_err = |source| ConfigError::Io {
+            path: path.to_path_buf(),
+            source,
+        };
+        let mut text = serde_json::to_string_pretty(self).map_err(|e| io_err(e.into()))?;
+        text.push('\n');
+        file::write_atomic(path, text.as_bytes(), 0o644).map_err(io_err)
+    }
+
+    /// Returns the directories to search for WireGuard profiles: the default
+    /// one followed by the ones listed in `conf_path`.
+    pub fn search_paths(&self) -> impl Iterator<Item = &Path> {
+        std::iter::once(Path::new(DEFAULT_SEARCH_PATH))
+            .chain(self.conf_path.iter().map(PathBuf::as_path))
+    }
+
+    /// Returns the properties of the profile at `path`, if recorded.
+    pub fn entry(&self, path: &Path) -> Option<&ConfEntry> {
+        self.confs.iter().find(|entry| entry.path == path)
+    }
+
+    /// Returns the properties of the profile at `path` for modification.
+    pub fn entry_mut(&mut self, path: &Path) -> Option<&mut ConfEntry> {
+        self.confs.iter_mut().find(|entry| entry.path == path)
+    }
+
+    /// Returns the properties of the profile called `name`: its alias or its
+    /// identifier.
+    pub fn named(&self, name: &str) -> Option<&ConfEntry> {
+        self.confs
+            .iter()
+            .find(|entry| entry.alias.as_deref() == Some(name))
+            .or_else(|| self.confs.iter().find(|entry| entry.id == name))
+    }
+
+    /// Returns the identifier of the profile at `path`, if recorded.
+    pub fn id_of(&self, path: &Path) -> Option<&str> {
+        self.entry(path).map(|entry| entry.id.as_str())
+    }
+
+    /// Returns the message set for an error code, if any.
+    pub fn error_message(&self, code: &str) -> Option<&str> {
+        self.error_codes.get(code).map(String::as_str)
+    }
+}
+
+/// Returns a new identifier for the profile at `path`: 12 hexadecimal
+/// digits of a hash of the path, the time and the process.
+pub(crate) fn generate_id(path: &Path) -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos());
+    let digest = Sha256::new()
+        .chain_update(path.as_os_str().as_encoded_bytes())
+        .chain_update(nanos.to_le_bytes())
+        .chain_update(process::id().to_le_bytes())
+        .finalize();
+    digest[..6].iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Returns whether `name` may identify a profile on the command line: not
+/// empty, without a `/`, which makes it a path, nor a space.
+fn is_name(name: &str) -> bool {
+    !name.is_empty() && !name.contains('/') && !name.contains(char::is_whitespace)
+}
+
+/// Reads the configuration file at `path`.
+fn read(path: &Path) -> Result<String, ConfigError> {
+    fs::read_to_string(path).map_err(|source| match source.kind() {
+        io::ErrorKind::NotFound => ConfigError::NotFound(path.to_path_buf()),
+        _ => ConfigError::Io {
+            path: path.to_path_buf(),
+            source,
+        },
+    })
+}
+
+/// Returns whether `text` holds a configuration older than
+/// [`CURRENT_VERSION`]. Malformed files are left to [`Config::parse`] to
+/// report.
+fn needs_upgrade(text: &str) -> bool {
+    serde_json::from_str(text)
+        .ok()
+        .and_then(|value| migration::version_of(&value))
+        .is_some_and(|version| version < CURRENT_VERSION)
+}
+
+/// Maps a deserialization error to the matching [`ConfigError`] variant.
+///
+/// `key` is the dotted path of the value being read when the error occurred.
+fn parse_error(path: &Path, inner: &serde_json::Error, key: String) -> ConfigError {
+    let path = path.to_path_buf();
+    let (line, column) = (inner.line(), inner.column());
+
+    if !inner.is_data() {
+        return ConfigError::Syntax {
+            path,
+            line,
+            column,
+            message: strip_position(&inner.to_string()),
+        };
+    }
+
+    let message = strip_position(&inner.to_string());
+    match message.strip_prefix("unknown field `") {
+        Some(rest) => {
+            let key = match key.as_str() {
+                "." | "" => rest.split('`').next().unwrap_or_default().to_owned(),
+                _ => key,
+            };
+            ConfigError::UnknownKey {
+                path,
+                line,
+                column,
+                key,
+            }
+        }
+        None => ConfigError::WrongType {
+            path,
+            line,
+            column,
+            key,
+            message,
+        },
+    }
+}
+
+/// Removes the trailing " at line X column Y" added by serde_json, as the
+/// position is reported separately.
+fn strip_position(message: &str) -> String {
+    match message.rfind(" at line ") {
+        Some(idx) => message[..idx].to_owned(),
+        None => message.to_owned(),
+    }
+}
+
+/// Deals with the temporary file left next to `path` by a writer that
+/// crashed before renaming it: it replaces a missing file if it holds a
+/// valid configuration, and is removed otherwise. Must be called with the
+/// lock held.
+fn recover_temp(path: &Path) {
+    let tmp = file::temp_path(path);
+    if !tmp.exists() {
+        return;
+    }
+    let usable = !path.exists()
+        && fs::read_to_string(&tmp).is_ok_and(|text| Config::parse(&text, path).is_ok());
+    let result = if usable {
+        fs::rename(&tmp, path)
+    } else {
+        fs::remove_file(&tmp)
+    };
+    let action = if usable { "restored" } else { "removed" };
+    match result {
+        Ok(()) => info!("{action} stale '{}'", tmp.display()),
+        Err(err) => warn!("{}: {err}", tmp.display()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Loads `text` from a temporary file, which must fail, and returns the
+    /// path of the file with the error. The errors point at the last
+    /// character of the faulty token.
+    fn refused(text: &str) -> (PathBuf, ConfigError) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".wgbconf.json");
+        fs::write(&path, text).unwrap();
+        let err = Config::load(&path).unwrap_err();
+        (path, err)
+    }
+
+    #[test]
+    fn reports_where_the_json_is_malformed() {
+        let (expected, err) = refused("{\n  \"version\": 3,\n  \"confs\": [\n}\n");
+        let ConfigError::Syntax {
+            path, line, column, ..
+        } = &err
+        else {
+            panic!("unexpected error: {err}");
+        };
+        assert_eq!((path, *line, *column), (&expected, 4, 1));
+        assert!(err
+            .to_string()
+            .starts_with(&format!("{}:4:1: malformed JSON: ", expected.display())));
+    }
+
+    #[test]
+    fn reports_an_unknown_key_of_a_profile() {
+        let text = r#"{
+  "version": 3,
+  "confs": [
+    {"id": "office", "path": "/etc/wireguard/office.conf", "tokn": true}
+  ]
+}"#;
+        let (expected, err) = refused(text);
+        let ConfigError::UnknownKey {
+            path,
+            line,
+            column,
+            key,
+        } = &err
+        else {
+            panic!("unexpected error: {err}");
+        };
+        assert_eq!((path, *line, *column), (&expected, 4, 65));
+        assert_eq!(key, "confs[0].tokn");
+    }
+
+    #[test]
+    fn reports_a_token_of_the_wrong_type() {
+        let text = r#"{
+  "version": 3,
+  "confs": [
+    {"id": "office", "path": "/etc/wireguard/office.conf", "token": "yes"}
+  ]
+}"#;
+        let (expected, err) = refused(text);
+        let ConfigError::WrongType {
+            path,
+            line,
+            column,
+            key,
+            message,
+        } = &err
+        else {
+            panic!("unexpected error: {err}");
+        };
+        assert_eq!((path, *line, *column), (&expected, 4, 73));
+        assert_eq!(key, "confs[0].token");
+        assert!(message.contains("expected a boolean"), "{message}");
+    }
+}
@@ -0,0 +1,154 @@
+//! Search of the WireGuard profiles in the configured directories.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::encrypted;
+use crate::exec::{self, ExecError};
+use crate::profile::{Cidr, Profile, ProfileError};
+
+/// Extension of the WireGuard profiles.
+pub const PROFILE_EXTENSION: &str = "conf";
+
+/// Returns the profiles found under `dirs`, recursively, sorted and without
+/// duplicates: the `*.conf` files and the `*.conf.age` encrypted ones.
+///
+/// Directories that do not exist are skipped. Directories the user cannot
+/// read, such as `/etc/wireguard`, are searched with root privileges when
+/// [`exec::may_sudo`], and skipped otherwise.
+pub fn find_profiles<'a>(
+    dirs: impl IntoIterator<Item = &'a Path>,
+) -> Result<Vec<PathBuf>, ExecError> {
+    let mut found = Vec::new();
+    for dir in dirs {
+        match walk(dir, &mut found) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::PermissionDenied && exec::may_sudo() => {
+                found.extend(find_privileged(dir)?);
+            }
+            Err(err) => warn!("{}: {err}", dir.display()),
+        }
+    }
+    found.sort();
+    found.dedup();
+    Ok(found)
+}
+
+/// Returns the profiles found under `dirs` like [`find_profiles`], but only
+/// in the directories the user can read, never asking for privileges.
+pub fn find_readable<'a>(dirs: impl IntoIterator<Item = &'a Path>) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    for dir in dirs {
+        let _ = walk(dir, &mut found);
+    }
+    found.sort();
+    found.dedup();
+    found
+}
+
+/// What the tool shows about a profile without bringing it up.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProfileInfo {
+    pub path: PathBuf,
+    pub addresses: Vec<Cidr>,
+    pub endpoints: Vec<String>,
+    /// Why the profile could not be read, if it could not.
+    pub error: Option<String>,
+}
+
+/// Reads the profiles at `paths` and describes them.
+pub fn describe(paths: &[PathBuf]) -> Vec<ProfileInfo> {
+    describe_with(paths, |err| err)
+}
+
+/// Like [`describe`], without quoting the content of the profiles in the
+/// errors, see [`ProfileError::redact`].
+pub fn describe_redacted(paths: &[PathBuf]) -> Vec<ProfileInfo> {
+    describe_with(paths, ProfileError::redact)
+}
+
+fn describe_with(
+    paths: &[PathBuf],
+    report: impl Fn(ProfileError) -> ProfileError,
+) -> Vec<ProfileInfo> {
+    paths
+        .iter()
+        .map(|path| match Profile::load(path) {
+            Ok(profile) => ProfileInfo {
+                path: path.clone(),
+                addresses: profile.interface().addresses(),
+                endpoints: profile
+                    .peers()
+                    .filter_map(|p| p.endpoint())
+                    .map(str::to_owned)
+                    .collect(),
+                error: None,
+            },
+            Err(err) => ProfileInfo {
+                path: path.clone(),
+                addresses: Vec::new(),
+                endpoints: Vec::new(),
+                error: Some(report(err).to_string()),
+            },
+        })
+        .collect()
+}
+
+/// Returns whether `path` names a WireGuard profile, plain or encrypted.
+pub fn is_profile(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == PROFILE_EXTENSION) || encrypted::is_encrypted(path)
+}
+
+/// Returns the name of the profile at `path`: its file name without the
+/// `.conf` or `.conf.age` extension.
+pub fn profile_name(path: &Path) -> Option<&str> {
+    let name = path.file_name()?.to_str()?;
+    let name = name
+        .strip_suffix(&format!(".{}", encrypted::EXTENSION) as &str)
+        .unwrap_or(name);
+    Some(
+        name.strip_suffix(&format!(".{PROFILE_EXTENSION}") as &str)
+            .unwrap_or(name),
+    )
+}
+
+fn walk(dir: &Path, found: &mut Vec<PathBuf>) -> io::Result<()> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err),
+    };
+    for entry in entries {
+        let entry = entry?;
+        let path = entry.path();
+        let kind = entry.file_type()?;
+        if kind.is_dir() {
+            walk(&path, found)?;
+        } else if kind.is_file() && is_profile(&path) {
+            found.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn find_privileged(dir: &Path) -> Result<Vec<PathBuf>, ExecError> {
+    let output = exec::run(
+        exec::privileged("find")
+            .arg(dir)
+            .args(["-type", "f", "(", "-name"])
+            .arg(format!("*.{PROFILE_EXTENSION}"))
+            .arg("-o")
+            .arg("-name")
+            .arg(format!("*.{PROFILE_EXTENSION}.{}", encrypted::EXTENSION))
+            .arg(")"),
+    )?;
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|l| !l.is_empty())
+        .map(PathBuf::from)
+        .collect())
+}
@@ -0,0 +1,248 @@
+//! Profiles encrypted at rest with age.
+//!
+//! A profile saved as `<name>.conf.age` is an age file holding a `wg-quick`
+//! profile, armored or not, so that profiles can be shared through a file
+//! share. It is decrypted in memory with the identity of the user, see
+//! [`identity_path`], whenever it is read; the plain text never reaches the
+//! disk.
+
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufReader, Read, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use age::armor::ArmoredReader;
+use age::{x25519, Decryptor, Encryptor, IdentityFile, Recipient};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::file;
+
+/// Extension added to the encrypted profiles.
+pub const EXTENSION: &str = "age";
+
+/// Name of the identity file, inside the configuration directory.
+pub const IDENTITY_FILE_NAME: &str = "identity.txt";
+
+/// Environment variable overriding the path of the identity file.
+pub const IDENTITY_ENV: &str = "WGB_AGE_IDENTITY";
+
+/// Errors raised while encrypting or decrypting a profile.
+#[derive(Debug, Error)]
+pub enum EncryptionError {
+    /// Neither `XDG_CONFIG_HOME` nor `HOME` is set.
+    #[error("unable to locate the configuration directory, HOME is not set")]
+    NoHome,
+
+    /// Reading or writing a file failed.
+    #[error("unable to access '{}': {source}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    /// The user has no identity.
+    #[error(
+        "no age identity at '{}', create one with: age-keygen -o '{0}'",
+        path.display()
+    )]
+    NoIdentity { path: PathBuf },
+
+    /// Other users may read the identity file.
+    #[error(
+        "'{}' is readable by other users, restrict it with: chmod 600 '{0}'",
+        path.display()
+    )]
+    Exposed { path: PathBuf },
+
+    /// The identity file is not valid.
+    #[error("{}: invalid age identity file: {message}", path.display())]
+    Identity { path: PathBuf, message: String },
+
+    /// A recipient is not an age public key.
+    #[error("'{0}' is not an age recipient, expected a public key starting with age1")]
+    Recipient(String),
+
+    /// The profile could not be encrypted.
+    #[error("unable to encrypt '{}': {message}", path.display())]
+    Encrypt { path: PathBuf, message: String },
+
+    /// The profile could not be decrypted.
+    #[error("unable to decrypt '{}': {message}", path.display())]
+    Decrypt { path: PathBuf, message: String },
+}
+
+/// The text of a profile, decrypted or read by the daemon. Wiped from
+/// memory once dropped.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Plaintext(String);
+
+impl Plaintext {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for Plaintext {
+    fn from(text: String) -> Self {
+        Self(text)
+    }
+}
+
+impl fmt::Debug for Plaintext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Plaintext(..)")
+    }
+}
+
+impl Drop for Plaintext {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+/// Returns whether `path` names an encrypted profile, `*.conf.age`.
+pub fn is_encrypted(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == EXTENSION)
+        && path
+            .file_stem()
+            .map(Path::new)
+            .and_then(Path::extension)
+            .is_some_and(|ext| ext == crate::discovery::PROFILE_EXTENSION)
+}
+
+/// Returns the path of the encrypted profile for the profile at `path`.
+pub fn encrypted_path(path: &Path) -> PathBuf {
+    file::with_suffix(path, &format!(".{EXTENSION}"))
+}
+
+/// Returns the path of the plain profile for the encrypted one at `path`.
+pub fn decrypted_path(path: &Path) -> PathBuf {
+    path.with_extension("")
+}
+
+/// Returns the path of the identity file: `$WGB_AGE_IDENTITY`, else
+/// `$XDG_CONFIG_HOME/wg-bridge/identity.txt`, else
+/// `~/.config/wg-bridge/identity.txt`.
+pub fn identity_path() -> Result<PathBuf, EncryptionError> {
+    if let Some(path) = env::var_os(IDENTITY_ENV).filter(|path| !path.is_empty()) {
+        return Ok(PathBuf::from(path));
+    }
+    let dir = file::config_dir().ok_or(EncryptionError::NoHome)?;
+    Ok(dir.join(IDENTITY_FILE_NAME))
+}
+
+/// Reads the identity file of the user. A file other users may read is
+/// refused.
+fn identity_file() -> Result<IdentityFile<age::NoCallbacks>, EncryptionError> {
+    let path = identity_path()?;
+    let meta = match fs::metadata(&path) {
+        Ok(meta) => meta,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            return Err(EncryptionError::NoIdentity { path })
+        }
+        Err(source) => return Err(EncryptionError::Io { path, source }),
+    };
+    if meta.permissions().mode() & 0o077 != 0 {
+        return Err(EncryptionError::Exposed { path });
+    }
+    let text = Zeroizing::new(fs::read(&path).map_err(|source| EncryptionError::Io {
+        path: path.clone(),
+        source,
+    })?);
+    IdentityFile::from_buffer(text.as_slice()).map_err(|err| EncryptionError::Identity {
+        path,
+        message: err.to_string(),
+    })
+}
+
+/// Decrypts the profile at `path` with the identity of the user.
+pub fn decrypt(path: &Path) -> Result<Plaintext, EncryptionError> {
+    let failed = |message: String| EncryptionError::Decrypt {
+        path: path.to_path_buf(),
+        message,
+    };
+    let file = fs::File::open(path).map_err(|source| EncryptionError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let identities = identity_file()?
+        .into_identities()
+        .map_err(|err| failed(err.to_string()))?;
+    let decryptor = Decryptor::new(ArmoredReader::new(BufReader::new(file)))
+        .map_err(|err| failed(err.to_string()))?;
+    let mut reader = decryptor
+        .decrypt(identities.iter().map(|identity| identity.as_ref()))
+        .map_err(|err| failed(err.to_string()))?;
+    let mut text = Zeroizing::new(Vec::new());
+    reader
+        .read_to_end(&mut text)
+        .map_err(|err| failed(err.to_string()))?;
+    let text = String::from_utf8(std::mem::take(&mut *text))
+        .map_err(|_| failed("the profile is not UTF-8 text".to_owned()))?;
+    Ok(Plaintext(text))
+}
+
+/// Encrypts `text`, the profile at `path`, to `recipients`, age public
+/// keys; to the identity of the user when there is none.
+pub fn encrypt(text: &str, path: &Path, recipients: &[String]) -> Result<Vec<u8>, EncryptionError> {
+    let failed = |message: String| EncryptionError::Encrypt {
+        path: path.to_path_buf(),
+        message,
+    };
+    let recipients: Vec<Box<dyn Recipient + Send>> = if recipients.is_empty() {
+        identity_file()?
+            .to_recipients()
+            .map_err(|err| failed(err.to_string()))?
+    } else {
+        recipients
+            .iter()
+            .map(|recipient| {
+                x25519::Recipient::from_str(recipient.trim())
+                    .map(|r| Box::new(r) as Box<dyn Recipient + Send>)
+                    .map_err(|_| EncryptionError::Recipient(recipient.clone()))
+            })
+            .collect::<Result<_, _>>()?
+    };
+    let encryptor = Encryptor::with_recipients(recipients.iter().map(|r| r.as_ref() as _))
+        .map_err(|err| failed(err.to_string()))?;
+    let mut output = Vec::new();
+    let mut writer = encryptor
+        .wrap_output(&mut output)
+        .map_err(|err| failed(err.to_string()))?;
+    writer
+        .write_all(text.as_bytes())
+        .and_then(|()| writer.finish().map(drop))
+        .map_err(|err| failed(err.to_string()))?;
+    Ok(output)
+}
+
+/// Writes `data`, an encrypted profile, to `path`, readable by its owner
+/// only.
+pub fn write(path: &Path, data: &[u8]) -> Result<(), EncryptionError> {
+    file::write_atomic(path, data, 0o600).map_err(|source| EncryptionError::Io {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// Reads the recipients listed in the file at `path`, one per line; blank
+/// lines and lines starting with `#` are skipped.
+pub fn read_recipients(path: &Path) -> Result<Vec<String>, EncryptionError> {
+    let text = fs::read_to_string(path).map_err(|source| EncryptionError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    Ok(text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_owned)
+        .collect())
+}
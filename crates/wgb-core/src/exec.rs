@@ -0,0 +1,168 @@
+//! Execution of external commands, with `sudo` when root privileges are
+//! required and the standard error kept in the log file.
+
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::process::{Command, ExitStatus, Output, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use thiserror::Error;
+use tracing::{debug, info, warn};
+
+/// Whether files may be read with `sudo`, see [`forbid_sudo`].
+static SUDO_ALLOWED: AtomicBool = AtomicBool::new(true);
+
+/// Errors raised while running an external command.
+#[derive(Debug, Error)]
+pub enum ExecError {
+    /// The command could not be started.
+    #[error("unable to run '{program}': {source}")]
+    Spawn {
+        program: String,
+        #[source]
+        source: io::Error,
+    },
+
+    /// The command exited with a failure status.
+    #[error("'{program}' failed ({status}){}", last_line(stderr))]
+    Failed {
+        program: String,
+        status: ExitStatus,
+        stderr: String,
+    },
+}
+
+/// Returns whether the process runs with root privileges.
+pub fn is_root() -> bool {
+    // SAFETY: geteuid has no preconditions and cannot fail.
+    unsafe { libc::geteuid() == 0 }
+}
+
+/// Forbids reading files and searching directories with `sudo` from now
+/// on: they are refused as the user is refused. Called when the daemon
+/// does the privileged work instead.
+pub fn forbid_sudo() {
+    SUDO_ALLOWED.store(false, Ordering::Relaxed);
+}
+
+/// Returns whether what the user cannot read may be read with `sudo`: the
+/// process is not root and [`forbid_sudo`] was not called.
+pub fn may_sudo() -> bool {
+    !is_root() && SUDO_ALLOWED.load(Ordering::Relaxed)
+}
+
+/// Builds a command for `program` that runs with root privileges, through
+/// `sudo` when the process is not already root.
+pub fn privileged(program: &str) -> Command {
+    if is_root() {
+        Command::new(program)
+    } else {
+        let mut cmd = Command::new("sudo");
+        cmd.arg(program);
+        cmd
+    }
+}
+
+/// Returns whether `program` is an executable file of the `PATH`.
+pub fn in_path(program: &str) -> bool {
+    env::var_os("PATH").is_some_and(|path| {
+        env::split_paths(&path).any(|dir| {
+            fs::metadata(dir.join(program))
+                .is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+        })
+    })
+}
+
+/// Runs `cmd` capturing its output.
+///
+/// The standard error is logged; a failure status is turned into
+/// [`ExecError::Failed`].
+pub fn run(cmd: &mut Command) -> Result<Output, ExecError> {
+    let program = program_name(cmd);
+    debug!(command = ?cmd, "running {program}");
+    let output = cmd
+        .stdin(Stdio::inherit())
+        .output()
+        .map_err(|source| ExecError::Spawn {
+            program: program.clone(),
+            source,
+        })?;
+    check(program, output)
+}
+
+/// Runs `cmd` like [`run`], writing `input` to its standard input.
+pub fn run_with_input(cmd: &mut Command, input: &[u8]) -> Result<Output, ExecError> {
+    let program = program_name(cmd);
+    debug!(command = ?cmd, "running {program}");
+    let spawn_err = |source| ExecError::Spawn {
+        program: program.clone(),
+        source,
+    };
+    let mut child = cmd
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(spawn_err)?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(input).map_err(spawn_err)?;
+    }
+    let output = child.wait_with_output().map_err(spawn_err)?;
+    check(program, output)
+}
+
+/// Reads the file at `path`, with root privileges when the user is not
+/// allowed to read it, unless [`may_sudo`] says otherwise.
+pub fn read_to_string(path: &Path) -> io::Result<String> {
+    match fs::read_to_string(path) {
+        Err(err) if err.kind() == io::ErrorKind::PermissionDenied && may_sudo() => {
+            let output = run(privileged("cat").arg("--").arg(path)).map_err(io::Error::other)?;
+            String::from_utf8(output.stdout).map_err(io::Error::other)
+        }
+        result => result,
+    }
+}
+
+/// Logs the standard error of a finished command and checks its status.
+fn check(program: String, output: Output) -> Result<Output, ExecError> {
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+    for line in stderr.lines().filter(|line| !line.trim().is_empty()) {
+        if output.status.success() {
+            info!("{program}: {line}");
+        } else {
+            warn!("{program}: {line}");
+        }
+    }
+    if output.status.success() {
+        Ok(output)
+    } else {
+        Err(ExecError::Failed {
+            program,
+            status: output.status,
+            stderr,
+        })
+    }
+}
+
+/// Returns the name of the program run by `cmd`, skipping `sudo`.
+fn program_name(cmd: &Command) -> String {
+    let program = cmd.get_program();
+    let program = if program == "sudo" {
+        cmd.get_args().next().unwrap_or(program)
+    } else {
+        program
+    };
+    program.to_string_lossy().into_owned()
+}
+
+fn last_line(stderr: &str) -> String {
+    stderr
+        .lines()
+        .rev()
+        .find(|l| !l.trim().is_empty())
+        .map(|l| format!(": {}", l.trim()))
+        .unwrap_or_default()
+}
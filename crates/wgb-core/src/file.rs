@@ -0,0 +1,103 @@
+//! Safe writes of the files shared by concurrent invocations.
+
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::{fchown, MetadataExt, OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+/// Exclusive advisory lock on a file, released when dropped.
+#[derive(Debug)]
+pub struct Lock {
+    _file: File,
+}
+
+impl Lock {
+    /// Waits for an exclusive lock on `path`, creating the file if needed.
+    /// An existing file is only opened for reading, so a lock file created
+    /// by root does not lock the user out.
+    pub fn acquire(path: &Path) -> io::Result<Self> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .mode(0o644)
+                .open(path)?,
+            Err(err) => return Err(err),
+        };
+        loop {
+            // SAFETY: the descriptor is owned by `file`, alive for the call.
+            if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } == 0 {
+                return Ok(Self { _file: file });
+            }
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(err);
+            }
+        }
+    }
+}
+
+/// Returns the configuration directory of the tool:
+/// `$XDG_CONFIG_HOME/wg-bridge`, else `~/.config/wg-bridge`; `None` when
+/// neither variable is set.
+pub fn config_dir() -> Option<PathBuf> {
+    let var = |name| env::var_os(name).filter(|value| !value.is_empty());
+    let dir = match var("XDG_CONFIG_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(var("HOME")?).join(".config"),
+    };
+    Some(dir.join("wg-bridge"))
+}
+
+/// Returns `path` with `suffix` appended, e.g. `.tmp`.
+pub fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// Returns the temporary file used while writing `path`.
+pub fn temp_path(path: &Path) -> PathBuf {
+    with_suffix(path, ".tmp")
+}
+
+/// Replaces the content of `path` with `contents`, so that readers see
+/// either the old or the new content, even after a crash.
+///
+/// The content is written to a temporary file, flushed to the disk and
+/// renamed over `path`. An existing file keeps its permissions and owner; a
+/// new one is created with `mode`.
+pub fn write_atomic(path: &Path, contents: &[u8], mode: u32) -> io::Result<()> {
+    let existing = fs::metadata(path).ok();
+    let tmp = temp_path(path);
+    let result = (|| {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(mode)
+            .open(&tmp)?;
+        if let Some(meta) = &existing {
+            file.set_permissions(fs::Permissions::from_mode(meta.mode() & 0o7777))?;
+            if meta.uid() != file.metadata()?.uid() {
+                fchown(&file, Some(meta.uid()), Some(meta.gid()))?;
+            }
+        }
+        file.write_all(contents)?;
+        file.sync_all()?;
+        fs::rename(&tmp, path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result?;
+    // Make the rename itself durable.
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
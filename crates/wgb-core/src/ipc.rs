@@ -0,0 +1,159 @@
+//! Protocol between the `wgb` client and the `wgbd` privileged daemon.
+//!
+//! The client connects to a Unix socket and sends one JSON encoded
+//! [`Request`] per line; the daemon answers each one with a JSON encoded
+//! [`Response`] on a line.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::backend::Device;
+use crate::discovery::ProfileInfo;
+use crate::encrypted::Plaintext;
+use crate::secret::Secrets;
+
+/// Default path of the daemon socket.
+pub const SOCKET_PATH: &str = "/run/wg-bridge/wgbd.sock";
+
+/// Environment variable overriding [`SOCKET_PATH`].
+pub const SOCKET_ENV: &str = "WGBD_SOCKET";
+
+/// Errors raised while talking to the daemon.
+#[derive(Debug, Error)]
+pub enum IpcError {
+    /// The daemon is not reachable.
+    #[error("unable to reach wgbd at '{}': {source}", path.display())]
+    Connect {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    /// The connection failed while exchanging messages.
+    #[error("lost connection with wgbd: {0}")]
+    Io(#[from] io::Error),
+
+    /// A message could not be understood.
+    #[error("unexpected message from wgbd: {0}")]
+    Protocol(String),
+
+    /// The daemon refused the request.
+    #[error("permission denied by wgbd: {0}")]
+    Denied(String),
+
+    /// The daemon accepted the request but it failed.
+    #[error("{0}")]
+    Failed(String),
+}
+
+/// Operation requested to the daemon.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Request {
+    /// Bring up the profile at `path`, with the `secrets` its keys
+    /// reference and its `plaintext`, if encrypted.
+    Connect {
+        path: PathBuf,
+        #[serde(default, skip_serializing_if = "Secrets::is_empty")]
+        secrets: Secrets,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        plaintext: Option<Plaintext>,
+    },
+    /// Tear down the profile at `path`, with its `plaintext`, if encrypted.
+    Disconnect {
+        path: PathBuf,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        plaintext: Option<Plaintext>,
+    },
+    /// Apply the profile at `path` to its running interface, with the
+    /// `secrets` its keys reference and its `plaintext`, if encrypted.
+    Sync {
+        path: PathBuf,
+        #[serde(default, skip_serializing_if = "Secrets::is_empty")]
+        secrets: Secrets,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        plaintext: Option<Plaintext>,
+    },
+    /// Report the state of the WireGuard interfaces.
+    Status,
+    /// Find and describe the profiles under the search paths of the daemon.
+    List,
+}
+
+/// Answer of the daemon.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "result", rename_all = "snake_case")]
+pub enum Response {
+    Done,
+    Devices { devices: Vec<Device> },
+    Profiles { profiles: Vec<ProfileInfo> },
+    Denied { message: String },
+    Failed { message: String },
+}
+
+/// Returns the path of the daemon socket.
+pub fn socket_path() -> PathBuf {
+    std::env::var_os(SOCKET_ENV)
+        .filter(|path| !path.is_empty())
+        .map_or_else(|| PathBuf::from(SOCKET_PATH), PathBuf::from)
+}
+
+/// Connection to the daemon.
+#[derive(Debug)]
+pub struct Client {
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+}
+
+impl Client {
+    /// Connects to the daemon listening on `path`.
+    pub fn connect(path: &Path) -> Result<Self, IpcError> {
+        let stream = UnixStream::connect(path).map_err(|source| IpcError::Connect {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Ok(Self {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+        })
+    }
+
+    /// Sends `request` and waits for the answer. Refusals and failures are
+    /// returned as errors.
+    pub fn call(&mut self, request: &Request) -> Result<Response, IpcError> {
+        write_message(&mut self.writer, request)?;
+        let response = read_message(&mut self.reader)?
+            .ok_or_else(|| IpcError::Protocol("connection closed".to_owned()))?;
+        match response {
+            Response::Denied { message } => Err(IpcError::Denied(message)),
+            Response::Failed { message } => Err(IpcError::Failed(message)),
+            response => Ok(response),
+        }
+    }
+}
+
+/// Writes `message` as a line of JSON.
+pub fn write_message<T: Serialize>(writer: &mut impl Write, message: &T) -> Result<(), IpcError> {
+    let mut line = serde_json::to_string(message).map_err(|e| IpcError::Protocol(e.to_string()))?;
+    line.push('\n');
+    writer.write_all(line.as_bytes())?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Reads a line of JSON; `None` at the end of the stream.
+pub fn read_message<T: for<'de> Deserialize<'de>>(
+    reader: &mut impl BufRead,
+) -> Result<Option<T>, IpcError> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    serde_json::from_str(&line)
+        .map(Some)
+        .map_err(|e| IpcError::Protocol(e.to_string()))
+}
@@ -0,0 +1,25 @@
+//! Core library of **wg-bridge**.
+//!
+//! It holds everything the `wgb` command line tool needs that is not tied to
+//! the terminal: the model of the `~/.wgbconf.json` configuration file, the
+//! WireGuard profile handling, the runtime state of the connections and the
+//! backends that bring tunnels up and down.
+
+pub mod auth;
+pub mod backend;
+pub mod catalog;
+pub mod config;
+pub mod discovery;
+pub mod encrypted;
+pub mod exec;
+mod file;
+pub mod ipc;
+pub mod log;
+pub mod migration;
+mod netlink;
+pub mod probe;
+pub mod profile;
+pub mod secret;
+pub mod state;
+pub mod status;
+pub mod totp;
@@ -0,0 +1,330 @@
+//! Logging of the tools, built on `tracing`.
+//!
+//! The events are written to a log file, rotated when it grows beyond a
+//! given size, and optionally to the standard error and the systemd journal.
+//! The events about a profile are recorded in spans carrying the `profile`
+//! and `interface` fields, which the journal keeps as `PROFILE` and
+//! `INTERFACE`.
+
+use std::env;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use tracing::level_filters::LevelFilter;
+use tracing::Subscriber;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer, Registry};
+
+/// Default log file.
+pub const LOG_FILE: &str = "/var/log/wg-bridge/wgb.log";
+
+/// Environment variable holding filter directives, such as
+/// `wgb_core=debug`, overriding the level.
+pub const LOG_ENV: &str = "WGB_LOG";
+
+/// Default size, in bytes, beyond which the log file is rotated.
+pub const DEFAULT_MAX_SIZE: u64 = 10 * 1024 * 1024;
+
+/// Default number of rotated log files kept.
+pub const DEFAULT_MAX_FILES: u32 = 5;
+
+/// Layout of the log lines.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Format {
+    /// One line of text per event.
+    #[default]
+    Text,
+    /// One JSON object per line, with the fields of the event and its spans.
+    Json,
+}
+
+impl Format {
+    pub const ALL: [Self; 2] = [Self::Text, Self::Json];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Text => "text",
+            Self::Json => "json",
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|format| format.name() == s)
+            .ok_or_else(|| format!("unknown log format '{s}'"))
+    }
+}
+
+/// Where and how the events are logged.
+#[derive(Debug, Clone)]
+pub struct Settings {
+    /// Most verbose level logged, unless [`LOG_ENV`] is set.
+    pub level: LevelFilter,
+    pub format: Format,
+    /// Log file; none to log no file.
+    pub file: Option<PathBuf>,
+    /// Size, in bytes, beyond which the log file is rotated.
+    pub max_size: u64,
+    /// Number of rotated files kept, `<file>.1` being the most recent.
+    pub max_files: u32,
+    /// Also log to the standard error.
+    pub stderr: bool,
+    /// Also log to the systemd journal.
+    pub journald: bool,
+    /// Name of the tool in the journal.
+    pub identifier: String,
+}
+
+impl Settings {
+    /// Returns the settings of `identifier` logging to [`LOG_FILE`] at the
+    /// `info` level.
+    pub fn new(identifier: &str) -> Self {
+        Self {
+            level: LevelFilter::INFO,
+            format: Format::Text,
+            file: Some(PathBuf::from(LOG_FILE)),
+            max_size: DEFAULT_MAX_SIZE,
+            max_files: DEFAULT_MAX_FILES,
+            stderr: false,
+            journald: false,
+            identifier: identifier.to_owned(),
+        }
+    }
+}
+
+/// Returns the level selected by `-v` given `verbose` times, or by `-q`:
+/// `info` by default, `debug` then `trace` with `-v`, `error` with `-q`.
+pub fn level(verbose: u8, quiet: bool) -> LevelFilter {
+    match (quiet, verbose) {
+        (true, _) => LevelFilter::ERROR,
+        (false, 0) => LevelFilter::INFO,
+        (false, 1) => LevelFilter::DEBUG,
+        (false, _) => LevelFilter::TRACE,
+    }
+}
+
+type BoxedLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+/// Installs the logger described by `settings` for the whole process.
+///
+/// Logging is best effort: a log file that cannot be opened or a missing
+/// journal only disable the matching output, and never make the tool fail.
+pub fn init(settings: &Settings) {
+    let mut layers: Vec<BoxedLayer> = Vec::new();
+    if let Some(path) = &settings.file {
+        if let Ok(file) = RotatingFile::open(path, settings.max_size, settings.max_files) {
+            layers.push(formatted(settings.format, Arc::new(file), false));
+        }
+    }
+    if settings.stderr {
+        let ansi = io::stderr().is_terminal();
+        layers.push(formatted(settings.format, io::stderr, ansi));
+    }
+    if settings.journald {
+        if let Ok(journald) = tracing_journald::layer() {
+            layers.push(Box::new(
+                journald
+                    .with_field_prefix(None)
+                    .with_syslog_identifier(settings.identifier.clone()),
+            ));
+        }
+    }
+    let filter = env::var(LOG_ENV)
+        .ok()
+        .and_then(|directives| EnvFilter::try_new(directives).ok())
+        .unwrap_or_else(|| EnvFilter::default().add_directive(settings.level.into()));
+    let _ = tracing_subscriber::registry()
+        .with(layers)
+        .with(filter)
+        .try_init();
+}
+
+/// Returns a layer writing the events to `writer` in `format`, colored if
+/// `ansi`.
+fn formatted<S, W>(format: Format, writer: W, ansi: bool) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> tracing_subscriber::fmt::MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = tracing_subscriber::fmt::layer()
+        .with_writer(writer)
+        .with_ansi(ansi)
+        .with_target(false);
+    match format {
+        Format::Text => Box::new(layer),
+        Format::Json => Box::new(layer.json().flatten_event(true)),
+    }
+}
+
+/// Log file renamed to `<path>.1` once it reaches its maximum size, the
+/// former `<path>.1` becoming `<path>.2` and so on.
+struct RotatingFile {
+    path: PathBuf,
+    max_size: u64,
+    max_files: u32,
+    current: Mutex<(File, u64)>,
+}
+
+impl RotatingFile {
+    fn open(path: &Path, max_size: u64, max_files: u32) -> io::Result<Self> {
+        let file = append(path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path: path.to_path_buf(),
+            max_size,
+            max_files,
+            current: Mutex::new((file, size)),
+        })
+    }
+
+    /// Shifts the rotated files and starts a new log file.
+    fn rotate(&self) -> io::Result<File> {
+        let rotated = |n: u32| crate::file::with_suffix(&self.path, &format!(".{n}"));
+        if self.max_files == 0 {
+            return File::create(&self.path);
+        }
+        let _ = fs::remove_file(rotated(self.max_files));
+        for n in (1..self.max_files).rev() {
+            let _ = fs::rename(rotated(n), rotated(n + 1));
+        }
+        fs::rename(&self.path, rotated(1))?;
+        append(&self.path)
+    }
+}
+
+impl Write for &RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut current = self.current.lock().unwrap_or_else(|e| e.into_inner());
+        let (file, size) = &mut *current;
+        if *size > 0 && *size + buf.len() as u64 > self.max_size {
+            // When the rotation fails, keep writing to the current file
+            // rather than losing the event.
+            if let Ok(new) = self.rotate() {
+                *file = new;
+                *size = 0;
+            }
+        }
+        let written = file.write(buf)?;
+        *size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let mut current = self.current.lock().unwrap_or_else(|e| e.into_inner());
+        current.0.flush()
+    }
+}
+
+fn append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+/// Returns the local time formatted as `%d-%m-%Y %H:%M:%S`.
+pub fn timestamp() -> String {
+    // SAFETY: `time` accepts a null pointer and `localtime_r` only writes
+    // into the zeroed `tm` owned by this frame.
+    let tm = unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        let mut tm: libc::tm = std::mem::zeroed();
+        libc::localtime_r(&now, &mut tm);
+        tm
+    };
+    format!(
+        "{:02}-{:02}-{:04} {:02}:{:02}:{:02}",
+        tm.tm_mday,
+        tm.tm_mon + 1,
+        tm.tm_year + 1900,
+        tm.tm_hour,
+        tm.tm_min,
+        tm.tm_sec
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes `lines` to a log file of 10 bytes at most in `dir`, keeping
+    /// `max_files` rotated files, and returns the content of the files, the
+    /// current one first.
+    fn write_lines(dir: &Path, max_files: u32, lines: &[&str]) -> Vec<String> {
+        let path = dir.join("wgb.log");
+        let log = RotatingFile::open(&path, 10, max_files).unwrap();
+        for line in lines {
+            (&log).write_all(line.as_bytes()).unwrap();
+        }
+        let mut files = vec![fs::read_to_string(&path).unwrap()];
+        for n in 1.. {
+            match fs::read_to_string(dir.join(format!("wgb.log.{n}"))) {
+                Ok(text) => files.push(text),
+                Err(_) => break,
+            }
+        }
+        files
+    }
+
+    #[test]
+    fn rotates_the_file_at_its_maximum_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = write_lines(dir.path(), 3, &["first\n", "two\n", "third\n"]);
+        assert_eq!(files, ["third\n", "first\ntwo\n"]);
+    }
+
+    #[test]
+    fn shifts_the_rotated_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = write_lines(dir.path(), 3, &["first\n", "second\n", "third\n"]);
+        assert_eq!(files, ["third\n", "second\n", "first\n"]);
+    }
+
+    #[test]
+    fn drops_the_files_beyond_the_maximum() {
+        let dir = tempfile::tempdir().unwrap();
+        let lines = ["first\n", "second\n", "third\n", "fourth\n"];
+        let files = write_lines(dir.path(), 2, &lines);
+        assert_eq!(files, ["fourth\n", "third\n", "second\n"]);
+        assert!(!dir.path().join("wgb.log.3").exists());
+    }
+
+    #[test]
+    fn truncates_the_file_when_no_rotated_file_is_kept() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = write_lines(dir.path(), 0, &["first\n", "second\n"]);
+        assert_eq!(files, ["second\n"]);
+        assert!(!dir.path().join("wgb.log.1").exists());
+    }
+
+    #[test]
+    fn keeps_an_event_larger_than_the_maximum() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = write_lines(dir.path(), 1, &["a long first event\n", "next\n"]);
+        assert_eq!(files, ["next\n", "a long first event\n"]);
+    }
+
+    #[test]
+    fn selects_the_level_of_the_options() {
+        assert_eq!(level(0, false), LevelFilter::INFO);
+        assert_eq!(level(1, false), LevelFilter::DEBUG);
+        assert_eq!(level(2, false), LevelFilter::TRACE);
+        assert_eq!(level(5, false), LevelFilter::TRACE);
+        assert_eq!(level(0, true), LevelFilter::ERROR);
+        assert_eq!(level(2, true), LevelFilter::ERROR);
+    }
+}
@@ -0,0 +1,263 @@
+//! Upgrades of configuration files written by older versions of the tool.
+//!
+//! The `version` key of `~/.wgbconf.json` tells which layout the file
+//! follows; files without it predate the key and are version 0. Each
+//! migration turns a file of one version into the next one, so a file of any
+//! older version is brought up to date by applying them in order. They work
+//! on the raw JSON value, as an older file may not deserialize into the
+//! current [`Config`](crate::config::Config).
+//!
+//! To change the layout, bump [`CURRENT_VERSION`] and append the migration
+//! from the previous version to [`MIGRATIONS`].
+
+use std::path::Path;
+
+use serde_json::{Map, Value};
+
+use crate::config::generate_id;
+
+/// Version of the configuration layout written by this build.
+pub const CURRENT_VERSION: u32 = 3;
+
+/// Name of the key holding the version of the layout.
+pub const VERSION_KEY: &str = "version";
+
+/// A migration, applied to the top-level object of the file.
+type Migration = fn(&mut Map<String, Value>);
+
+/// `MIGRATIONS[n]` upgrades a file of version `n` to version `n + 1`.
+const MIGRATIONS: [Migration; CURRENT_VERSION as usize] =
+    [drop_connected, totp_to_auth, assign_ids];
+
+/// Returns the version of the configuration held in `value`, 0 when it has
+/// no `version` key, or `None` when the key does not hold a version number.
+pub fn version_of(value: &Value) -> Option<u32> {
+    match value.get(VERSION_KEY) {
+        None => Some(0),
+        Some(version) => version.as_u64().and_then(|v| u32::try_from(v).ok()),
+    }
+}
+
+/// Brings the configuration held in `value` up to [`CURRENT_VERSION`].
+///
+/// Returns the version it had, or `None` when there was nothing to do: the
+/// value is already current, newer than this build or not an object.
+pub fn migrate(value: &mut Value) -> Option<u32> {
+    let from = version_of(value).filter(|&v| v < CURRENT_VERSION)?;
+    let object = value.as_object_mut()?;
+    for migration in &MIGRATIONS[from as usize..] {
+        migration(object);
+    }
+    object.insert(VERSION_KEY.to_owned(), CURRENT_VERSION.into());
+    Some(from)
+}
+
+/// 0 → 1: the connection status moved from `confs[].connected` to the state
+/// file.
+fn drop_connected(config: &mut Map<String, Value>) {
+    let Some(Value::Array(confs)) = config.get_mut("confs") else {
+        return;
+    };
+    for entry in confs.iter_mut().filter_map(Value::as_object_mut) {
+        entry.remove("connected");
+    }
+}
+
+/// 1 → 2: the TOTP settings, with the form field, moved from `confs[].totp`
+/// to a `form` authenticator in `confs[].auth`.
+fn totp_to_auth(config: &mut Map<String, Value>) {
+    let Some(Value::Array(confs)) = config.get_mut("confs") else {
+        return;
+    };
+    for entry in confs.iter_mut().filter_map(Value::as_object_mut) {
+        let Some(Value::Object(mut totp)) = entry.remove("totp") else {
+            continue;
+        };
+        let field = totp.remove("field").unwrap_or_else(|| "token".into());
+        let auth = serde_json::json!({
+            "method": "form",
+            "field": field,
+            "totp": totp,
+        });
+        entry.insert("auth".to_owned(), auth);
+    }
+}
+
+/// 2 → 3: the profiles got an identifier, `confs[].id`. The TOTP seeds,
+/// stored by path until then, move to it when they are next read, see
+/// [`Seeds::upgrade`](crate::totp::Seeds::upgrade).
+fn assign_ids(config: &mut Map<String, Value>) {
+    let Some(Value::Array(confs)) = config.get_mut("confs") else {
+        return;
+    };
+    for entry in confs.iter_mut().filter_map(Value::as_object_mut) {
+        if entry.contains_key("id") {
+            continue;
+        }
+        let path = entry
+            .get("path")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        entry.insert("id".to_owned(), generate_id(Path::new(path)).into());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::config::Config;
+
+    /// A file of version 0, before the `version` key.
+    const V0: &str = r#"{
+        "conf_path": ["/home/user/vpn"],
+        "confs": [
+            {
+                "path": "/etc/wireguard/office.conf",
+                "token": true,
+                "uri": "https://vpn.example.com/2fa",
+                "connected": true,
+                "totp": {"algorithm": "SHA256", "digits": 8, "field": "pin"}
+            },
+            {"path": "/home/user/vpn/home.conf", "token": false, "uri": "", "connected": false}
+        ],
+        "error_codes": {"000": "Missing wgb configuration"}
+    }"#;
+
+    /// The same configuration in version 1, without the connection status.
+    const V1: &str = r#"{
+        "version": 1,
+        "conf_path": ["/home/user/vpn"],
+        "confs": [
+            {
+                "path": "/etc/wireguard/office.conf",
+                "token": true,
+                "uri": "https://vpn.example.com/2fa",
+                "totp": {"algorithm": "SHA256", "digits": 8, "field": "pin"}
+            },
+            {"path": "/home/user/vpn/home.conf", "token": false, "uri": ""}
+        ],
+        "error_codes": {"000": "Missing wgb configuration"}
+    }"#;
+
+    /// The same configuration in version 2, with the TOTP settings in `auth`.
+    const V2: &str = r#"{
+        "version": 2,
+        "conf_path": ["/home/user/vpn"],
+        "confs": [
+            {
+                "path": "/etc/wireguard/office.conf",
+                "token": true,
+                "uri": "https://vpn.example.com/2fa",
+                "auth": {"method": "form", "field": "pin", "totp": {"algorithm": "SHA256", "digits": 8}}
+            },
+            {"path": "/home/user/vpn/home.conf", "token": false, "uri": ""}
+        ],
+        "error_codes": {"000": "Missing wgb configuration"}
+    }"#;
+
+    /// The configuration in the current version, without the generated IDs.
+    fn current() -> Value {
+        json!({
+            "version": CURRENT_VERSION,
+            "conf_path": ["/home/user/vpn"],
+            "confs": [
+                {
+                    "path": "/etc/wireguard/office.conf",
+                    "token": true,
+                    "uri": "https://vpn.example.com/2fa",
+                    "auth": {
+                        "method": "form",
+                        "field": "pin",
+                        "totp": {"algorithm": "SHA256", "digits": 8, "period": 30}
+                    }
+                },
+                {"path": "/home/user/vpn/home.conf", "token": false, "uri": ""}
+            ],
+            "error_codes": {"000": "Missing wgb configuration"}
+        })
+    }
+
+    /// Removes the IDs of the profiles from `value`, checking their format,
+    /// and returns them.
+    fn take_ids(value: &mut Value) -> Vec<String> {
+        value["confs"]
+            .as_array_mut()
+            .unwrap()
+            .iter_mut()
+            .map(|entry| {
+                let id = entry.as_object_mut().unwrap().remove("id").unwrap();
+                let id = id.as_str().unwrap().to_owned();
+                assert_eq!(id.len(), 12);
+                assert!(id.bytes().all(|b| b.is_ascii_hexdigit()));
+                id
+            })
+            .collect()
+    }
+
+    fn read(path: &Path) -> Value {
+        serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn upgrades_every_version_to_the_current_one() {
+        for (version, text) in [(0, V0), (1, V1), (2, V2)] {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join(".wgbconf.json");
+            fs::write(&path, text).unwrap();
+
+            let config = Config::load(&path).unwrap();
+            assert_eq!(config.version, CURRENT_VERSION);
+            let mut written = read(&path);
+            let ids = take_ids(&mut written);
+            assert_eq!(written, current(), "from version {version}");
+            assert_ne!(ids[0], ids[1]);
+            let loaded: Vec<&str> = config.confs.iter().map(|e| e.id.as_str()).collect();
+            assert_eq!(loaded, ids);
+
+            let backup = Config::backup_path(&path, version);
+            assert_eq!(fs::read_to_string(&backup).unwrap(), text);
+        }
+    }
+
+    #[test]
+    fn upgrading_twice_changes_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".wgbconf.json");
+        fs::write(&path, V0).unwrap();
+
+        let first = Config::load(&path).unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        let second = Config::load(&path).unwrap();
+        assert_eq!(first, second);
+        assert_eq!(fs::read_to_string(&path).unwrap(), text);
+        let mut files: Vec<_> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        files.sort();
+        assert_eq!(
+            files,
+            [
+                ".wgbconf.json",
+                ".wgbconf.json.lock",
+                ".wgbconf.json.v0.bak"
+            ]
+        );
+
+        let mut value: Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(migrate(&mut value), None);
+    }
+
+    #[test]
+    fn leaves_newer_and_invalid_versions_alone() {
+        let mut newer = json!({ "version": CURRENT_VERSION + 1, "confs": [] });
+        assert_eq!(migrate(&mut newer), None);
+        assert_eq!(newer["version"], CURRENT_VERSION + 1);
+        let mut invalid = json!({ "version": "two" });
+        assert_eq!(migrate(&mut invalid), None);
+    }
+}
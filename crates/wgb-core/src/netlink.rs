@@ -0,0 +1,368 @@
+//! Minimal netlink client used by the native tunnel backend.
+//!
+//! It only covers what the backend needs: building requests made of a fixed
+//! header followed by attributes, sending them and walking the attributes of
+//! the replies.
+
+use std::io;
+use std::mem;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+/// Size of `struct nlmsghdr`.
+const HEADER_LEN: usize = 16;
+/// Size of `struct nlattr`.
+const ATTR_HEADER_LEN: usize = 4;
+
+/// Rounds `len` up to the netlink alignment.
+fn align(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+/// A netlink request being built.
+#[derive(Debug)]
+pub struct Message {
+    buf: Vec<u8>,
+    nests: Vec<usize>,
+}
+
+impl Message {
+    /// Starts a request of type `ty`. `NLM_F_REQUEST` is always set.
+    pub fn new(ty: u16, flags: u16) -> Self {
+        let mut buf = vec![0; HEADER_LEN];
+        buf[4..6].copy_from_slice(&ty.to_ne_bytes());
+        buf[6..8].copy_from_slice(&(flags | libc::NLM_F_REQUEST as u16).to_ne_bytes());
+        Self {
+            buf,
+            nests: Vec::new(),
+        }
+    }
+
+    /// Appends a fixed header, such as `struct ifinfomsg` or
+    /// `struct genlmsghdr`.
+    pub fn header(mut self, bytes: &[u8]) -> Self {
+        self.put(bytes);
+        self
+    }
+
+    /// Appends an attribute.
+    pub fn attr(mut self, ty: u16, payload: &[u8]) -> Self {
+        let len = (ATTR_HEADER_LEN + payload.len()) as u16;
+        self.buf.extend_from_slice(&len.to_ne_bytes());
+        self.buf.extend_from_slice(&ty.to_ne_bytes());
+        self.put(payload);
+        self
+    }
+
+    pub fn attr_u8(self, ty: u16, value: u8) -> Self {
+        self.attr(ty, &[value])
+    }
+
+    pub fn attr_u16(self, ty: u16, value: u16) -> Self {
+        self.attr(ty, &value.to_ne_bytes())
+    }
+
+    pub fn attr_u32(self, ty: u16, value: u32) -> Self {
+        self.attr(ty, &value.to_ne_bytes())
+    }
+
+    /// Appends a null terminated string attribute.
+    pub fn attr_str(self, ty: u16, value: &str) -> Self {
+        let mut payload = value.as_bytes().to_vec();
+        payload.push(0);
+        self.attr(ty, &payload)
+    }
+
+    /// Opens a nested attribute, closed by [`Message::end_nested`].
+    pub fn begin_nested(mut self, ty: u16) -> Self {
+        self.nests.push(self.buf.len());
+        self.buf.extend_from_slice(&0u16.to_ne_bytes());
+        self.buf
+            .extend_from_slice(&(ty | libc::NLA_F_NESTED as u16).to_ne_bytes());
+        self
+    }
+
+    /// Closes the innermost nested attribute.
+    pub fn end_nested(mut self) -> Self {
+        let start = self.nests.pop().expect("end_nested without begin_nested");
+        let len = (self.buf.len() - start) as u16;
+        self.buf[start..start + 2].copy_from_slice(&len.to_ne_bytes());
+        self
+    }
+
+    fn put(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+        self.buf.resize(align(self.buf.len()), 0);
+    }
+
+    fn finish(mut self, seq: u32) -> Vec<u8> {
+        assert!(self.nests.is_empty(), "unterminated nested attribute");
+        let len = self.buf.len() as u32;
+        self.buf[0..4].copy_from_slice(&len.to_ne_bytes());
+        self.buf[8..12].copy_from_slice(&seq.to_ne_bytes());
+        self.buf
+    }
+
+    /// Returns the request after the netlink header.
+    #[cfg(test)]
+    pub fn payload(&self) -> &[u8] {
+        &self.buf[HEADER_LEN..]
+    }
+
+    fn flags(&self) -> u16 {
+        u16::from_ne_bytes([self.buf[6], self.buf[7]])
+    }
+}
+
+/// A netlink socket bound to one protocol.
+#[derive(Debug)]
+pub struct Socket {
+    fd: OwnedFd,
+    seq: u32,
+}
+
+impl Socket {
+    /// Opens a socket for `protocol`, e.g. `NETLINK_ROUTE`.
+    pub fn open(protocol: libc::c_int) -> io::Result<Self> {
+        // SAFETY: plain system calls; the descriptor is owned right after
+        // creation and `addr` is a zeroed `sockaddr_nl` of the right size.
+        unsafe {
+            let fd = libc::socket(
+                libc::AF_NETLINK,
+                libc::SOCK_RAW | libc::SOCK_CLOEXEC,
+                protocol,
+            );
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let fd = OwnedFd::from_raw_fd(fd);
+            let mut addr: libc::sockaddr_nl = mem::zeroed();
+            addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+            let rc = libc::bind(
+                fd.as_raw_fd(),
+                &addr as *const _ as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+            );
+            if rc < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Self { fd, seq: 0 })
+        }
+    }
+
+    /// Sends `msg` and returns the payload of every reply.
+    ///
+    /// Requests without `NLM_F_DUMP` are sent with `NLM_F_ACK`, so a kernel
+    /// error is always reported as an [`io::Error`].
+    pub fn request(&mut self, msg: Message) -> io::Result<Vec<Vec<u8>>> {
+        let dump = msg.flags() & libc::NLM_F_DUMP as u16 == libc::NLM_F_DUMP as u16;
+        let msg = if dump {
+            msg
+        } else {
+            let flags = msg.flags() | libc::NLM_F_ACK as u16;
+            let mut msg = msg;
+            msg.buf[6..8].copy_from_slice(&flags.to_ne_bytes());
+            msg
+        };
+        self.seq = self.seq.wrapping_add(1);
+        let seq = self.seq;
+        let buf = msg.finish(seq);
+
+        // SAFETY: `buf` is a valid buffer of the given length.
+        let sent = unsafe { libc::send(self.fd.as_raw_fd(), buf.as_ptr().cast(), buf.len(), 0) };
+        if sent < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut replies = Vec::new();
+        let mut recv = vec![0u8; 64 * 1024];
+        loop {
+            // SAFETY: `recv` is a valid, writable buffer of the given length.
+            let len =
+                unsafe { libc::recv(self.fd.as_raw_fd(), recv.as_mut_ptr().cast(), recv.len(), 0) };
+            if len < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            if read_replies(&recv[..len as usize], seq, &mut replies)? {
+                return Ok(replies);
+            }
+        }
+    }
+}
+
+/// Appends to `replies` the payloads of the messages in `data` answering
+/// the request `seq`. Returns whether the answer is complete: the dump is
+/// done or the request acknowledged.
+fn read_replies(mut data: &[u8], seq: u32, replies: &mut Vec<Vec<u8>>) -> io::Result<bool> {
+    let truncated = || io::Error::new(io::ErrorKind::InvalidData, "truncated netlink message");
+    while data.len() >= HEADER_LEN {
+        let msg_len = read_u32(data).ok_or_else(truncated)? as usize;
+        let ty = data.get(4..).and_then(read_u16).ok_or_else(truncated)?;
+        let msg_seq = data.get(8..).and_then(read_u32).ok_or_else(truncated)?;
+        if msg_len < HEADER_LEN || msg_len > data.len() {
+            return Err(truncated());
+        }
+        let payload = &data[HEADER_LEN..msg_len];
+        data = &data[align(msg_len).min(data.len())..];
+        if msg_seq != seq {
+            continue;
+        }
+        match ty as libc::c_int {
+            libc::NLMSG_DONE => return Ok(true),
+            libc::NLMSG_ERROR => {
+                let code = read_u32(payload).ok_or_else(truncated)? as i32;
+                if code != 0 {
+                    return Err(io::Error::from_raw_os_error(-code));
+                }
+                return Ok(true);
+            }
+            // Without NLM_F_DUMP, the acknowledgement follows.
+            _ => replies.push(payload.to_vec()),
+        }
+    }
+    Ok(false)
+}
+
+/// Iterator over the attributes of a netlink payload.
+#[derive(Debug, Clone)]
+pub struct Attrs<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Attrs<'a> {
+    /// Walks the attributes in `payload`, skipping a fixed header of
+    /// `header_len` bytes.
+    pub fn new(payload: &'a [u8], header_len: usize) -> Self {
+        Self {
+            data: payload.get(align(header_len)..).unwrap_or_default(),
+        }
+    }
+}
+
+impl<'a> Iterator for Attrs<'a> {
+    /// Attribute type, without the nested and byte order flags, and payload.
+    type Item = (u16, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.len() < ATTR_HEADER_LEN {
+            return None;
+        }
+        let len = u16::from_ne_bytes([self.data[0], self.data[1]]) as usize;
+        let ty = u16::from_ne_bytes([self.data[2], self.data[3]]) & libc::NLA_TYPE_MASK as u16;
+        if len < ATTR_HEADER_LEN || len > self.data.len() {
+            self.data = &[];
+            return None;
+        }
+        let payload = &self.data[ATTR_HEADER_LEN..len];
+        self.data = &self.data[align(len).min(self.data.len())..];
+        Some((ty, payload))
+    }
+}
+
+/// Reads a native endian `u16` attribute.
+pub fn read_u16(payload: &[u8]) -> Option<u16> {
+    Some(u16::from_ne_bytes(payload.get(..2)?.try_into().ok()?))
+}
+
+/// Reads a native endian `u32` attribute.
+pub fn read_u32(payload: &[u8]) -> Option<u32> {
+    Some(u32::from_ne_bytes(payload.get(..4)?.try_into().ok()?))
+}
+
+/// Reads a native endian `u64` attribute.
+pub fn read_u64(payload: &[u8]) -> Option<u64> {
+    Some(u64::from_ne_bytes(payload.get(..8)?.try_into().ok()?))
+}
+
+/// Reads a null terminated string attribute.
+pub fn read_str(payload: &[u8]) -> Option<&str> {
+    let end = payload
+        .iter()
+        .position(|b| *b == 0)
+        .unwrap_or(payload.len());
+    std::str::from_utf8(&payload[..end]).ok()
+}
+
+/// Resolves the identifier of the generic netlink family `name`.
+pub fn resolve_family(name: &str) -> io::Result<u16> {
+    let mut socket = Socket::open(libc::NETLINK_GENERIC)?;
+    let msg = Message::new(libc::GENL_ID_CTRL as u16, 0)
+        .header(&[libc::CTRL_CMD_GETFAMILY as u8, 1, 0, 0])
+        .attr_str(libc::CTRL_ATTR_FAMILY_NAME as u16, name);
+    socket
+        .request(msg)?
+        .iter()
+        .flat_map(|reply| Attrs::new(reply, 4))
+        .find(|(ty, _)| *ty == libc::CTRL_ATTR_FAMILY_ID as u16)
+        .and_then(|(_, payload)| read_u16(payload))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("no {name} netlink family")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Encodes a netlink message of type `ty` answering the request `seq`.
+    fn message(ty: libc::c_int, seq: u32, payload: &[u8]) -> Vec<u8> {
+        let mut buf = vec![0; HEADER_LEN];
+        buf[0..4].copy_from_slice(&((HEADER_LEN + payload.len()) as u32).to_ne_bytes());
+        buf[4..6].copy_from_slice(&(ty as u16).to_ne_bytes());
+        buf[8..12].copy_from_slice(&seq.to_ne_bytes());
+        buf.extend_from_slice(payload);
+        buf.resize(align(buf.len()), 0);
+        buf
+    }
+
+    #[test]
+    fn reads_the_replies_up_to_the_end_of_the_dump() {
+        let mut data = message(libc::RTM_NEWLINK as _, 7, b"first");
+        data.extend(message(libc::RTM_NEWLINK as _, 6, b"stale"));
+        data.extend(message(libc::RTM_NEWLINK as _, 7, b"second"));
+        let mut replies = Vec::new();
+        assert!(!read_replies(&data, 7, &mut replies).unwrap());
+        assert!(read_replies(&message(libc::NLMSG_DONE, 7, &[0; 4]), 7, &mut replies).unwrap());
+        assert_eq!(replies, [b"first".to_vec(), b"second".to_vec()]);
+    }
+
+    #[test]
+    fn reports_the_error_of_the_kernel() {
+        let mut replies = Vec::new();
+        let ack = message(libc::NLMSG_ERROR, 3, &0i32.to_ne_bytes());
+        assert!(read_replies(&ack, 3, &mut replies).unwrap());
+
+        let error = message(libc::NLMSG_ERROR, 3, &(-libc::EEXIST).to_ne_bytes());
+        let err = read_replies(&error, 3, &mut replies).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EEXIST));
+        assert!(replies.is_empty());
+    }
+
+    #[test]
+    fn refuses_truncated_messages() {
+        let mut replies = Vec::new();
+        let error = message(libc::NLMSG_ERROR, 3, &[]);
+        let err = read_replies(&error, 3, &mut replies).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let mut long = message(libc::RTM_NEWLINK as _, 3, b"payload");
+        long[0..4].copy_from_slice(&64u32.to_ne_bytes());
+        let err = read_replies(&long, 3, &mut replies).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn walks_the_attributes_of_a_message() {
+        let msg = Message::new(libc::RTM_NEWLINK, 0)
+            .header(&[1, 2, 3])
+            .attr_u32(1, 51820)
+            .begin_nested(2)
+            .attr_str(3, "wg0")
+            .end_nested()
+            .attr_u16(4, 7);
+        let attrs: Vec<(u16, &[u8])> = Attrs::new(msg.payload(), 3).collect();
+        assert_eq!(attrs.len(), 3);
+        assert_eq!((attrs[0].0, read_u32(attrs[0].1)), (1, Some(51820)));
+        let nested: Vec<(u16, &[u8])> = Attrs::new(attrs[1].1, 0).collect();
+        assert_eq!((attrs[1].0, nested[0].0), (2, 3));
+        assert_eq!(read_str(nested[0].1), Some("wg0"));
+        assert_eq!((attrs[2].0, read_u16(attrs[2].1)), (4, Some(7)));
+    }
+}
@@ -0,0 +1,67 @@
+//! Checks that traffic flows through a tunnel once its 2FA step is done.
+//!
+//! A token-protected tunnel is up as soon as the interface is configured,
+//! but the server only lets traffic through once the PIN is accepted. The
+//! tool polls either the latest handshake of the peers or a command given in
+//! `confs[].probe` until one of them tells the tunnel works.
+
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use tracing::warn;
+
+use crate::backend::Device;
+
+/// Time given to the 2FA authorization when none is configured, in seconds.
+pub const DEFAULT_TIMEOUT: u64 = 120;
+
+/// Returns whether a peer of `interface` completed a handshake since
+/// `since`.
+pub fn handshake_since(devices: &[Device], interface: &str, since: SystemTime) -> bool {
+    // Handshake times only have a precision of a second.
+    let since = since
+        .duration_since(UNIX_EPOCH)
+        .map(|d| UNIX_EPOCH + Duration::from_secs(d.as_secs()))
+        .unwrap_or(since);
+    devices
+        .iter()
+        .filter(|device| device.name == interface)
+        .flat_map(|device| &device.peers)
+        .any(|peer| peer.latest_handshake.is_some_and(|time| time >= since))
+}
+
+/// Runs `command` with `sh -c` and returns whether it succeeded; it is
+/// killed and counts as a failure when it lasts more than `timeout`.
+pub fn run_command(command: &str, timeout: Duration) -> bool {
+    let spawned = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+    let mut child = match spawned {
+        Ok(child) => child,
+        Err(err) => {
+            warn!("probe '{command}': {err}");
+            return false;
+        }
+    };
+    let deadline = Instant::now() + timeout;
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => return status.success(),
+            Ok(None) if Instant::now() < deadline => thread::sleep(Duration::from_millis(100)),
+            Ok(None) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return false;
+            }
+            Err(err) => {
+                warn!("probe '{command}': {err}");
+                return false;
+            }
+        }
+    }
+}
@@ -0,0 +1,860 @@
+//! Parser and serializer for WireGuard profiles in the `wg-quick` format.
+//!
+//! A [`Profile`] keeps every line of the original file, comments and keys it
+//! does not know about included, so it can be edited and written back without
+//! losing anything. Typed accessors ([`Profile::interface`],
+//! [`Profile::peers`]) expose the values the tool cares about.
+
+use std::fmt;
+use std::io;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+
+use crate::encrypted::{self, EncryptionError};
+use crate::{exec, file};
+
+/// Errors raised while reading, parsing or editing a profile.
+#[derive(Debug, Error)]
+pub enum ProfileError {
+    /// Reading or writing the file failed.
+    #[error("unable to access '{}': {source}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    /// The file is not a valid `wg-quick` configuration.
+    #[error("{}:{line}: {message}", path.display())]
+    Syntax {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+
+    /// The file has no `[Interface]` section.
+    #[error("{}: missing [Interface] section", path.display())]
+    MissingInterface { path: PathBuf },
+
+    /// The encrypted profile could not be decrypted.
+    #[error(transparent)]
+    Encryption(#[from] EncryptionError),
+
+    /// The profile is encrypted and cannot be written back as is.
+    #[error("'{}' is encrypted, decrypt it first with: wgb profile decrypt", .0.display())]
+    Encrypted(PathBuf),
+
+    /// A value does not match the format expected for its key.
+    #[error("invalid value '{value}' for {key}: {reason}")]
+    InvalidValue {
+        key: String,
+        value: String,
+        reason: String,
+    },
+}
+
+impl ProfileError {
+    /// Returns the error without the content of the file it quotes, for the
+    /// daemon to report to users who may not be able to read the file.
+    pub fn redact(self) -> Self {
+        match self {
+            Self::Syntax { path, line, .. } => Self::Syntax {
+                path,
+                line,
+                message: "invalid line".to_owned(),
+            },
+            Self::InvalidValue { key, reason, .. } => Self::InvalidValue {
+                key,
+                value: "...".to_owned(),
+                reason,
+            },
+            err => err,
+        }
+    }
+}
+
+/// An IP address with its prefix length, as used by `Address` and
+/// `AllowedIPs`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Cidr {
+    pub addr: IpAddr,
+    pub prefix: u8,
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+        let addr: IpAddr = addr
+            .trim()
+            .parse()
+            .map_err(|_| format!("'{addr}' is not an IP address"))?;
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .trim()
+                .parse()
+                .ok()
+                .filter(|p| *p <= max)
+                .ok_or_else(|| format!("'{prefix}' is not a prefix length"))?,
+            None => max,
+        };
+        Ok(Self { addr, prefix })
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+impl Serialize for Cidr {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Cidr {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// Kind of a section of the profile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SectionKind {
+    Interface,
+    Peer,
+    /// A section `wg-quick` does not know; kept as is.
+    Other(String),
+}
+
+/// A single line of the profile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Line {
+    /// Blank line or comment.
+    Text(String),
+    /// `Key = Value` pair. `raw` is the original text, dropped when the
+    /// value is changed.
+    Entry {
+        key: String,
+        value: String,
+        raw: Option<String>,
+    },
+}
+
+/// A `[Section]` of the profile with the lines that follow its header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    kind: SectionKind,
+    header: String,
+    lines: Vec<Line>,
+}
+
+impl Section {
+    /// Creates an empty section of the given kind.
+    pub fn new(kind: SectionKind) -> Self {
+        let header = match &kind {
+            SectionKind::Interface => "[Interface]".to_owned(),
+            SectionKind::Peer => "[Peer]".to_owned(),
+            SectionKind::Other(name) => format!("[{name}]"),
+        };
+        Self {
+            kind,
+            header,
+            lines: Vec::new(),
+        }
+    }
+
+    /// Returns the kind of the section.
+    pub fn kind(&self) -> &SectionKind {
+        &self.kind
+    }
+
+    /// Returns the first value of `key`. Keys are case insensitive.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v)
+    }
+
+    /// Returns every value of `key`, in file order.
+    pub fn get_all<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.entries()
+            .filter(move |(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v)
+    }
+
+    /// Returns every `(key, value)` pair of the section, in file order.
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.lines.iter().filter_map(|line| match line {
+            Line::Entry { key, value, .. } => Some((key.as_str(), value.as_str())),
+            Line::Text(_) => None,
+        })
+    }
+
+    /// Sets `key` to `value`.
+    ///
+    /// The first occurrence of the key is replaced and any further one is
+    /// removed; if the key is missing it is added after the last entry.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), ProfileError> {
+        check_value(&self.kind, key, value)?;
+        let mut found = false;
+        self.lines.retain_mut(|line| match line {
+            Line::Entry { key: k, .. } if k.eq_ignore_ascii_case(key) && found => false,
+            Line::Entry {
+                key: k,
+                value: v,
+                raw,
+            } if k.eq_ignore_ascii_case(key) => {
+                found = true;
+                *v = value.to_owned();
+                *raw = None;
+                true
+            }
+            _ => true,
+        });
+        if !found {
+            self.append(key, value)?;
+        }
+        Ok(())
+    }
+
+    /// Adds a further occurrence of `key`, for keys that may be repeated
+    /// such as `Address`, `AllowedIPs` or `PostUp`.
+    pub fn append(&mut self, key: &str, value: &str) -> Result<(), ProfileError> {
+        check_value(&self.kind, key, value)?;
+        let at = self
+            .lines
+            .iter()
+            .rposition(|line| matches!(line, Line::Entry { .. }))
+            .map_or(0, |i| i + 1);
+        self.lines.insert(
+            at,
+            Line::Entry {
+                key: key.to_owned(),
+                value: value.to_owned(),
+                raw: None,
+            },
+        );
+        Ok(())
+    }
+
+    /// Removes every occurrence of `key`. Returns whether any was found.
+    pub fn remove(&mut self, key: &str) -> bool {
+        let before = self.lines.len();
+        self.lines.retain(
+            |line| !matches!(line, Line::Entry { key: k, .. } if k.eq_ignore_ascii_case(key)),
+        );
+        before != self.lines.len()
+    }
+}
+
+/// Typed view of the `[Interface]` section.
+#[derive(Debug, Clone, Copy)]
+pub struct Interface<'a>(&'a Section);
+
+impl<'a> Interface<'a> {
+    /// Returns the underlying section.
+    pub fn section(&self) -> &'a Section {
+        self.0
+    }
+
+    pub fn private_key(&self) -> Option<&'a str> {
+        self.0.get("PrivateKey")
+    }
+
+    pub fn listen_port(&self) -> Option<u16> {
+        self.0.get("ListenPort").and_then(|v| v.parse().ok())
+    }
+
+    pub fn fwmark(&self) -> Option<&'a str> {
+        self.0.get("FwMark")
+    }
+
+    pub fn addresses(&self) -> Vec<Cidr> {
+        parse_list(self.0, "Address")
+    }
+
+    /// DNS servers and search domains, as written in the file.
+    pub fn dns(&self) -> Vec<&'a str> {
+        split_list(self.0, "DNS")
+    }
+
+    pub fn mtu(&self) -> Option<u16> {
+        self.0.get("MTU").and_then(|v| v.parse().ok())
+    }
+
+    /// Routing table, `off`, `auto` or a table name or number.
+    pub fn table(&self) -> Option<&'a str> {
+        self.0.get("Table")
+    }
+
+    pub fn pre_up(&self) -> Vec<&'a str> {
+        self.0.get_all("PreUp").collect()
+    }
+
+    pub fn post_up(&self) -> Vec<&'a str> {
+        self.0.get_all("PostUp").collect()
+    }
+
+    pub fn pre_down(&self) -> Vec<&'a str> {
+        self.0.get_all("PreDown").collect()
+    }
+
+    pub fn post_down(&self) -> Vec<&'a str> {
+        self.0.get_all("PostDown").collect()
+    }
+
+    pub fn save_config(&self) -> bool {
+        self.0
+            .get("SaveConfig")
+            .is_some_and(|v| v.eq_ignore_ascii_case("true"))
+    }
+}
+
+/// Typed view of a `[Peer]` section.
+#[derive(Debug, Clone, Copy)]
+pub struct Peer<'a>(&'a Section);
+
+impl<'a> Peer<'a> {
+    /// Returns the underlying section.
+    pub fn section(&self) -> &'a Section {
+        self.0
+    }
+
+    pub fn public_key(&self) -> Option<&'a str> {
+        self.0.get("PublicKey")
+    }
+
+    pub fn preshared_key(&self) -> Option<&'a str> {
+        self.0.get("PresharedKey")
+    }
+
+    pub fn allowed_ips(&self) -> Vec<Cidr> {
+        parse_list(self.0, "AllowedIPs")
+    }
+
+    /// Endpoint as `host:port`, where host may be a name or an address.
+    pub fn endpoint(&self) -> Option<&'a str> {
+        self.0.get("Endpoint")
+    }
+
+    /// Keepalive interval in seconds; `None` when missing or `off`.
+    pub fn persistent_keepalive(&self) -> Option<u16> {
+        self.0
+            .get("PersistentKeepalive")
+            .and_then(|v| v.parse().ok())
+            .filter(|v| *v != 0)
+    }
+}
+
+/// A WireGuard profile in the `wg-quick` format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profile {
+    /// Lines before the first section header.
+    preamble: Vec<Line>,
+    sections: Vec<Section>,
+    /// Whether the lines end with `\r\n`, as in the first line of the file.
+    crlf: bool,
+    /// Whether the last line ends with a line break.
+    final_newline: bool,
+}
+
+impl Profile {
+    /// Reads and parses the profile at `path`.
+    ///
+    /// Profiles in directories only root can read, such as
+    /// `/etc/wireguard`, are read with root privileges, see
+    /// [`exec::read_to_string`]. Encrypted profiles,
+    /// `*.conf.age`, are decrypted in memory with the identity of the user.
+    pub fn load(path: &Path) -> Result<Self, ProfileError> {
+        if encrypted::is_encrypted(path) {
+            return Self::parse(encrypted::decrypt(path)?.as_str(), path);
+        }
+        let text = exec::read_to_string(path).map_err(|source| ProfileError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Self::parse(&text, path)
+    }
+
+    /// Parses `text` as the content of the profile at `path`.
+    ///
+    /// `path` is only used to build error messages.
+    pub fn parse(text: &str, path: &Path) -> Result<Self, ProfileError> {
+        let syntax = |line: usize, message: String| ProfileError::Syntax {
+            path: path.to_path_buf(),
+            line,
+            message,
+        };
+        let mut preamble = Vec::new();
+        let mut sections: Vec<Section> = Vec::new();
+
+        for (idx, raw) in text.lines().enumerate() {
+            let number = idx + 1;
+            // Like wg-quick, everything after '#' is a comment.
+            let content = raw.split('#').next().unwrap_or_default().trim();
+
+            if content.is_empty() {
+                let line = Line::Text(raw.to_owned());
+                match sections.last_mut() {
+                    Some(section) => section.lines.push(line),
+                    None => preamble.push(line),
+                }
+                continue;
+            }
+
+            if let Some(name) = content.strip_prefix('[') {
+                let name = name
+                    .strip_suffix(']')
+                    .ok_or_else(|| {
+                        syntax(number, format!("unterminated section header '{content}'"))
+                    })?
+                    .trim();
+                let kind = if name.eq_ignore_ascii_case("Interface") {
+                    if sections.iter().any(|s| s.kind == SectionKind::Interface) {
+                        return Err(syntax(number, "duplicate [Interface] section".to_owned()));
+                    }
+                    SectionKind::Interface
+                } else if name.eq_ignore_ascii_case("Peer") {
+                    SectionKind::Peer
+                } else {
+                    SectionKind::Other(name.to_owned())
+                };
+                sections.push(Section {
+                    kind,
+                    header: raw.to_owned(),
+                    lines: Vec::new(),
+                });
+                continue;
+            }
+
+            let (key, value) = content.split_once('=').ok_or_else(|| {
+                syntax(number, format!("expected 'Key = Value', found '{content}'"))
+            })?;
+            let (key, value) = (key.trim(), value.trim());
+            if key.is_empty() {
+                return Err(syntax(number, "missing key before '='".to_owned()));
+            }
+            let section = sections
+                .last_mut()
+                .ok_or_else(|| syntax(number, format!("'{key}' is outside of any section")))?;
+            check_value(&section.kind, key, value)
+                .map_err(|err| syntax(number, err.to_string()))?;
+            section.lines.push(Line::Entry {
+                key: key.to_owned(),
+                value: value.to_owned(),
+                raw: Some(raw.to_owned()),
+            });
+        }
+
+        if !sections.iter().any(|s| s.kind == SectionKind::Interface) {
+            return Err(ProfileError::MissingInterface {
+                path: path.to_path_buf(),
+            });
+        }
+        Ok(Self {
+            preamble,
+            sections,
+            crlf: text
+                .split_once('\n')
+                .is_some_and(|(first, _)| first.ends_with('\r')),
+            final_newline: text.ends_with('\n'),
+        })
+    }
+
+    /// Writes the profile to `path`, readable by its owner only since it
+    /// holds private keys.
+    pub fn save(&self, path: &Path) -> Result<(), ProfileError> {
+        if encrypted::is_encrypted(path) {
+            return Err(ProfileError::Encrypted(path.to_path_buf()));
+        }
+        file::write_atomic(path, self.to_string().as_bytes(), 0o600).map_err(|source| {
+            ProfileError::Io {
+                path: path.to_path_buf(),
+                source,
+            }
+        })
+    }
+
+    /// Returns the `[Interface]` section.
+    pub fn interface(&self) -> Interface<'_> {
+        Interface(self.interface_section())
+    }
+
+    /// Returns the `[Interface]` section for modification.
+    pub fn interface_mut(&mut self) -> &mut Section {
+        self.sections
+            .iter_mut()
+            .find(|s| s.kind == SectionKind::Interface)
+            .expect("a parsed profile always has an [Interface] section")
+    }
+
+    /// Returns the `[Peer]` sections, in file order.
+    pub fn peers(&self) -> impl Iterator<Item = Peer<'_>> {
+        self.sections
+            .iter()
+            .filter(|s| s.kind == SectionKind::Peer)
+            .map(Peer)
+    }
+
+    /// Returns the `[Peer]` section with the given public key for
+    /// modification.
+    pub fn peer_mut(&mut self, public_key: &str) -> Option<&mut Section> {
+        self.sections
+            .iter_mut()
+            .find(|s| s.kind == SectionKind::Peer && s.get("PublicKey") == Some(public_key))
+    }
+
+    /// Appends a new section and returns it.
+    pub fn add_section(&mut self, kind: SectionKind) -> &mut Section {
+        self.sections.push(Section::new(kind));
+        self.sections.last_mut().expect("section just pushed")
+    }
+
+    /// Returns every section, in file order.
+    pub fn sections(&self) -> &[Section] {
+        &self.sections
+    }
+
+    /// Returns every section, in file order, for modification.
+    pub fn sections_mut(&mut self) -> &mut [Section] {
+        &mut self.sections
+    }
+
+    fn interface_section(&self) -> &Section {
+        self.sections
+            .iter()
+            .find(|s| s.kind == SectionKind::Interface)
+            .expect("a parsed profile always has an [Interface] section")
+    }
+}
+
+/// Writes the lines with the line endings of the original text.
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let eol = if self.crlf { "\r\n" } else { "\n" };
+        let lines = self
+            .preamble
+            .iter()
+            .map(LineRef::Line)
+            .chain(self.sections.iter().flat_map(|section| {
+                std::iter::once(LineRef::Header(&section.header))
+                    .chain(section.lines.iter().map(LineRef::Line))
+            }));
+        for (idx, line) in lines.enumerate() {
+            if idx > 0 {
+                f.write_str(eol)?;
+            }
+            match line {
+                LineRef::Header(text)
+                | LineRef::Line(Line::Text(text))
+                | LineRef::Line(Line::Entry {
+                    raw: Some(text), ..
+                }) => f.write_str(text)?,
+                LineRef::Line(Line::Entry { key, value, .. }) => write!(f, "{key} = {value}")?,
+            }
+        }
+        if self.final_newline {
+            f.write_str(eol)?;
+        }
+        Ok(())
+    }
+}
+
+/// A line of the profile being written: a section header or a line of a
+/// section.
+enum LineRef<'a> {
+    Header(&'a String),
+    Line(&'a Line),
+}
+
+/// Splits the comma separated values of every occurrence of `key`.
+fn split_list<'a>(section: &'a Section, key: &'a str) -> Vec<&'a str> {
+    section
+        .get_all(key)
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .collect()
+}
+
+fn parse_list(section: &Section, key: &str) -> Vec<Cidr> {
+    split_list(section, key)
+        .into_iter()
+        .filter_map(|v| v.parse().ok())
+        .collect()
+}
+
+/// Checks the format of the values the tool interprets. Unknown keys are
+/// accepted as they are.
+fn check_value(kind: &SectionKind, key: &str, value: &str) -> Result<(), ProfileError> {
+    let invalid = |reason: String| ProfileError::InvalidValue {
+        key: key.to_owned(),
+        value: value.to_owned(),
+        reason,
+    };
+    let key = key.to_ascii_lowercase();
+    match (kind, key.as_str()) {
+        (SectionKind::Interface, "address") | (SectionKind::Peer, "allowedips") => {
+            for item in value.split(',').map(str::trim).filter(|v| !v.is_empty()) {
+                item.parse::<Cidr>().map_err(invalid)?;
+            }
+        }
+        (SectionKind::Interface, "listenport") => {
+            value
+                .parse::<u16>()
+                .map_err(|_| invalid("not a port number".to_owned()))?;
+        }
+        (SectionKind::Interface, "mtu") => {
+            value
+                .parse::<u16>()
+                .map_err(|_| invalid("not a number".to_owned()))?;
+        }
+        (SectionKind::Peer, "persistentkeepalive") if !value.eq_ignore_ascii_case("off") => {
+            value
+                .parse::<u16>()
+                .map_err(|_| invalid("not a number of seconds or 'off'".to_owned()))?;
+        }
+        (SectionKind::Peer, "endpoint") => {
+            let port = value.rsplit_once(':').map(|(_, port)| port);
+            if port.and_then(|p| p.parse::<u16>().ok()).is_none() {
+                return Err(invalid("expected 'host:port'".to_owned()));
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OFFICE: &str = include_str!("../../../tests/fixtures/office.conf");
+
+    /// The office profile with comments and a key `wg-quick` does not know.
+    fn annotated() -> String {
+        OFFICE
+            .replace("[Interface]\n", "# Office VPN\n[Interface]\n")
+            .replace("Bmk=\n", "Bmk=  # rotated monthly\n")
+            .replace(
+                "Address = 10.0.0.2/32\n",
+                "Address = 10.0.0.2/32\nFooBar = kept as is\n",
+            )
+    }
+
+    fn round_trip(text: &str) -> String {
+        Profile::parse(text, Path::new("office.conf"))
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn writes_back_the_parsed_text() {
+        let text = annotated();
+        assert_eq!(round_trip(&text), text);
+    }
+
+    #[test]
+    fn keeps_crlf_line_endings() {
+        let text = annotated().replace('\n', "\r\n");
+        assert_eq!(round_trip(&text), text);
+    }
+
+    #[test]
+    fn keeps_a_missing_final_newline() {
+        let text = annotated();
+        let text = text.trim_end();
+        assert_eq!(round_trip(text), text);
+        let text = annotated().replace('\n', "\r\n");
+        let text = text.trim_end();
+        assert_eq!(round_trip(text), text);
+    }
+
+    #[test]
+    fn writes_changed_entries_with_the_line_endings_of_the_file() {
+        let text = annotated().replace('\n', "\r\n");
+        let mut profile = Profile::parse(&text, Path::new("office.conf")).unwrap();
+        profile.interface_mut().set("MTU", "1420").unwrap();
+        assert_eq!(
+            profile.to_string(),
+            text.replace(
+                "FooBar = kept as is\r\n",
+                "FooBar = kept as is\r\nMTU = 1420\r\n"
+            )
+        );
+    }
+
+    /// A profile with every key the tool reads, two peers and a section
+    /// `wg-quick` does not know.
+    const FULL: &str = "[Interface]\n\
+        PrivateKey = yAnz5TF+lXXJte14tji3zlMNq+hd2rYUIgJBgB3fBmk=\n\
+        Address = 10.0.0.2/32, fd00::2/128\n\
+        Address = 10.0.1.2/24\n\
+        DNS = 10.0.0.1, vpn.example.com\n\
+        ListenPort = 51820\n\
+        MTU = 1420\n\
+        \n\
+        [Peer]\n\
+        PublicKey = xTIBA5rboUvnH4htodjb6e697QjLERt1NAB4mZqp8Dg=\n\
+        AllowedIPs = 10.0.0.0/16\n\
+        AllowedIPs = fd00::/64\n\
+        Endpoint = vpn.example.com:51820\n\
+        PersistentKeepalive = 25\n\
+        \n\
+        [Peer]\n\
+        PublicKey = 9yyrdRs/rPTKvAB8MmEaPqGgMJZRc0UoH/NHzlqSAV0=\n\
+        AllowedIPs = 0.0.0.0/0\n\
+        PersistentKeepalive = off\n\
+        \n\
+        [Relay]\n\
+        Host = relay.example.com\n";
+
+    fn parse(text: &str) -> Result<Profile, ProfileError> {
+        Profile::parse(text, Path::new("office.conf"))
+    }
+
+    fn strings(cidrs: Vec<Cidr>) -> Vec<String> {
+        cidrs.iter().map(ToString::to_string).collect()
+    }
+
+    /// Returns the line and message of the syntax error of `text`.
+    fn syntax_error(text: &str) -> (usize, String) {
+        match parse(text) {
+            Err(ProfileError::Syntax { line, message, .. }) => (line, message),
+            other => panic!("unexpected result: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn reads_the_values_of_the_interface() {
+        let profile = parse(FULL).unwrap();
+        let interface = profile.interface();
+        assert_eq!(
+            strings(interface.addresses()),
+            ["10.0.0.2/32", "fd00::2/128", "10.0.1.2/24"]
+        );
+        assert_eq!(interface.dns(), ["10.0.0.1", "vpn.example.com"]);
+        assert_eq!(interface.listen_port(), Some(51820));
+        assert_eq!(interface.mtu(), Some(1420));
+        assert_eq!(interface.fwmark(), None);
+    }
+
+    #[test]
+    fn reads_the_values_of_every_peer() {
+        let profile = parse(FULL).unwrap();
+        let peers: Vec<Peer> = profile.peers().collect();
+        assert_eq!(peers.len(), 2);
+        assert_eq!(
+            strings(peers[0].allowed_ips()),
+            ["10.0.0.0/16", "fd00::/64"]
+        );
+        assert_eq!(peers[0].endpoint(), Some("vpn.example.com:51820"));
+        assert_eq!(peers[0].persistent_keepalive(), Some(25));
+        assert_eq!(strings(peers[1].allowed_ips()), ["0.0.0.0/0"]);
+        assert_eq!(peers[1].endpoint(), None);
+        assert_eq!(peers[1].persistent_keepalive(), None);
+    }
+
+    #[test]
+    fn keeps_the_sections_it_does_not_know() {
+        let profile = parse(FULL).unwrap();
+        let relay = &profile.sections()[3];
+        assert_eq!(relay.kind(), &SectionKind::Other("Relay".to_owned()));
+        assert_eq!(relay.get("host"), Some("relay.example.com"));
+        assert_eq!(profile.to_string(), FULL);
+    }
+
+    #[test]
+    fn edits_the_values_and_keeps_the_rest() {
+        let text = annotated();
+        let mut profile = parse(&text).unwrap();
+        let interface = profile.interface_mut();
+        interface.set("Address", "10.0.0.3/32").unwrap();
+        interface.append("DNS", "10.0.0.1").unwrap();
+        let peer = profile
+            .peer_mut("xTIBA5rboUvnH4htodjb6e697QjLERt1NAB4mZqp8Dg=")
+            .unwrap();
+        assert!(peer.remove("endpoint"));
+        assert!(!peer.remove("Endpoint"));
+        peer.set("PersistentKeepalive", "25").unwrap();
+        let expected = text
+            .replace("10.0.0.2/32", "10.0.0.3/32")
+            .replace("kept as is\n", "kept as is\nDNS = 10.0.0.1\n")
+            .replace(
+                "Endpoint = vpn.example.com:51820",
+                "PersistentKeepalive = 25",
+            );
+        assert_eq!(profile.to_string(), expected);
+    }
+
+    #[test]
+    fn replaces_every_occurrence_of_a_key() {
+        let mut profile = parse(FULL).unwrap();
+        profile
+            .interface_mut()
+            .set("address", "10.0.0.3/32")
+            .unwrap();
+        assert_eq!(strings(profile.interface().addresses()), ["10.0.0.3/32"]);
+    }
+
+    #[test]
+    fn refuses_invalid_edits() {
+        let mut profile = parse(OFFICE).unwrap();
+        let text = profile.to_string();
+        let interface = profile.interface_mut();
+        assert!(interface.set("Address", "10.0.0.300/32").is_err());
+        assert!(interface.append("ListenPort", "http").is_err());
+        assert_eq!(profile.to_string(), text);
+    }
+
+    #[test]
+    fn refuses_a_key_outside_of_any_section() {
+        let (line, message) = syntax_error("# Office\nPrivateKey = x\n[Interface]\n");
+        assert_eq!(line, 2);
+        assert_eq!(message, "'PrivateKey' is outside of any section");
+    }
+
+    #[test]
+    fn refuses_an_invalid_address() {
+        let (line, message) = syntax_error("[Interface]\nAddress = 10.0.0.2/33\n");
+        assert_eq!(line, 2);
+        assert!(
+            message.starts_with("invalid value '10.0.0.2/33' for Address"),
+            "{message}"
+        );
+        let (line, _) = syntax_error(&OFFICE.replace("0.0.0.0/0", "0.0.0.0/0, nowhere"));
+        assert_eq!(line, 7);
+    }
+
+    #[test]
+    fn refuses_malformed_sections() {
+        let (line, message) = syntax_error("[Interface\nAddress = 10.0.0.2/32\n");
+        assert_eq!(
+            (line, message.as_str()),
+            (1, "unterminated section header '[Interface'")
+        );
+        let (line, message) = syntax_error("[Interface]\n[interface]\n");
+        assert_eq!(
+            (line, message.as_str()),
+            (2, "duplicate [Interface] section")
+        );
+        assert!(matches!(
+            parse("[Peer]\nAllowedIPs = 0.0.0.0/0\n"),
+            Err(ProfileError::MissingInterface { .. })
+        ));
+    }
+}
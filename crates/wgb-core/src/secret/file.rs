@@ -0,0 +1,208 @@
+//! Secrets kept in a file only readable by the user.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, DirBuilder};
+use std::io;
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use zeroize::{Zeroize, Zeroizing};
+
+use super::{SecretError, SecretStore, StoreKind};
+use crate::file;
+
+/// Name of the secret file, inside the configuration directory.
+pub const SECRETS_FILE_NAME: &str = "secrets.json";
+
+/// Environment variable overriding the path of the secret file.
+pub const SECRETS_ENV: &str = "WGB_SECRETS";
+
+/// Content of the secret file: the secrets, by name.
+#[derive(Default, Serialize, Deserialize)]
+struct Content {
+    #[serde(default)]
+    secrets: BTreeMap<String, String>,
+}
+
+impl Drop for Content {
+    fn drop(&mut self) {
+        for value in self.secrets.values_mut() {
+            value.zeroize();
+        }
+    }
+}
+
+/// Keeps the secrets in a JSON file, like the TOTP seeds: the fallback for
+/// the sessions without a Secret Service.
+pub struct FileStore {
+    path: PathBuf,
+    content: Content,
+}
+
+impl FileStore {
+    /// Returns the path of the secret file: `$WGB_SECRETS`, else
+    /// `$XDG_CONFIG_HOME/wg-bridge/secrets.json`, else
+    /// `~/.config/wg-bridge/secrets.json`.
+    pub fn default_path() -> Result<PathBuf, SecretError> {
+        if let Some(path) = env::var_os(SECRETS_ENV).filter(|path| !path.is_empty()) {
+            return Ok(PathBuf::from(path));
+        }
+        let dir = file::config_dir().ok_or(SecretError::NoHome)?;
+        Ok(dir.join(SECRETS_FILE_NAME))
+    }
+
+    /// Reads the secret file at `path`; a missing file holds no secret. A
+    /// file other users may read is refused.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, SecretError> {
+        let path = path.into();
+        let io_err = |source| SecretError::Io {
+            path: path.clone(),
+            source,
+        };
+        let meta = match fs::metadata(&path) {
+            Ok(meta) => meta,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Ok(Self {
+                    path,
+                    content: Content::default(),
+                })
+            }
+            Err(err) => return Err(io_err(err)),
+        };
+        if meta.permissions().mode() & 0o077 != 0 {
+            return Err(SecretError::Exposed { path });
+        }
+        let text = Zeroizing::new(fs::read_to_string(&path).map_err(io_err)?);
+        let content = serde_json::from_str(&text).map_err(|e| SecretError::Syntax {
+            path: path.clone(),
+            message: e.to_string(),
+        })?;
+        Ok(Self { path, content })
+    }
+
+    /// Returns the path of the secret file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Writes the secrets, readable by the user only, creating the
+    /// directory.
+    fn save(&self) -> Result<(), SecretError> {
+        let io_err = |source| SecretError::Io {
+            path: self.path.clone(),
+            source,
+        };
+        if let Some(dir) = self.path.parent().filter(|dir| !dir.exists()) {
+            DirBuilder::new()
+                .recursive(true)
+                .mode(0o700)
+                .create(dir)
+                .map_err(io_err)?;
+        }
+        let mut text = Zeroizing::new(
+            serde_json::to_string_pretty(&self.content).map_err(|e| io_err(e.into()))?,
+        );
+        text.push('\n');
+        file::write_atomic(&self.path, text.as_bytes(), 0o600).map_err(io_err)
+    }
+}
+
+impl SecretStore for FileStore {
+    fn kind(&self) -> StoreKind {
+        StoreKind::File
+    }
+
+    fn get(&self, name: &str) -> Result<Option<Zeroizing<String>>, SecretError> {
+        Ok(self.content.secrets.get(name).cloned().map(Zeroizing::new))
+    }
+
+    fn set(&mut self, name: &str, value: &str) -> Result<(), SecretError> {
+        if let Some(mut old) = self
+            .content
+            .secrets
+            .insert(name.to_owned(), value.to_owned())
+        {
+            old.zeroize();
+        }
+        self.save()
+    }
+
+    fn delete(&mut self, name: &str) -> Result<bool, SecretError> {
+        match self.content.secrets.remove(name) {
+            Some(mut old) => {
+                old.zeroize();
+                self.save()?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stores_gets_and_deletes_the_secrets() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wgb").join(SECRETS_FILE_NAME);
+        let mut store = FileStore::open(&path).unwrap();
+        assert!(store.get("office/PrivateKey").unwrap().is_none());
+        assert!(!path.exists());
+
+        store.set("office/PrivateKey", "first").unwrap();
+        store.set("office/PrivateKey", "second").unwrap();
+        store.set("home/PrivateKey", "other").unwrap();
+        assert_eq!(
+            fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+        let parent = path.parent().unwrap();
+        assert_eq!(
+            fs::metadata(parent).unwrap().permissions().mode() & 0o777,
+            0o700
+        );
+
+        let mut store = FileStore::open(&path).unwrap();
+        assert_eq!(
+            store
+                .get("office/PrivateKey")
+                .unwrap()
+                .as_deref()
+                .map(String::as_str),
+            Some("second")
+        );
+        assert_eq!(
+            store
+                .get("home/PrivateKey")
+                .unwrap()
+                .as_deref()
+                .map(String::as_str),
+            Some("other")
+        );
+
+        assert!(store.delete("office/PrivateKey").unwrap());
+        assert!(!store.delete("office/PrivateKey").unwrap());
+        let store = FileStore::open(&path).unwrap();
+        assert!(store.get("office/PrivateKey").unwrap().is_none());
+        assert!(store.get("home/PrivateKey").unwrap().is_some());
+    }
+
+    #[test]
+    fn refuses_a_file_other_users_may_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(SECRETS_FILE_NAME);
+        fs::write(&path, "{\"secrets\": {}}\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        assert!(matches!(
+            FileStore::open(&path),
+            Err(SecretError::Exposed { .. })
+        ));
+
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+        assert!(FileStore::open(&path).is_ok());
+    }
+}
@@ -0,0 +1,154 @@
+//! Secrets kept in the user keyring of the Linux kernel.
+
+use std::ffi::{c_long, CStr, CString};
+use std::io;
+
+use zeroize::Zeroizing;
+
+use super::{SecretError, SecretStore, StoreKind};
+
+/// Type of the keys: a blob of at most 32 KiB, readable by its owner.
+const KEY_TYPE: &CStr = c"user";
+
+/// Prefix of the key descriptions, to tell them from those of other tools.
+const DESCRIPTION_PREFIX: &str = "wgb:";
+
+/// Keeps the secrets as `user` keys named `wgb:<name>` in the user keyring
+/// (`@u`), where `keyctl` can inspect them. The keyring lives in the kernel
+/// only: it is empty after a reboot.
+#[derive(Debug, Clone, Default)]
+pub struct KeyringStore;
+
+impl KeyringStore {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+/// Returns the description of the key holding the secret `name`.
+fn description(name: &str) -> Result<CString, SecretError> {
+    CString::new(format!("{DESCRIPTION_PREFIX}{name}"))
+        .map_err(|err| SecretError::Keyring(io::Error::new(io::ErrorKind::InvalidInput, err)))
+}
+
+/// Maps the result of a keyring system call, `-1` on failure.
+fn check(result: c_long) -> Result<c_long, SecretError> {
+    if result < 0 {
+        Err(SecretError::Keyring(io::Error::last_os_error()))
+    } else {
+        Ok(result)
+    }
+}
+
+/// Returns the serial number of the key holding the secret `name`.
+fn search(name: &str) -> Result<Option<c_long>, SecretError> {
+    let description = description(name)?;
+    // SAFETY: both strings are NUL terminated and outlive the call.
+    let result = unsafe {
+        libc::syscall(
+            libc::SYS_keyctl,
+            libc::KEYCTL_SEARCH,
+            libc::KEY_SPEC_USER_KEYRING,
+            KEY_TYPE.as_ptr(),
+            description.as_ptr(),
+            0,
+        )
+    };
+    match check(result) {
+        Ok(serial) => Ok(Some(serial)),
+        Err(SecretError::Keyring(err)) if err.raw_os_error() == Some(libc::ENOKEY) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+impl SecretStore for KeyringStore {
+    fn kind(&self) -> StoreKind {
+        StoreKind::Keyring
+    }
+
+    fn get(&self, name: &str) -> Result<Option<Zeroizing<String>>, SecretError> {
+        let Some(serial) = search(name)? else {
+            return Ok(None);
+        };
+        let mut buffer = Zeroizing::new(Vec::new());
+        loop {
+            // SAFETY: the kernel writes at most `buffer.len()` bytes to it.
+            let size = check(unsafe {
+                libc::syscall(
+                    libc::SYS_keyctl,
+                    libc::KEYCTL_READ,
+                    serial,
+                    buffer.as_mut_ptr(),
+                    buffer.len(),
+                )
+            })? as usize;
+            if size <= buffer.len() {
+                buffer.truncate(size);
+                break;
+            }
+            // The key holds more than the buffer: retry with its size.
+            buffer.resize(size, 0);
+        }
+        let value = String::from_utf8(std::mem::take(&mut *buffer))
+            .map_err(|err| SecretError::Keyring(io::Error::new(io::ErrorKind::InvalidData, err)))?;
+        Ok(Some(Zeroizing::new(value)))
+    }
+
+    fn set(&mut self, name: &str, value: &str) -> Result<(), SecretError> {
+        let description = description(name)?;
+        // SAFETY: the strings are NUL terminated and the payload is
+        // `value.len()` bytes long, all outliving the call. An existing key
+        // with the same description is updated.
+        check(unsafe {
+            libc::syscall(
+                libc::SYS_add_key,
+                KEY_TYPE.as_ptr(),
+                description.as_ptr(),
+                value.as_ptr(),
+                value.len(),
+                libc::KEY_SPEC_USER_KEYRING,
+            )
+        })?;
+        Ok(())
+    }
+
+    fn delete(&mut self, name: &str) -> Result<bool, SecretError> {
+        let Some(serial) = search(name)? else {
+            return Ok(false);
+        };
+        // SAFETY: the call takes no pointer.
+        check(unsafe {
+            libc::syscall(
+                libc::SYS_keyctl,
+                libc::KEYCTL_UNLINK,
+                serial,
+                libc::KEY_SPEC_USER_KEYRING,
+            )
+        })?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stores_gets_and_deletes_the_secrets() {
+        let name = format!("test/{}/PrivateKey", std::process::id());
+        let mut store = KeyringStore::new();
+        // Containers often deny the keyring system calls.
+        let Ok(None) = store.get(&name) else {
+            return;
+        };
+        store.set(&name, "first").unwrap();
+        store.set(&name, "second").unwrap();
+        assert_eq!(
+            store.get(&name).unwrap().as_deref().map(String::as_str),
+            Some("second")
+        );
+        assert!(store.delete(&name).unwrap());
+        assert!(!store.delete(&name).unwrap());
+        assert!(store.get(&name).unwrap().is_none());
+    }
+}
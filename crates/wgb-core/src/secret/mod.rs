@@ -0,0 +1,513 @@
+//! Keys of the profiles kept outside of the profile files.
+//!
+//! A sealed profile holds, instead of its `PrivateKey` and `PresharedKey`
+//! values, references of the form `secret:<store>:<name>` to secrets kept
+//! in a [`SecretStore`]:
+//!
+//! - [`ServiceStore`] in the Secret Service of the desktop session (GNOME
+//!   Keyring, KeePassXC...), through D-Bus;
+//! - [`FileStore`] in a file only readable by the user, where no Secret
+//!   Service runs;
+//! - [`KeyringStore`] in the user keyring of the Linux kernel, which does not
+//!   survive a reboot.
+//!
+//! When connecting, the secrets are fetched as the user, see [`resolve`],
+//! and travel with the [`Tunnel`](crate::backend::Tunnel) to the backend,
+//! which puts them back in the profile in memory only, see [`materialize`].
+
+mod file;
+mod keyring;
+mod service;
+
+use std::collections::btree_map::{BTreeMap, Entry};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::profile::{Profile, ProfileError, SectionKind};
+
+pub use file::{FileStore, SECRETS_ENV, SECRETS_FILE_NAME};
+pub use keyring::KeyringStore;
+pub use service::ServiceStore;
+
+/// Prefix of the values referencing a secret.
+pub const PREFIX: &str = "secret:";
+
+/// Errors raised while storing or fetching the secrets.
+#[derive(Debug, Error)]
+pub enum SecretError {
+    /// The profile could not be read or changed.
+    #[error(transparent)]
+    Profile(#[from] ProfileError),
+
+    /// Neither `XDG_CONFIG_HOME` nor `HOME` is set.
+    #[error("unable to locate the configuration directory, HOME is not set")]
+    NoHome,
+
+    /// Reading or writing the secret file failed.
+    #[error("unable to access '{}': {source}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    /// The secret file is not valid.
+    #[error("{}: malformed secret file: {message}", path.display())]
+    Syntax { path: PathBuf, message: String },
+
+    /// Other users may read the secret file.
+    #[error(
+        "'{}' is readable by other users, restrict it with: chmod 600 '{0}'",
+        path.display()
+    )]
+    Exposed { path: PathBuf },
+
+    /// The Secret Service could not be reached or refused the request.
+    #[error("the Secret Service failed: {0}")]
+    Service(#[source] Box<zbus::Error>),
+
+    /// The user dismissed the unlock prompt of the Secret Service.
+    #[error("the Secret Service prompt was dismissed")]
+    Dismissed,
+
+    /// A request to the kernel keyring failed.
+    #[error("the kernel keyring failed: {0}")]
+    Keyring(io::Error),
+
+    /// A value starting with [`PREFIX`] is not a valid reference.
+    #[error("'{0}' is not a valid secret reference, expected 'secret:<store>:<name>' with a store among service, file or keyring")]
+    Reference(String),
+
+    /// A referenced secret is not in its store.
+    #[error("secret '{0}' not found")]
+    Missing(String),
+
+    /// The profile would write the keys back to the file.
+    #[error("'{}' sets SaveConfig, which writes the keys back on disconnection", .0.display())]
+    SaveConfig(PathBuf),
+}
+
+impl From<zbus::Error> for SecretError {
+    fn from(err: zbus::Error) -> Self {
+        Self::Service(Box::new(err))
+    }
+}
+
+/// Identifies a secret store.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum StoreKind {
+    #[default]
+    Service,
+    File,
+    Keyring,
+}
+
+impl StoreKind {
+    /// Every store, in the order they are documented.
+    pub const ALL: [StoreKind; 3] = [Self::Service, Self::File, Self::Keyring];
+
+    /// Returns the name used in the references and on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Service => "service",
+            Self::File => "file",
+            Self::Keyring => "keyring",
+        }
+    }
+
+    /// Opens the store with its default settings.
+    pub fn open(self) -> Result<Box<dyn SecretStore>, SecretError> {
+        Ok(match self {
+            Self::Service => Box::new(ServiceStore::connect()?),
+            Self::File => Box::new(FileStore::open(FileStore::default_path()?)?),
+            Self::Keyring => Box::new(KeyringStore::new()),
+        })
+    }
+}
+
+impl fmt::Display for StoreKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for StoreKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.name() == s)
+            .ok_or_else(|| format!("unknown secret store '{s}'"))
+    }
+}
+
+/// Storage of secrets by name.
+pub trait SecretStore {
+    /// Returns the kind of the store.
+    fn kind(&self) -> StoreKind;
+
+    /// Returns the secret `name`, if stored.
+    fn get(&self, name: &str) -> Result<Option<Zeroizing<String>>, SecretError>;
+
+    /// Stores `value` as the secret `name`, replacing any previous one.
+    fn set(&mut self, name: &str, value: &str) -> Result<(), SecretError>;
+
+    /// Forgets the secret `name`; returns whether it was stored.
+    fn delete(&mut self, name: &str) -> Result<bool, SecretError>;
+}
+
+/// A `secret:<store>:<name>` value of a profile.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Reference {
+    pub store: StoreKind,
+    pub name: String,
+}
+
+impl Reference {
+    /// Parses `value`; `None` when it is not a reference but a plain key.
+    pub fn parse(value: &str) -> Result<Option<Self>, SecretError> {
+        let Some(rest) = value.strip_prefix(PREFIX) else {
+            return Ok(None);
+        };
+        let invalid = || SecretError::Reference(value.to_owned());
+        let (store, name) = rest.split_once(':').ok_or_else(invalid)?;
+        let store = store.parse().map_err(|_| invalid())?;
+        if name.trim().is_empty() {
+            return Err(invalid());
+        }
+        Ok(Some(Self {
+            store,
+            name: name.to_owned(),
+        }))
+    }
+}
+
+impl fmt::Display for Reference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{PREFIX}{}:{}", self.store, self.name)
+    }
+}
+
+/// The secrets referenced by a profile, by reference. Wiped from memory
+/// once dropped.
+#[derive(Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Secrets(BTreeMap<String, String>);
+
+impl Secrets {
+    /// Returns whether there is no secret.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns the secret `reference` points to.
+    pub fn get(&self, reference: &Reference) -> Option<&str> {
+        self.0.get(&reference.to_string()).map(String::as_str)
+    }
+}
+
+impl fmt::Debug for Secrets {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.0.keys()).finish()
+    }
+}
+
+impl Drop for Secrets {
+    fn drop(&mut self) {
+        for value in self.0.values_mut() {
+            value.zeroize();
+        }
+    }
+}
+
+/// Returns the key holding a secret in the sections of `kind`.
+fn secret_key(kind: &SectionKind) -> Option<&'static str> {
+    match kind {
+        SectionKind::Interface => Some("PrivateKey"),
+        SectionKind::Peer => Some("PresharedKey"),
+        SectionKind::Other(_) => None,
+    }
+}
+
+/// Returns the references held by `profile`, in file order.
+pub fn references(profile: &Profile) -> Result<Vec<Reference>, SecretError> {
+    let mut references = Vec::new();
+    for section in profile.sections() {
+        let Some(key) = secret_key(section.kind()) else {
+            continue;
+        };
+        if let Some(reference) = section.get(key).map(Reference::parse).transpose()? {
+            references.extend(reference);
+        }
+    }
+    Ok(references)
+}
+
+/// Opens the store of a kind, [`StoreKind::open`] outside of the tests.
+type Opener<'a> = &'a dyn Fn(StoreKind) -> Result<Box<dyn SecretStore>, SecretError>;
+
+/// Fetches from their stores the secrets referenced by `profile`.
+pub fn fetch(profile: &Profile) -> Result<Secrets, SecretError> {
+    fetch_from(profile, &StoreKind::open)
+}
+
+fn fetch_from(profile: &Profile, open: Opener) -> Result<Secrets, SecretError> {
+    let mut stores: BTreeMap<StoreKind, Box<dyn SecretStore>> = BTreeMap::new();
+    let mut secrets = Secrets::default();
+    for reference in references(profile)? {
+        let store = match stores.entry(reference.store) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(open(reference.store)?),
+        };
+        let value = store
+            .get(&reference.name)?
+            .ok_or_else(|| SecretError::Missing(reference.to_string()))?;
+        secrets.0.insert(reference.to_string(), value.to_string());
+    }
+    Ok(secrets)
+}
+
+/// Fetches, as the current user, the secrets referenced by the profile at
+/// `path`. A profile the user cannot read, such as one in
+/// `/etc/wireguard`, references none of their secrets: nothing is fetched.
+pub fn resolve(path: &Path) -> Result<Secrets, SecretError> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => Zeroizing::new(text),
+        Err(err)
+            if matches!(
+                err.kind(),
+                io::ErrorKind::NotFound | io::ErrorKind::PermissionDenied
+            ) =>
+        {
+            return Ok(Secrets::default())
+        }
+        Err(source) => {
+            return Err(SecretError::Io {
+                path: path.to_path_buf(),
+                source,
+            })
+        }
+    };
+    fetch(&Profile::parse(&text, path)?)
+}
+
+/// Replaces the references of `profile` with the secrets they point to.
+pub fn materialize(profile: &mut Profile, secrets: &Secrets) -> Result<(), SecretError> {
+    for section in profile.sections_mut() {
+        let Some(key) = secret_key(section.kind()) else {
+            continue;
+        };
+        let Some(reference) = section
+            .get(key)
+            .map(Reference::parse)
+            .transpose()?
+            .flatten()
+        else {
+            continue;
+        };
+        let value = secrets
+            .get(&reference)
+            .ok_or_else(|| SecretError::Missing(reference.to_string()))?;
+        section.set(key, value)?;
+    }
+    Ok(())
+}
+
+/// Removes the keys from `profile`, references or not. Returns whether
+/// there was any.
+pub fn strip(profile: &mut Profile) -> bool {
+    let mut stripped = false;
+    for section in profile.sections_mut() {
+        if let Some(key) = secret_key(section.kind()) {
+            stripped |= section.remove(key);
+        }
+    }
+    stripped
+}
+
+/// Moves the plain keys of `profile`, the profile at `path`, to `store` and
+/// replaces them with references. Returns the references, to [`delete`] if
+/// the profile cannot be saved.
+///
+/// The secrets are named after the canonical path of the profile, so that
+/// profiles of the same name in different directories do not share them.
+/// Profiles with `SaveConfig` set are refused, as `wg-quick` would write the
+/// keys back to the file.
+pub fn seal(
+    profile: &mut Profile,
+    path: &Path,
+    store: &mut dyn SecretStore,
+) -> Result<Vec<Reference>, SecretError> {
+    if profile.interface().save_config() {
+        return Err(SecretError::SaveConfig(path.to_path_buf()));
+    }
+    let scope = fs::canonicalize(path).map_err(|source| SecretError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let scope = scope.display();
+    let mut sealed: Vec<Reference> = Vec::new();
+    for section in profile.sections_mut() {
+        let Some(key) = secret_key(section.kind()) else {
+            continue;
+        };
+        let Some(value) = section.get(key).filter(|value| !value.starts_with(PREFIX)) else {
+            continue;
+        };
+        let value = Zeroizing::new(value.to_owned());
+        let name = match section.kind() {
+            SectionKind::Peer => match section.get("PublicKey") {
+                Some(peer) => format!("{scope}/{peer}/{key}"),
+                None => continue,
+            },
+            _ => format!("{scope}/{key}"),
+        };
+        let reference = Reference {
+            store: store.kind(),
+            name,
+        };
+        if let Err(err) = store
+            .set(&reference.name, &value)
+            .and_then(|()| Ok(section.set(key, &reference.to_string())?))
+        {
+            let _ = store.delete(&reference.name);
+            for reference in &sealed {
+                let _ = store.delete(&reference.name);
+            }
+            return Err(err);
+        }
+        sealed.push(reference);
+    }
+    Ok(sealed)
+}
+
+/// Puts the plain keys back in `profile` and returns the references they
+/// replaced, to [`delete`] once the profile is saved.
+pub fn unseal(profile: &mut Profile) -> Result<Vec<Reference>, SecretError> {
+    unseal_from(profile, &StoreKind::open)
+}
+
+fn unseal_from(profile: &mut Profile, open: Opener) -> Result<Vec<Reference>, SecretError> {
+    let references = references(profile)?;
+    materialize(profile, &fetch_from(profile, open)?)?;
+    Ok(references)
+}
+
+/// Removes the secrets `references` point to from their stores.
+pub fn delete(references: &[Reference]) -> Result<(), SecretError> {
+    delete_from(references, &StoreKind::open)
+}
+
+fn delete_from(references: &[Reference], open: Opener) -> Result<(), SecretError> {
+    for store in StoreKind::ALL {
+        let mut names = references
+            .iter()
+            .filter(|reference| reference.store == store)
+            .peekable();
+        if names.peek().is_none() {
+            continue;
+        }
+        let mut opened = open(store)?;
+        for reference in names {
+            opened.delete(&reference.name)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRIVATE_KEY: &str = "yAnz5TF+lXXJte14tji3zlMNq+hd2rYUIgJBgB3fBmk=";
+    const PRESHARED_KEY: &str = "FpCyhws9cxwWoV4xELtfJvjJN+zQVRPISllRWgeopVE=";
+
+    const OFFICE: &str = include_str!("../../../../tests/fixtures/office.conf");
+
+    /// The office profile, with a preshared key.
+    fn profile_text() -> String {
+        OFFICE.replace(
+            "AllowedIPs",
+            &format!("PresharedKey = {PRESHARED_KEY}\nAllowedIPs"),
+        )
+    }
+
+    #[test]
+    fn seals_and_unseals_a_profile() {
+        let plain = profile_text();
+        assert!(plain.contains(PRIVATE_KEY) && plain.contains(PRESHARED_KEY));
+        let dir = tempfile::tempdir().unwrap();
+        let secrets = dir.path().join(SECRETS_FILE_NAME);
+        let mut store = FileStore::open(&secrets).unwrap();
+        let open = |kind| -> Result<Box<dyn SecretStore>, SecretError> {
+            assert_eq!(kind, StoreKind::File);
+            Ok(Box::new(FileStore::open(&secrets)?))
+        };
+
+        let mut sealed = Vec::new();
+        for name in ["a", "b"] {
+            let path = dir.path().join(name).join("office.conf");
+            fs::create_dir(path.parent().unwrap()).unwrap();
+            fs::write(&path, &plain).unwrap();
+            let mut profile = Profile::load(&path).unwrap();
+            let references = seal(&mut profile, &path, &mut store).unwrap();
+            profile.save(&path).unwrap();
+            sealed.push((path, references));
+        }
+
+        let (path, references) = &sealed[0];
+        let scope = fs::canonicalize(path).unwrap();
+        let names: Vec<&str> = references.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                format!("{}/PrivateKey", scope.display()),
+                format!(
+                    "{}/xTIBA5rboUvnH4htodjb6e697QjLERt1NAB4mZqp8Dg=/PresharedKey",
+                    scope.display()
+                ),
+            ]
+        );
+        assert!(references.iter().all(|r| r.store == StoreKind::File));
+        assert_ne!(sealed[0].1, sealed[1].1);
+
+        let text = fs::read_to_string(path).unwrap();
+        assert!(!text.contains(PRIVATE_KEY) && !text.contains(PRESHARED_KEY));
+        assert!(text.contains(&format!("PrivateKey = {}", references[0])));
+
+        for (path, references) in &sealed {
+            let mut profile = Profile::load(path).unwrap();
+            assert_eq!(&unseal_from(&mut profile, &open).unwrap(), references);
+            assert_eq!(profile.to_string(), plain);
+            delete_from(references, &open).unwrap();
+        }
+        let store = FileStore::open(&secrets).unwrap();
+        for (_, references) in &sealed {
+            assert!(store.get(&references[0].name).unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn refuses_to_seal_a_profile_saving_its_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("office.conf");
+        let text = profile_text().replace("[Interface]\n", "[Interface]\nSaveConfig = true\n");
+        fs::write(&path, &text).unwrap();
+        let mut profile = Profile::load(&path).unwrap();
+        let mut store = FileStore::open(dir.path().join(SECRETS_FILE_NAME)).unwrap();
+        assert!(matches!(
+            seal(&mut profile, &path, &mut store),
+            Err(SecretError::SaveConfig(_))
+        ));
+        assert_eq!(profile.to_string(), text);
+        assert!(!dir.path().join(SECRETS_FILE_NAME).exists());
+    }
+}
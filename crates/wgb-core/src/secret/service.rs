@@ -0,0 +1,189 @@
+//! Secrets kept in the Secret Service of the desktop session.
+//!
+//! A minimal client of the `org.freedesktop.Secret` API on the session bus,
+//! served by GNOME Keyring, KeePassXC and the like. The secrets are
+//! exchanged in a `plain` session: the session bus is private to the user.
+
+use std::collections::HashMap;
+
+use zbus::blocking::{Connection, Proxy};
+use zbus::zvariant::{ObjectPath, OwnedObjectPath, OwnedValue, Value};
+use zeroize::{Zeroize, Zeroizing};
+
+use super::{SecretError, SecretStore, StoreKind};
+
+/// Bus name of the Secret Service.
+const SERVICE: &str = "org.freedesktop.secrets";
+
+/// Path of the service object.
+const SERVICE_PATH: &str = "/org/freedesktop/secrets";
+
+const SERVICE_INTERFACE: &str = "org.freedesktop.Secret.Service";
+const COLLECTION_INTERFACE: &str = "org.freedesktop.Secret.Collection";
+const ITEM_INTERFACE: &str = "org.freedesktop.Secret.Item";
+const PROMPT_INTERFACE: &str = "org.freedesktop.Secret.Prompt";
+
+/// Value of the `application` attribute of the items.
+const APPLICATION: &str = "wg-bridge";
+
+/// Collection the items are created in.
+const DEFAULT_ALIAS: &str = "default";
+
+/// A secret as exchanged with the service: session, parameters, value and
+/// content type.
+type Secret = (OwnedObjectPath, Vec<u8>, Vec<u8>, String);
+
+/// Keeps the secrets as items of the default collection, with the
+/// attributes `application` = `wg-bridge` and `name` = the secret name.
+pub struct ServiceStore {
+    connection: Connection,
+    session: OwnedObjectPath,
+}
+
+impl ServiceStore {
+    /// Connects to the Secret Service of the session bus.
+    pub fn connect() -> Result<Self, SecretError> {
+        let connection = Connection::session()?;
+        let service = proxy(&connection, SERVICE_PATH, SERVICE_INTERFACE)?;
+        let (_, session): (OwnedValue, OwnedObjectPath) =
+            service.call("OpenSession", &("plain", Value::from("")))?;
+        Ok(Self {
+            connection,
+            session,
+        })
+    }
+
+    fn service(&self) -> Result<Proxy<'_>, SecretError> {
+        proxy(&self.connection, SERVICE_PATH, SERVICE_INTERFACE)
+    }
+
+    /// Returns the items holding the secret `name`, unlocked.
+    fn find(&self, name: &str) -> Result<Vec<OwnedObjectPath>, SecretError> {
+        let (mut unlocked, locked): (Vec<OwnedObjectPath>, Vec<OwnedObjectPath>) =
+            self.service()?.call("SearchItems", &(attributes(name),))?;
+        if !locked.is_empty() {
+            unlocked.extend(self.unlock(locked)?);
+        }
+        Ok(unlocked)
+    }
+
+    /// Unlocks `objects`, prompting the user if needed, and returns those
+    /// unlocked.
+    fn unlock(&self, objects: Vec<OwnedObjectPath>) -> Result<Vec<OwnedObjectPath>, SecretError> {
+        let (mut unlocked, prompt): (Vec<OwnedObjectPath>, OwnedObjectPath) =
+            self.service()?.call("Unlock", &(objects,))?;
+        if let Some(result) = self.prompt(&prompt)? {
+            unlocked.extend(Vec::<OwnedObjectPath>::try_from(result).map_err(zbus::Error::from)?);
+        }
+        Ok(unlocked)
+    }
+
+    /// Shows the prompt at `path`, unless it is `/` (no prompt needed), and
+    /// returns its result once the user answered.
+    fn prompt(&self, path: &ObjectPath<'_>) -> Result<Option<OwnedValue>, SecretError> {
+        if path.as_str() == "/" {
+            return Ok(None);
+        }
+        let prompt = proxy(&self.connection, path.as_str(), PROMPT_INTERFACE)?;
+        let mut completed = prompt.receive_signal("Completed")?;
+        prompt.call_method("Prompt", &("",))?;
+        let message = completed.next().ok_or(SecretError::Dismissed)?;
+        let (dismissed, result): (bool, OwnedValue) = message.body().deserialize()?;
+        if dismissed {
+            return Err(SecretError::Dismissed);
+        }
+        Ok(Some(result))
+    }
+
+    /// Returns the default collection, unlocked, creating it if needed.
+    fn collection(&self) -> Result<OwnedObjectPath, SecretError> {
+        let service = self.service()?;
+        let mut path: OwnedObjectPath = service.call("ReadAlias", &(DEFAULT_ALIAS,))?;
+        if path.as_str() == "/" {
+            let properties = HashMap::from([(
+                "org.freedesktop.Secret.Collection.Label",
+                Value::from("Login"),
+            )]);
+            let prompt: OwnedObjectPath;
+            (path, prompt) = service.call("CreateCollection", &(properties, DEFAULT_ALIAS))?;
+            if path.as_str() == "/" {
+                let result = self.prompt(&prompt)?.ok_or(SecretError::Dismissed)?;
+                path = OwnedObjectPath::try_from(result).map_err(zbus::Error::from)?;
+            }
+        }
+        self.unlock(vec![path.clone()])?;
+        Ok(path)
+    }
+}
+
+impl SecretStore for ServiceStore {
+    fn kind(&self) -> StoreKind {
+        StoreKind::Service
+    }
+
+    fn get(&self, name: &str) -> Result<Option<Zeroizing<String>>, SecretError> {
+        let Some(item) = self.find(name)?.into_iter().next() else {
+            return Ok(None);
+        };
+        let (_, _, value, _): Secret = proxy(&self.connection, item.as_str(), ITEM_INTERFACE)?
+            .call("GetSecret", &(&self.session,))?;
+        let value = Zeroizing::new(value);
+        let value = String::from_utf8(value.to_vec())
+            .map_err(|_| zbus::Error::Failure(format!("secret '{name}' is not UTF-8")))?;
+        Ok(Some(Zeroizing::new(value)))
+    }
+
+    fn set(&mut self, name: &str, value: &str) -> Result<(), SecretError> {
+        let collection = self.collection()?;
+        let properties = HashMap::from([
+            (
+                "org.freedesktop.Secret.Item.Label",
+                Value::from(format!("{APPLICATION}: {name}")),
+            ),
+            (
+                "org.freedesktop.Secret.Item.Attributes",
+                Value::from(attributes(name)),
+            ),
+        ]);
+        let secret: Secret = (
+            self.session.clone(),
+            Vec::new(),
+            value.as_bytes().to_vec(),
+            "text/plain".to_owned(),
+        );
+        let result: Result<(OwnedObjectPath, OwnedObjectPath), _> =
+            proxy(&self.connection, collection.as_str(), COLLECTION_INTERFACE)?
+                .call("CreateItem", &(properties, &secret, true));
+        let (_, _, mut bytes, _) = secret;
+        bytes.zeroize();
+        let (item, prompt) = result?;
+        if item.as_str() == "/" {
+            self.prompt(&prompt)?;
+        }
+        Ok(())
+    }
+
+    fn delete(&mut self, name: &str) -> Result<bool, SecretError> {
+        let items = self.find(name)?;
+        for item in &items {
+            let prompt: OwnedObjectPath =
+                proxy(&self.connection, item.as_str(), ITEM_INTERFACE)?.call("Delete", &())?;
+            self.prompt(&prompt)?;
+        }
+        Ok(!items.is_empty())
+    }
+}
+
+/// Returns the attributes identifying the secret `name`.
+fn attributes(name: &str) -> HashMap<&str, &str> {
+    HashMap::from([("application", APPLICATION), ("name", name)])
+}
+
+/// Creates a proxy for `interface` of the service object at `path`.
+fn proxy<'a>(
+    connection: &Connection,
+    path: &'a str,
+    interface: &'a str,
+) -> Result<Proxy<'a>, SecretError> {
+    Ok(Proxy::new(connection, SERVICE, path, interface)?)
+}
@@ -0,0 +1,265 @@
+//! Runtime state of the connections, kept apart from the configuration.
+//!
+//! The state file records what wgb knows about the profiles it brought up:
+//! whether they are connected, their interface, when they were connected and
+//! the last error. It lives under `$XDG_STATE_HOME`, or `/run` for root, so
+//! that `~/.wgbconf.json` is only written when the user changes a setting.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::backend::Device;
+use crate::status;
+use crate::{exec, file};
+
+/// Name of the state file, inside the state directory.
+pub const STATE_FILE_NAME: &str = "state.json";
+
+/// Environment variable overriding the path of the state file.
+pub const STATE_ENV: &str = "WGB_STATE";
+
+/// State directory of root, cleared at boot like the interfaces.
+pub const RUN_DIR: &str = "/run/wg-bridge";
+
+/// Errors raised while loading or saving the state.
+#[derive(Debug, Error)]
+pub enum StateError {
+    /// Neither `XDG_STATE_HOME` nor `HOME` is set.
+    #[error("unable to locate the state directory, HOME is not set")]
+    NoHome,
+
+    /// Reading or writing the file failed.
+    #[error("unable to access '{}': {source}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    /// The file is not a valid state file.
+    #[error("{}: malformed state file: {message}", path.display())]
+    Syntax { path: PathBuf, message: String },
+}
+
+/// What is known about a profile brought up or down by wgb.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProfileState {
+    pub connected: bool,
+    /// Interface brought up by the profile.
+    pub interface: Option<String>,
+    /// When the profile was connected, in seconds since the epoch.
+    pub connected_at: Option<u64>,
+    /// Last error met while connecting or disconnecting.
+    pub last_error: Option<String>,
+    /// When the last error happened, in seconds since the epoch.
+    pub failed_at: Option<u64>,
+}
+
+/// Content of the state file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct State {
+    /// State of the profiles, by path.
+    #[serde(default)]
+    pub profiles: BTreeMap<PathBuf, ProfileState>,
+}
+
+impl State {
+    /// Returns the path of the state file: `$WGB_STATE`, else
+    /// `$XDG_STATE_HOME/wg-bridge/state.json`, else `/run/wg-bridge/state.json`
+    /// for root and `~/.local/state/wg-bridge/state.json` for the others.
+    pub fn default_path() -> Result<PathBuf, StateError> {
+        let var = |name| env::var_os(name).filter(|value| !value.is_empty());
+        if let Some(path) = var(STATE_ENV) {
+            return Ok(PathBuf::from(path));
+        }
+        let dir = match var("XDG_STATE_HOME") {
+            Some(dir) => PathBuf::from(dir).join("wg-bridge"),
+            None if exec::is_root() => PathBuf::from(RUN_DIR),
+            None => {
+                PathBuf::from(var("HOME").ok_or(StateError::NoHome)?).join(".local/state/wg-bridge")
+            }
+        };
+        Ok(dir.join(STATE_FILE_NAME))
+    }
+
+    /// Reads the state file at `path`; a missing file is an empty state.
+    pub fn load(path: &Path) -> Result<Self, StateError> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(source) => {
+                return Err(StateError::Io {
+                    path: path.to_path_buf(),
+                    source,
+                })
+            }
+        };
+        serde_json::from_str(&text).map_err(|e| StateError::Syntax {
+            path: path.to_path_buf(),
+            message: e.to_string(),
+        })
+    }
+
+    /// Writes the state to `path`, creating its directory.
+    pub fn save(&self, path: &Path) -> Result<(), StateError> {
+        let io_err = |source| StateError::Io {
+            path: path.to_path_buf(),
+            source,
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(io_err)?;
+        }
+        let mut text = serde_json::to_string_pretty(self).map_err(|e| io_err(e.into()))?;
+        text.push('\n');
+        file::write_atomic(path, text.as_bytes(), 0o644).map_err(io_err)
+    }
+
+    /// Returns the profiles recorded as connected.
+    pub fn connected(&self) -> impl Iterator<Item = &Path> {
+        self.profiles
+            .iter()
+            .filter(|(_, state)| state.connected)
+            .map(|(path, _)| path.as_path())
+    }
+
+    /// Returns whether the profile at `path` is recorded as connected.
+    pub fn is_connected(&self, path: &Path) -> bool {
+        self.profiles.get(path).is_some_and(|state| state.connected)
+    }
+
+    /// Records that the profile at `path` brought up `interface`.
+    pub fn set_connected(&mut self, path: &Path, interface: &str) {
+        let state = self.profiles.entry(path.to_path_buf()).or_default();
+        state.connected = true;
+        state.interface = Some(interface.to_owned());
+        state.connected_at = Some(now());
+        state.last_error = None;
+        state.failed_at = None;
+    }
+
+    /// Records that the profile at `path` was brought down.
+    pub fn set_disconnected(&mut self, path: &Path) {
+        if let Some(state) = self.profiles.get_mut(path) {
+            state.connected = false;
+            state.connected_at = None;
+        }
+    }
+
+    /// Records the error met while bringing the profile at `path` up or
+    /// down.
+    pub fn set_error(&mut self, path: &Path, message: &str) {
+        let state = self.profiles.entry(path.to_path_buf()).or_default();
+        state.last_error = Some(message.to_owned());
+        state.failed_at = Some(now());
+    }
+
+    /// Updates the recorded connections from the live `devices`: the
+    /// records are only a cache of the kernel state, which changes behind
+    /// wgb when `wg-quick` is run directly, a connection fails or the
+    /// machine reboots.
+    ///
+    /// When several recorded profiles bring up the same interface, those
+    /// already connected keep the flag; if none is, all of them get it.
+    /// Returns whether a record changed.
+    pub fn reconcile(&mut self, devices: &[Device]) -> bool {
+        let flags: Vec<bool> = self
+            .profiles
+            .keys()
+            .map(|path| {
+                let Some(device) = devices.iter().find(|d| status::brings_up(path, &d.name)) else {
+                    return false;
+                };
+                let claimed = self.profiles.iter().any(|(other, state)| {
+                    state.connected && status::brings_up(other, &device.name)
+                });
+                self.is_connected(path) || !claimed
+            })
+            .collect();
+        let mut changed = false;
+        for (state, flag) in self.profiles.values_mut().zip(flags) {
+            if state.connected != flag {
+                state.connected = flag;
+                if !flag {
+                    state.connected_at = None;
+                }
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device(name: &str) -> Device {
+        Device {
+            name: name.to_owned(),
+            public_key: None,
+            listen_port: None,
+            fwmark: None,
+            peers: Vec::new(),
+        }
+    }
+
+    fn recorded(paths: &[&str]) -> State {
+        let mut state = State::default();
+        for path in paths {
+            state.set_connected(Path::new(path), "wg0");
+            state.set_disconnected(Path::new(path));
+        }
+        state
+    }
+
+    #[test]
+    fn flags_a_profile_connected_outside_wgb() {
+        let mut state = recorded(&["/etc/wireguard/office.conf"]);
+        assert!(state.reconcile(&[device("office")]));
+        assert!(state.is_connected(Path::new("/etc/wireguard/office.conf")));
+        assert!(!state.reconcile(&[device("office")]));
+    }
+
+    #[test]
+    fn clears_a_tunnel_gone_after_a_reboot() {
+        let path = Path::new("/etc/wireguard/office.conf");
+        let mut state = State::default();
+        state.set_connected(path, "office");
+        assert!(state.reconcile(&[device("home")]));
+        let profile = &state.profiles[path];
+        assert!(!profile.connected);
+        assert_eq!(profile.connected_at, None);
+        assert_eq!(profile.interface.as_deref(), Some("office"));
+        assert!(!state.reconcile(&[]));
+    }
+
+    #[test]
+    fn flags_every_profile_of_an_interface_when_none_is_connected() {
+        let paths = ["/etc/wireguard/office.conf", "/home/user/vpn/office.conf"];
+        let mut state = recorded(&paths);
+        assert!(state.reconcile(&[device("office")]));
+        assert_eq!(state.connected().count(), 2);
+    }
+
+    #[test]
+    fn keeps_the_connected_profile_of_a_shared_interface() {
+        let ours = Path::new("/home/user/vpn/office.conf");
+        let mut state = recorded(&["/etc/wireguard/office.conf"]);
+        state.set_connected(ours, "office");
+        assert!(!state.reconcile(&[device("office")]));
+        assert_eq!(state.connected().collect::<Vec<_>>(), [ours]);
+    }
+}
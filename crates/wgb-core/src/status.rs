@@ -0,0 +1,521 @@
+//! Status of the WireGuard interfaces, tied to the profiles that brought
+//! them up.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::backend::{self, Device, PeerState};
+
+/// Age after which a handshake is stale. WireGuard renews the session every
+/// two minutes while traffic flows and rejects it after three.
+pub const STALE_HANDSHAKE: Duration = Duration::from_secs(180);
+
+/// State of an interface and of its peers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InterfaceStatus {
+    pub device: Device,
+    /// Profile that brought the interface up, if known.
+    pub profile: Option<PathBuf>,
+    pub peers: Vec<PeerStatus>,
+}
+
+/// State of a peer, with the age of its latest handshake.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerStatus {
+    pub state: PeerState,
+    /// Time elapsed since the latest handshake, `None` if none happened.
+    pub handshake_age: Option<Duration>,
+}
+
+impl PeerStatus {
+    fn new(state: PeerState, now: SystemTime) -> Self {
+        let handshake_age = state
+            .latest_handshake
+            .map(|time| now.duration_since(time).unwrap_or_default());
+        Self {
+            state,
+            handshake_age,
+        }
+    }
+
+    /// Returns whether the session with the peer looks dead: its latest
+    /// handshake is older than [`STALE_HANDSHAKE`], or there was none while
+    /// the peer has an endpoint to reach.
+    pub fn is_stale(&self) -> bool {
+        match self.handshake_age {
+            Some(age) => age > STALE_HANDSHAKE,
+            None => self.state.endpoint.is_some(),
+        }
+    }
+}
+
+impl InterfaceStatus {
+    /// Returns the warnings about the peers of the interface.
+    pub fn warnings(&self) -> Vec<String> {
+        self.peers
+            .iter()
+            .filter(|peer| peer.is_stale())
+            .map(|peer| match peer.handshake_age {
+                Some(age) => format!(
+                    "{}: no handshake with peer {} for {} seconds",
+                    self.device.name,
+                    peer.state.public_key,
+                    age.as_secs()
+                ),
+                None => format!(
+                    "{}: no handshake with peer {} yet",
+                    self.device.name, peer.state.public_key
+                ),
+            })
+            .collect()
+    }
+}
+
+/// Builds the status of `devices` at `now`, tying each interface to the
+/// profile among `profiles` that brings up an interface of the same name.
+/// Earlier profiles take precedence.
+pub fn build(devices: Vec<Device>, profiles: &[PathBuf], now: SystemTime) -> Vec<InterfaceStatus> {
+    devices
+        .into_iter()
+        .map(|device| {
+            let profile = profiles
+                .iter()
+                .find(|path| brings_up(path, &device.name))
+                .cloned();
+            let peers = device
+                .peers
+                .iter()
+                .cloned()
+                .map(|state| PeerStatus::new(state, now))
+                .collect();
+            InterfaceStatus {
+                device,
+                profile,
+                peers,
+            }
+        })
+        .collect()
+}
+
+/// Returns whether the profile at `path` brings up the interface `name`.
+pub fn brings_up(path: &Path, name: &str) -> bool {
+    backend::interface_name(path).is_ok_and(|interface| interface == name)
+}
+
+/// Transfer rates of a peer between two samples, in bytes per second.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rates {
+    pub rx: f64,
+    pub tx: f64,
+}
+
+/// Something that happened between two samples.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// The interface appeared.
+    Up { interface: String },
+    /// The interface disappeared.
+    Down { interface: String },
+    /// A new handshake completed with the peer; `after` is the time elapsed
+    /// since the previous one, if there was one.
+    Handshake {
+        interface: String,
+        peer: String,
+        after: Option<Duration>,
+    },
+    /// The endpoint of the peer changed.
+    Roamed {
+        interface: String,
+        peer: String,
+        endpoint: Option<SocketAddr>,
+    },
+    /// The transfer counters of the peer went back, the interface or the
+    /// peer was recreated.
+    Reset { interface: String, peer: String },
+}
+
+impl Event {
+    /// Returns the name of the kind of event, e.g. `handshake`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Up { .. } => "up",
+            Self::Down { .. } => "down",
+            Self::Handshake { .. } => "handshake",
+            Self::Roamed { .. } => "roamed",
+            Self::Reset { .. } => "reset",
+        }
+    }
+
+    /// Returns the interface concerned.
+    pub fn interface(&self) -> &str {
+        match self {
+            Self::Up { interface }
+            | Self::Down { interface }
+            | Self::Handshake { interface, .. }
+            | Self::Roamed { interface, .. }
+            | Self::Reset { interface, .. } => interface,
+        }
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Up { interface } => write!(f, "{interface}: up"),
+            Self::Down { interface } => write!(f, "{interface}: down"),
+            Self::Handshake {
+                interface,
+                peer,
+                after: Some(after),
+            } => write!(
+                f,
+                "{interface}: handshake with {peer} after {} seconds",
+                after.as_secs()
+            ),
+            Self::Handshake {
+                interface, peer, ..
+            } => write!(f, "{interface}: first handshake with {peer}"),
+            Self::Roamed {
+                interface,
+                peer,
+                endpoint: Some(endpoint),
+            } => write!(f, "{interface}: {peer} moved to {endpoint}"),
+            Self::Roamed {
+                interface, peer, ..
+            } => write!(f, "{interface}: {peer} lost its endpoint"),
+            Self::Reset { interface, peer } => {
+                write!(f, "{interface}: counters of {peer} were reset")
+            }
+        }
+    }
+}
+
+/// Differences between two samples of the status.
+#[derive(Debug, Clone, Default)]
+pub struct Changes {
+    /// Rates by interface and peer public key.
+    pub rates: BTreeMap<(String, String), Rates>,
+    /// Peers, by interface and public key, whose handshake was renewed.
+    pub renewed: BTreeSet<(String, String)>,
+    pub events: Vec<Event>,
+}
+
+/// Compares the sample `next`, taken `elapsed` after `previous`.
+pub fn compare(
+    previous: &[InterfaceStatus],
+    next: &[InterfaceStatus],
+    elapsed: Duration,
+) -> Changes {
+    let mut changes = Changes::default();
+    fn find<'a>(statuses: &'a [InterfaceStatus], name: &str) -> Option<&'a InterfaceStatus> {
+        statuses.iter().find(|s| s.device.name == name)
+    }
+    for old in previous {
+        if find(next, &old.device.name).is_none() {
+            changes.events.push(Event::Down {
+                interface: old.device.name.clone(),
+            });
+        }
+    }
+    for new in next {
+        let interface = new.device.name.clone();
+        let Some(old) = find(previous, &interface) else {
+            changes.events.push(Event::Up { interface });
+            continue;
+        };
+        for peer in &new.peers {
+            let key = (interface.clone(), peer.state.public_key.clone());
+            let Some(before) = old
+                .peers
+                .iter()
+                .find(|p| p.state.public_key == peer.state.public_key)
+            else {
+                continue;
+            };
+            let (now, then) = (&peer.state, &before.state);
+            if now.rx_bytes < then.rx_bytes || now.tx_bytes < then.tx_bytes {
+                changes.events.push(Event::Reset {
+                    interface: interface.clone(),
+                    peer: now.public_key.clone(),
+                });
+            } else if !elapsed.is_zero() {
+                let secs = elapsed.as_secs_f64();
+                changes.rates.insert(
+                    key.clone(),
+                    Rates {
+                        rx: (now.rx_bytes - then.rx_bytes) as f64 / secs,
+                        tx: (now.tx_bytes - then.tx_bytes) as f64 / secs,
+                    },
+                );
+            }
+            if now.latest_handshake.is_some() && now.latest_handshake != then.latest_handshake {
+                changes.events.push(Event::Handshake {
+                    interface: interface.clone(),
+                    peer: now.public_key.clone(),
+                    after: now
+                        .latest_handshake
+                        .zip(then.latest_handshake)
+                        .and_then(|(now, then)| now.duration_since(then).ok()),
+                });
+                changes.renewed.insert(key);
+            }
+            if now.endpoint != then.endpoint {
+                changes.events.push(Event::Roamed {
+                    interface: interface.clone(),
+                    peer: now.public_key.clone(),
+                    endpoint: now.endpoint,
+                });
+            }
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::UNIX_EPOCH;
+
+    use super::*;
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    fn peer(public_key: &str, handshake: Option<u64>, endpoint: Option<&str>) -> PeerState {
+        PeerState {
+            public_key: public_key.to_owned(),
+            endpoint: endpoint.map(|e| e.parse().unwrap()),
+            allowed_ips: Vec::new(),
+            latest_handshake: handshake.map(at),
+            rx_bytes: 0,
+            tx_bytes: 0,
+            persistent_keepalive: None,
+        }
+    }
+
+    fn device(name: &str, peers: Vec<PeerState>) -> Device {
+        Device {
+            name: name.to_owned(),
+            public_key: None,
+            listen_port: None,
+            fwmark: None,
+            peers,
+        }
+    }
+
+    #[test]
+    fn ties_the_interfaces_to_their_profile() {
+        let profiles = [
+            PathBuf::from("/etc/wireguard/office.conf"),
+            PathBuf::from("/home/alice/vpn/office.conf"),
+            PathBuf::from("/home/alice/vpn/home.conf.age"),
+        ];
+        let devices = vec![
+            device("office", Vec::new()),
+            device("home", Vec::new()),
+            device("wg0", Vec::new()),
+        ];
+        let statuses = build(devices, &profiles, at(0));
+        let tied: Vec<Option<&Path>> = statuses.iter().map(|s| s.profile.as_deref()).collect();
+        assert_eq!(
+            tied,
+            [
+                Some(profiles[0].as_path()),
+                Some(profiles[2].as_path()),
+                None
+            ]
+        );
+        assert!(brings_up(&profiles[1], "office"));
+        assert!(!brings_up(Path::new("/vpn/a name.conf"), "a name"));
+    }
+
+    #[test]
+    fn ages_the_handshakes_at_the_time_of_the_sample() {
+        let devices = vec![device(
+            "office",
+            vec![peer("recent", Some(1000), None), peer("never", None, None)],
+        )];
+        let statuses = build(devices, &[], at(1042));
+        let ages: Vec<Option<Duration>> =
+            statuses[0].peers.iter().map(|p| p.handshake_age).collect();
+        assert_eq!(ages, [Some(Duration::from_secs(42)), None]);
+
+        // A handshake in the future, the clock having moved back, is fresh.
+        let statuses = build(
+            vec![device("office", vec![peer("ahead", Some(2000), None)])],
+            &[],
+            at(1000),
+        );
+        assert_eq!(statuses[0].peers[0].handshake_age, Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn handshakes_are_stale_after_three_minutes() {
+        let stale = |handshake: Option<u64>, endpoint: Option<&str>| {
+            let devices = vec![device("office", vec![peer("key", handshake, endpoint)])];
+            build(devices, &[], at(10_000)).remove(0).peers[0].is_stale()
+        };
+        let limit = 10_000 - STALE_HANDSHAKE.as_secs();
+        assert!(!stale(Some(limit), None));
+        assert!(stale(Some(limit - 1), None));
+        assert!(stale(None, Some("203.0.113.5:51820")));
+        assert!(!stale(None, None));
+    }
+
+    #[test]
+    fn warns_about_the_stale_peers() {
+        let devices = vec![device(
+            "office",
+            vec![
+                peer("fresh", Some(9_990), Some("203.0.113.5:51820")),
+                peer("old", Some(9_000), Some("203.0.113.6:51820")),
+                peer("silent", None, Some("203.0.113.7:51820")),
+            ],
+        )];
+        let statuses = build(devices, &[], at(10_000));
+        assert_eq!(
+            statuses[0].warnings(),
+            [
+                "office: no handshake with peer old for 1000 seconds",
+                "office: no handshake with peer silent yet",
+            ]
+        );
+    }
+
+    /// Samples `office` with one peer having transferred `rx` and `tx`
+    /// bytes, its latest handshake at `handshake`.
+    fn sample(rx: u64, tx: u64, handshake: Option<u64>, endpoint: &str) -> Vec<InterfaceStatus> {
+        let mut state = peer("key", handshake, Some(endpoint));
+        state.rx_bytes = rx;
+        state.tx_bytes = tx;
+        build(vec![device("office", vec![state])], &[], at(10_000))
+    }
+
+    fn key() -> (String, String) {
+        ("office".to_owned(), "key".to_owned())
+    }
+
+    const ENDPOINT: &str = "203.0.113.5:51820";
+
+    #[test]
+    fn computes_the_rates_between_two_samples() {
+        let before = sample(1_000, 500, Some(9_000), ENDPOINT);
+        let after = sample(5_000, 1_500, Some(9_000), ENDPOINT);
+        let changes = compare(&before, &after, Duration::from_secs(2));
+        assert_eq!(
+            changes.rates[&key()],
+            Rates {
+                rx: 2_000.0,
+                tx: 500.0
+            }
+        );
+        assert!(changes.events.is_empty());
+        assert!(changes.renewed.is_empty());
+
+        let changes = compare(&before, &after, Duration::from_millis(500));
+        assert_eq!(changes.rates[&key()].rx, 8_000.0);
+    }
+
+    #[test]
+    fn computes_no_rate_without_time_elapsed() {
+        let before = sample(1_000, 500, None, ENDPOINT);
+        let after = sample(5_000, 1_500, None, ENDPOINT);
+        let changes = compare(&before, &after, Duration::ZERO);
+        assert!(changes.rates.is_empty());
+        assert!(changes.events.is_empty());
+    }
+
+    #[test]
+    fn reports_counters_going_back() {
+        let before = sample(5_000, 1_500, Some(9_000), ENDPOINT);
+        for after in [
+            sample(100, 1_500, Some(9_000), ENDPOINT),
+            sample(5_000, 10, Some(9_000), ENDPOINT),
+        ] {
+            let changes = compare(&before, &after, Duration::from_secs(2));
+            assert!(changes.rates.is_empty());
+            assert_eq!(
+                changes.events,
+                [Event::Reset {
+                    interface: "office".to_owned(),
+                    peer: "key".to_owned()
+                }]
+            );
+        }
+    }
+
+    #[test]
+    fn reports_the_renewed_handshakes() {
+        let first = sample(0, 0, None, ENDPOINT);
+        let second = sample(0, 0, Some(9_000), ENDPOINT);
+        let third = sample(0, 0, Some(9_120), ENDPOINT);
+
+        let changes = compare(&first, &second, Duration::from_secs(2));
+        assert_eq!(
+            changes.events,
+            [Event::Handshake {
+                interface: "office".to_owned(),
+                peer: "key".to_owned(),
+                after: None
+            }]
+        );
+        assert_eq!(
+            changes.events[0].to_string(),
+            "office: first handshake with key"
+        );
+        assert!(changes.renewed.contains(&key()));
+
+        let changes = compare(&second, &third, Duration::from_secs(2));
+        assert_eq!(
+            changes.events[0].to_string(),
+            "office: handshake with key after 120 seconds"
+        );
+        assert!(changes.renewed.contains(&key()));
+
+        let changes = compare(&third, &third, Duration::from_secs(2));
+        assert!(changes.events.is_empty());
+        assert!(changes.renewed.is_empty());
+    }
+
+    #[test]
+    fn reports_a_roaming_peer() {
+        let before = sample(0, 0, Some(9_000), ENDPOINT);
+        let after = sample(0, 0, Some(9_000), "198.51.100.9:4500");
+        let changes = compare(&before, &after, Duration::from_secs(2));
+        assert_eq!(changes.events.len(), 1);
+        assert_eq!(changes.events[0].kind(), "roamed");
+        assert_eq!(
+            changes.events[0].to_string(),
+            "office: key moved to 198.51.100.9:4500"
+        );
+    }
+
+    #[test]
+    fn reports_a_reconnection() {
+        let connected = sample(5_000, 1_500, Some(9_000), ENDPOINT);
+        let changes = compare(&connected, &[], Duration::from_secs(2));
+        assert_eq!(
+            changes.events,
+            [Event::Down {
+                interface: "office".to_owned()
+            }]
+        );
+
+        let changes = compare(&[], &connected, Duration::from_secs(2));
+        assert_eq!(changes.events[0].to_string(), "office: up");
+        assert!(changes.rates.is_empty());
+
+        // Reconnected between two samples: the counters start over with a
+        // new handshake.
+        let reconnected = sample(200, 100, Some(9_990), ENDPOINT);
+        let changes = compare(&connected, &reconnected, Duration::from_secs(2));
+        let kinds: Vec<&str> = changes.events.iter().map(Event::kind).collect();
+        assert_eq!(kinds, ["reset", "handshake"]);
+        assert!(changes.events.iter().all(|e| e.interface() == "office"));
+        assert!(changes.rates.is_empty());
+        assert!(changes.renewed.contains(&key()));
+    }
+}
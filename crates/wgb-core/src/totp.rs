@@ -0,0 +1,387 @@
+//! Time-based one-time passwords (RFC 6238) for the 2FA step.
+//!
+//! For the profiles whose TOTP seed is known, wgb computes the code itself
+//! and submits it to the 2FA page, see
+//! [`FormAuthenticator`](crate::auth::FormAuthenticator). The seeds are kept
+//! in a file only readable by the user, apart from `~/.wgbconf.json`, and
+//! wiped from memory once used.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs::{self, DirBuilder};
+use std::io;
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use hmac::digest::core_api::BlockSizeUser;
+use hmac::digest::Digest;
+use hmac::{Mac, SimpleHmac};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::file;
+
+/// Name of the seed file, inside the configuration directory.
+pub const SEEDS_FILE_NAME: &str = "totp.json";
+
+/// Environment variable overriding the path of the seed file.
+pub const SEEDS_ENV: &str = "WGB_TOTP_SEEDS";
+
+/// Errors raised while reading or storing the seeds.
+#[derive(Debug, Error)]
+pub enum TotpError {
+    /// Neither `XDG_CONFIG_HOME` nor `HOME` is set.
+    #[error("unable to locate the configuration directory, HOME is not set")]
+    NoHome,
+
+    /// Reading or writing the seed file failed.
+    #[error("unable to access '{}': {source}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    /// The seed file is not valid.
+    #[error("{}: malformed seed file: {message}", path.display())]
+    Syntax { path: PathBuf, message: String },
+
+    /// Other users may read the seed file.
+    #[error(
+        "'{}' is readable by other users, restrict it with: chmod 600 '{0}'",
+        path.display()
+    )]
+    Exposed { path: PathBuf },
+
+    /// The seed is not a base32 string.
+    #[error("the TOTP seed is not a valid base32 string")]
+    InvalidSeed,
+
+    /// No seed is stored for the profile, identified by its ID.
+    #[error("no TOTP seed stored for the profile {0}")]
+    NoSeed(String),
+}
+
+/// Hash function of the HMAC.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Algorithm {
+    #[default]
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Sha1 => "SHA1",
+            Self::Sha256 => "SHA256",
+            Self::Sha512 => "SHA512",
+        })
+    }
+}
+
+/// How the codes of a profile are computed, stored in `confs[].auth.totp`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct Settings {
+    /// Hash function of the HMAC.
+    #[serde(default)]
+    pub algorithm: Algorithm,
+
+    /// Number of digits of the code.
+    #[serde(default = "default_digits")]
+    pub digits: u32,
+
+    /// Validity of a code, in seconds.
+    #[serde(default = "default_period")]
+    pub period: u64,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            algorithm: Algorithm::default(),
+            digits: default_digits(),
+            period: default_period(),
+        }
+    }
+}
+
+fn default_digits() -> u32 {
+    6
+}
+
+fn default_period() -> u64 {
+    30
+}
+
+impl Settings {
+    /// Returns why the settings cannot produce codes, if they cannot.
+    pub fn check(&self) -> Result<(), String> {
+        if !(6..=8).contains(&self.digits) {
+            return Err(format!("digits is {}, expected 6 to 8", self.digits));
+        }
+        if self.period == 0 {
+            return Err("period must be at least one second".to_owned());
+        }
+        Ok(())
+    }
+
+    /// Returns the code valid at `time` for `seed`.
+    pub fn code(&self, seed: &Seed, time: SystemTime) -> String {
+        let secs = time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let counter = (secs / self.period.max(1)).to_be_bytes();
+        let digest = match self.algorithm {
+            Algorithm::Sha1 => hmac::<sha1::Sha1>(&seed.0, &counter),
+            Algorithm::Sha256 => hmac::<sha2::Sha256>(&seed.0, &counter),
+            Algorithm::Sha512 => hmac::<sha2::Sha512>(&seed.0, &counter),
+        };
+        // Dynamic truncation, RFC 4226 section 5.3.
+        let offset = usize::from(digest[digest.len() - 1] & 0x0f);
+        let value = u32::from_be_bytes([
+            digest[offset] & 0x7f,
+            digest[offset + 1],
+            digest[offset + 2],
+            digest[offset + 3],
+        ]);
+        let code = u64::from(value) % 10u64.pow(self.digits);
+        format!("{code:0width$}", width = self.digits as usize)
+    }
+
+    /// Returns how long the code computed at `time` remains valid.
+    pub fn remaining(&self, time: SystemTime) -> Duration {
+        let secs = time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let period = self.period.max(1);
+        Duration::from_secs(period - secs % period)
+    }
+}
+
+fn hmac<D: Digest + BlockSizeUser>(key: &[u8], message: &[u8]) -> Zeroizing<Vec<u8>> {
+    let mut mac = SimpleHmac::<D>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(message);
+    Zeroizing::new(mac.finalize().into_bytes().to_vec())
+}
+
+/// Secret key shared with the 2FA server, wiped when dropped.
+pub struct Seed(Zeroizing<Vec<u8>>);
+
+impl Seed {
+    /// Decodes a base32 seed, as shown by the 2FA enrolment page. Spaces,
+    /// dashes, padding and lowercase letters are accepted.
+    pub fn from_base32(text: &str) -> Result<Self, TotpError> {
+        let normalized: Zeroizing<String> = Zeroizing::new(
+            text.chars()
+                .filter(|c| !matches!(c, ' ' | '-' | '=') && !c.is_whitespace())
+                .map(|c| c.to_ascii_uppercase())
+                .collect(),
+        );
+        let bytes = base32::decode(base32::Alphabet::Rfc4648 { padding: false }, &normalized)
+            .filter(|bytes| !bytes.is_empty())
+            .ok_or(TotpError::InvalidSeed);
+        bytes.map(|bytes| Self(Zeroizing::new(bytes)))
+    }
+}
+
+impl fmt::Debug for Seed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Seed(..)")
+    }
+}
+
+/// Content of the seed file: the base32 seeds, by profile ID, see
+/// [`ConfEntry::id`](crate::config::ConfEntry::id).
+#[derive(Default, Serialize, Deserialize)]
+pub struct Seeds {
+    #[serde(default)]
+    seeds: BTreeMap<String, String>,
+}
+
+impl Drop for Seeds {
+    fn drop(&mut self) {
+        for seed in self.seeds.values_mut() {
+            seed.zeroize();
+        }
+    }
+}
+
+impl Seeds {
+    /// Returns the path of the seed file: `$WGB_TOTP_SEEDS`, else
+    /// `$XDG_CONFIG_HOME/wg-bridge/totp.json`, else
+    /// `~/.config/wg-bridge/totp.json`.
+    pub fn default_path() -> Result<PathBuf, TotpError> {
+        if let Some(path) = env::var_os(SEEDS_ENV).filter(|path| !path.is_empty()) {
+            return Ok(PathBuf::from(path));
+        }
+        let dir = file::config_dir().ok_or(TotpError::NoHome)?;
+        Ok(dir.join(SEEDS_FILE_NAME))
+    }
+
+    /// Reads the seed file at `path`; a missing file holds no seed. A file
+    /// other users may read is refused.
+    pub fn load(path: &Path) -> Result<Self, TotpError> {
+        let io_err = |source| TotpError::Io {
+            path: path.to_path_buf(),
+            source,
+        };
+        let meta = match fs::metadata(path) {
+            Ok(meta) => meta,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(io_err(err)),
+        };
+        if meta.permissions().mode() & 0o077 != 0 {
+            return Err(TotpError::Exposed {
+                path: path.to_path_buf(),
+            });
+        }
+        let text = Zeroizing::new(fs::read_to_string(path).map_err(io_err)?);
+        serde_json::from_str(&text).map_err(|e| TotpError::Syntax {
+            path: path.to_path_buf(),
+            message: e.to_string(),
+        })
+    }
+
+    /// Writes the seeds to `path`, readable by the user only, creating its
+    /// directory.
+    pub fn save(&self, path: &Path) -> Result<(), TotpError> {
+        let io_err = |source| TotpError::Io {
+            path: path.to_path_buf(),
+            source,
+        };
+        if let Some(dir) = path.parent().filter(|dir| !dir.exists()) {
+            DirBuilder::new()
+                .recursive(true)
+                .mode(0o700)
+                .create(dir)
+                .map_err(io_err)?;
+        }
+        let mut text =
+            Zeroizing::new(serde_json::to_string_pretty(self).map_err(|e| io_err(e.into()))?);
+        text.push('\n');
+        file::write_atomic(path, text.as_bytes(), 0o600).map_err(io_err)
+    }
+
+    /// Returns the seed of the profile `id`.
+    pub fn get(&self, id: &str) -> Result<Seed, TotpError> {
+        let text = self
+            .seeds
+            .get(id)
+            .ok_or_else(|| TotpError::NoSeed(id.to_owned()))?;
+        Seed::from_base32(text)
+    }
+
+    /// Returns whether a seed is stored for the profile `id`.
+    pub fn contains(&self, id: &str) -> bool {
+        self.seeds.contains_key(id)
+    }
+
+    /// Stores `seed`, in base32, for the profile `id`, once checked.
+    pub fn set(&mut self, id: &str, seed: &str) -> Result<(), TotpError> {
+        Seed::from_base32(seed)?;
+        let normalized = seed
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .map(|c| c.to_ascii_uppercase())
+            .collect();
+        if let Some(mut old) = self.seeds.insert(id.to_owned(), normalized) {
+            old.zeroize();
+        }
+        Ok(())
+    }
+
+    /// Forgets the seed of the profile `id`; returns whether there was one.
+    pub fn remove(&mut self, id: &str) -> bool {
+        match self.seeds.remove(id) {
+            Some(mut old) => {
+                old.zeroize();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Moves the seeds stored by profile path, before the profiles had an
+    /// ID, to the ID `id_of` gives for the path. Returns whether a seed
+    /// moved.
+    pub fn upgrade<'a>(&mut self, id_of: impl Fn(&Path) -> Option<&'a str>) -> bool {
+        let moved: Vec<(String, &str)> = self
+            .seeds
+            .keys()
+            .filter(|key| key.starts_with('/'))
+            .filter_map(|key| Some((key.clone(), id_of(Path::new(key))?)))
+            .collect();
+        for (path, id) in &moved {
+            if let Some(seed) = self.seeds.remove(path) {
+                self.seeds.insert((*id).to_owned(), seed);
+            }
+        }
+        !moved.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test vectors of RFC 6238, appendix B: time, then the SHA1, SHA256
+    /// and SHA512 codes.
+    const VECTORS: [(u64, [&str; 3]); 6] = [
+        (59, ["94287082", "46119246", "90693936"]),
+        (1111111109, ["07081804", "68084774", "25091201"]),
+        (1111111111, ["14050471", "67062674", "99943326"]),
+        (1234567890, ["89005924", "91819424", "93441116"]),
+        (2000000000, ["69279037", "90698825", "38618901"]),
+        (20000000000, ["65353130", "77737706", "47863826"]),
+    ];
+
+    /// Returns the seed of the RFC for `algorithm`: the ASCII digits
+    /// repeated to the length of its digest.
+    fn rfc_seed(algorithm: Algorithm) -> Seed {
+        let len = match algorithm {
+            Algorithm::Sha1 => 20,
+            Algorithm::Sha256 => 32,
+            Algorithm::Sha512 => 64,
+        };
+        Seed(Zeroizing::new(b"1234567890".repeat(7)[..len].to_vec()))
+    }
+
+    #[test]
+    fn computes_the_codes_of_rfc_6238() {
+        let algorithms = [Algorithm::Sha1, Algorithm::Sha256, Algorithm::Sha512];
+        for (i, algorithm) in algorithms.into_iter().enumerate() {
+            let settings = Settings {
+                algorithm,
+                digits: 8,
+                period: 30,
+            };
+            let seed = rfc_seed(algorithm);
+            for (secs, codes) in VECTORS {
+                let time = UNIX_EPOCH + Duration::from_secs(secs);
+                assert_eq!(
+                    settings.code(&seed, time),
+                    codes[i],
+                    "{algorithm} at {secs}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn decodes_base32_seeds() {
+        let seed = Seed::from_base32("gezd gnbv-gy3t qojq GEZDGNBVGY3TQOJQ====").unwrap();
+        assert_eq!(*seed.0, b"12345678901234567890");
+        assert!(Seed::from_base32("not base32!").is_err());
+    }
+}
@@ -0,0 +1,311 @@
+//! Generates the man pages of wgb: wgb(1) and a page per command from the
+//! definition of the command line, `src/cli.rs`, and wgbconf(5) from the
+//! schema of the configuration file.
+//!
+//! The pages are written to `$OUT_DIR/man`, listed in `$OUT_DIR/man.rs`
+//! for the binary to install them, see `src/man.rs`.
+
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use clap::CommandFactory;
+use clap_mangen::roff::{bold, italic, roman, Roff};
+use clap_mangen::Man;
+use schemars::schema_for;
+use serde_json::Value;
+use wgb_core::catalog::ErrorCode;
+use wgb_core::config::{ConfEntry, Config, CONFIG_FILE_NAME, DEFAULT_SEARCH_PATH};
+use wgb_core::log::LOG_FILE;
+
+#[allow(dead_code)]
+#[path = "src/cli.rs"]
+mod cli;
+
+/// Stand-ins for the completion of the profiles, which the command line
+/// refers to but the pages do not need.
+mod complete {
+    use clap_complete::engine::CompletionCandidate;
+
+    pub fn profile_names() -> Vec<CompletionCandidate> {
+        Vec::new()
+    }
+
+    pub fn connected_names() -> Vec<CompletionCandidate> {
+        Vec::new()
+    }
+}
+
+fn main() -> io::Result<()> {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=src/cli.rs");
+    let out_dir = PathBuf::from(env::var_os("OUT_DIR").expect("OUT_DIR is set by cargo"));
+    let man_dir = out_dir.join("man");
+    if man_dir.exists() {
+        fs::remove_dir_all(&man_dir)?;
+    }
+    fs::create_dir_all(&man_dir)?;
+
+    let cmd = cli::Cli::command().version(env!("CARGO_PKG_VERSION"));
+    clap_mangen::generate_to(cmd.clone(), &man_dir)?;
+    // The main page also documents the exit statuses and the files.
+    let mut page = Vec::new();
+    Man::new(cmd).render(&mut page)?;
+    wgb_sections().to_writer(&mut page)?;
+    fs::write(man_dir.join("wgb.1"), page)?;
+    fs::write(man_dir.join("wgbconf.5"), wgbconf().render())?;
+
+    write_index(&man_dir, &out_dir.join("man.rs"))
+}
+
+/// Lists the pages in `man_dir` as `PAGES`, embedding their content.
+fn write_index(man_dir: &Path, index: &Path) -> io::Result<()> {
+    let mut names: Vec<String> = fs::read_dir(man_dir)?
+        .map(|entry| entry.map(|entry| entry.file_name().to_string_lossy().into_owned()))
+        .collect::<Result<_, _>>()?;
+    names.sort();
+    let mut text = String::from("pub const PAGES: &[(&str, &str)] = &[\n");
+    for name in names {
+        let path = man_dir.join(&name);
+        let _ = writeln!(text, "    ({name:?}, include_str!({path:?})),");
+    }
+    text.push_str("];\n");
+    fs::write(index, text)
+}
+
+/// Sections appended to wgb(1).
+fn wgb_sections() -> Roff {
+    let mut roff = Roff::new();
+    roff.control("SH", ["EXIT STATUS"]).text([roman(
+        "wgb exits with 0 on success. Otherwise it prints the code, the message and the \
+         cause of the error, followed by a hint; with --output json or yaml, the error is \
+         printed as a document holding them. The codes and exit statuses are stable:",
+    )]);
+    for code in ErrorCode::ALL {
+        roff.control("TP", [])
+            .text([
+                bold(code.code()),
+                roman(" "),
+                italic(code.name()),
+                roman(format!(" (exit status {})", code.exit_code())),
+            ])
+            .text([roman(code.message())]);
+    }
+    roff.control("SH", ["FILES"]);
+    for (file, description) in [
+        (
+            format!("~/{CONFIG_FILE_NAME}"),
+            "Configuration file, see wgbconf(5).".to_owned(),
+        ),
+        (
+            DEFAULT_SEARCH_PATH.to_owned(),
+            "Directory always searched for profiles.".to_owned(),
+        ),
+        (LOG_FILE.to_owned(), "Default log file.".to_owned()),
+    ] {
+        roff.control("TP", [])
+            .text([bold(file)])
+            .text([roman(description)]);
+    }
+    roff.control("SH", ["SEE ALSO"])
+        .text([roman("wgbconf(5), wg(8), wg-quick(8)")]);
+    roff
+}
+
+/// Builds wgbconf(5) from the schema of [`Config`].
+fn wgbconf() -> Roff {
+    let schema = schema_for!(Config).to_value();
+    let defs = schema.get("$defs").cloned().unwrap_or(Value::Null);
+    let mut roff = Roff::new();
+    roff.control(
+        "TH",
+        [
+            "WGBCONF",
+            "5",
+            "",
+            &format!("wgb {}", env!("CARGO_PKG_VERSION")),
+        ],
+    )
+    .control("SH", ["NAME"])
+    .text([roman("wgbconf - configuration file of wgb")])
+    .control("SH", ["SYNOPSIS"])
+    .text([bold(format!("~/{CONFIG_FILE_NAME}"))])
+    .control("SH", ["DESCRIPTION"])
+    .text([roman(format!(
+        "The configuration file of wgb is a JSON object; unknown properties are refused. \
+         wgb takes the lock file ~/{CONFIG_FILE_NAME}.lock while it changes the file, and \
+         replaces it atomically. A file written by an older version is upgraded when wgb \
+         reads it, the original being kept as ~/{CONFIG_FILE_NAME}.v<version>.bak."
+    ))])
+    .control("SH", ["PROPERTIES"]);
+    document_properties(&mut roff, &schema, &defs, "");
+
+    let mut example = Config::default();
+    example.conf_path.push(PathBuf::from("/home/user/vpn"));
+    let mut entry = ConfEntry::new("/etc/wireguard/office.conf");
+    // A fixed identifier keeps the page the same from one build to the next.
+    entry.id = "3f9a1c07b2e4".to_owned();
+    entry.alias = Some("work".to_owned());
+    entry.token = true;
+    entry.uri = "https://vpn.example.com/2fa".to_owned();
+    example.confs.push(entry);
+    let example = serde_json::to_string_pretty(&example).expect("the configuration serializes");
+    roff.control("SH", ["EXAMPLE"])
+        .control("nf", [])
+        .text([roman(example)])
+        .control("fi", [])
+        .control("SH", ["SEE ALSO"])
+        .text([roman("wgb(1)")]);
+    roff
+}
+
+/// Documents the properties of the object described by `schema`, their
+/// names starting with `prefix`.
+fn document_properties(roff: &mut Roff, schema: &Value, defs: &Value, prefix: &str) {
+    let Some(properties) = schema.get("properties").and_then(Value::as_object) else {
+        return;
+    };
+    for (name, property) in properties {
+        let description = property
+            .get("description")
+            .and_then(Value::as_str)
+            .map(plain);
+        let property = resolve(property, defs);
+        let name = format!("{prefix}{name}");
+        roff.control("TP", []).text([
+            bold(&name),
+            roman(format!(" ({})", type_name(property, defs))),
+        ]);
+        let description = description.or_else(|| {
+            property
+                .get("description")
+                .and_then(Value::as_str)
+                .map(plain)
+        });
+        if let Some(description) = description {
+            roff.text([roman(description)]);
+        }
+        if let Some(values) = property.get("enum").and_then(Value::as_array) {
+            let values: Vec<String> = values.iter().map(ToString::to_string).collect();
+            roff.text([roman(format!("One of {}.", values.join(", ")))]);
+        }
+        let items = property
+            .get("items")
+            .map(|items| resolve(items, defs))
+            .filter(|items| items.get("properties").is_some());
+        if let Some(items) = items {
+            document_properties(roff, items, defs, &format!("{name}[]."));
+        } else if let Some(variants) = property.get("oneOf").and_then(Value::as_array) {
+            for variant in variants {
+                variant_properties(roff, variant, defs, &name);
+            }
+        } else {
+            document_properties(roff, property, defs, &format!("{name}."));
+        }
+    }
+}
+
+/// Documents a variant of the tagged object `name`: its tag first, then its
+/// other properties.
+fn variant_properties(roff: &mut Roff, variant: &Value, defs: &Value, name: &str) {
+    let Some(properties) = variant.get("properties").and_then(Value::as_object) else {
+        return;
+    };
+    let Some((tag, value)) = properties
+        .iter()
+        .find_map(|(tag, p)| p.get("const").map(|value| (tag, value)))
+    else {
+        return;
+    };
+    roff.control("TP", [])
+        .text([bold(format!("{name}.{tag} = {value}"))]);
+    if let Some(description) = variant.get("description").and_then(Value::as_str) {
+        roff.text([roman(plain(description))]);
+    }
+    let mut rest = variant.clone();
+    if let Some(properties) = rest.get_mut("properties").and_then(Value::as_object_mut) {
+        properties.remove(tag);
+        if properties.is_empty() {
+            return;
+        }
+    }
+    roff.control("RS", []);
+    document_properties(roff, &rest, defs, &format!("{name}."));
+    roff.control("RE", []);
+}
+
+/// Follows the references of `schema` and unwraps the optional values.
+fn resolve<'a>(schema: &'a Value, defs: &'a Value) -> &'a Value {
+    if let Some(name) = schema
+        .get("$ref")
+        .and_then(Value::as_str)
+        .and_then(|r| r.strip_prefix("#/$defs/"))
+    {
+        return resolve(&defs[name], defs);
+    }
+    let any_of = schema.get("anyOf").and_then(Value::as_array);
+    if let Some([value, null]) = any_of.map(Vec::as_slice) {
+        if null.get("type").and_then(Value::as_str) == Some("null") {
+            return resolve(value, defs);
+        }
+    }
+    schema
+}
+
+/// Returns the JSON type of the values described by `schema`.
+fn type_name(schema: &Value, defs: &Value) -> String {
+    let schema = resolve(schema, defs);
+    if schema.get("oneOf").is_some() {
+        return "object".to_owned();
+    }
+    let kind = match schema.get("type") {
+        Some(Value::String(kind)) => kind.as_str(),
+        Some(Value::Array(kinds)) => kinds
+            .iter()
+            .filter_map(Value::as_str)
+            .find(|kind| *kind != "null")
+            .unwrap_or("null"),
+        _ => "value",
+    };
+    match kind {
+        "array" => match schema.get("items") {
+            Some(items) => format!("array of {}", type_name(items, defs)),
+            None => "array".to_owned(),
+        },
+        "object" if schema.get("properties").is_none() => {
+            match schema.get("additionalProperties") {
+                Some(Value::Object(_)) => format!(
+                    "object of {}",
+                    type_name(&schema["additionalProperties"], defs)
+                ),
+                _ => "object".to_owned(),
+            }
+        }
+        "integer" => "number".to_owned(),
+        kind => kind.to_owned(),
+    }
+}
+
+/// Removes the Markdown of a doc comment: code spans and link targets.
+fn plain(text: &str) -> String {
+    let mut plain = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '`' | '[' => {}
+            ']' if chars.peek() == Some(&'(') => {
+                for c in chars.by_ref() {
+                    if c == ')' {
+                        break;
+                    }
+                }
+            }
+            ']' => {}
+            '\n' => plain.push(' '),
+            c => plain.push(c),
+        }
+    }
+    plain
+}
@@ -0,0 +1,547 @@
+//! Definition of the command line.
+
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use clap::builder::BoolishValueParser;
+use clap::{ArgAction, ArgGroup, Args, Parser, Subcommand, ValueEnum};
+use clap_complete::engine::ArgValueCandidates;
+use wgb_core::backend::BackendKind;
+use wgb_core::log;
+use wgb_core::secret::StoreKind;
+use wgb_core::totp::Algorithm;
+
+use crate::complete;
+
+/// A tool to handle a Wireguard VPN
+#[derive(Debug, Parser)]
+#[command(name = "wgb", version)]
+#[command(
+    after_help = "Example:\n  wgb connect server1    # or /path/to/server1.conf\n  wgb disconnect    # Disconnect without specifying a resource"
+)]
+pub struct Cli {
+    /// Enable verbose mode: log debug events, trace events with -vv, also
+    /// to the standard error
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Log errors only
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// Backend used to bring the tunnels up and down [default: the wgbd
+    /// daemon when it is running, wg-quick otherwise]
+    #[arg(long, global = true, env = "WGB_BACKEND", value_enum)]
+    pub backend: Option<Backend>,
+
+    /// Format of the output of list, status and path list, and of the errors
+    #[arg(
+        short,
+        long,
+        global = true,
+        env = "WGB_OUTPUT",
+        value_enum,
+        default_value_t
+    )]
+    pub output: Format,
+
+    /// How to choose the profiles when none is given
+    #[arg(long, global = true, env = "WGB_PICKER", value_enum, default_value_t)]
+    pub picker: Picker,
+
+    /// Never ask anything: missing answers are errors
+    #[arg(
+        long,
+        global = true,
+        env = "WGB_NON_INTERACTIVE",
+        value_parser = BoolishValueParser::new()
+    )]
+    pub non_interactive: bool,
+
+    /// Configuration file to use instead of ~/.wgbconf.json
+    #[arg(long, global = true, env = "WGB_CONFIG", value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub log: LogArgs,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Connect to a specified resource
+    Connect {
+        /// Name of the profile: its alias, the name of its file or its path
+        #[arg(add = ArgValueCandidates::new(complete::profile_names))]
+        profile: Option<String>,
+
+        /// Seconds given to the 2FA authorization [default: the timeout of
+        /// the profile, or 120]
+        #[arg(long, value_name = "SECONDS", value_parser = clap::value_parser!(u64).range(1..))]
+        timeout: Option<u64>,
+
+        /// Do not wait for the 2FA authorization
+        #[arg(long, conflicts_with = "timeout")]
+        no_wait: bool,
+    },
+
+    /// Disconnect from a specified resource
+    Disconnect {
+        /// Name of the profile: its alias, the name of its file or its path
+        #[arg(add = ArgValueCandidates::new(complete::connected_names))]
+        profile: Option<String>,
+    },
+
+    /// List available resources
+    List,
+
+    /// List active VPN
+    Status {
+        /// Refresh the status until interrupted, with rates and events
+        #[arg(short, long)]
+        watch: bool,
+
+        /// Seconds between two refreshes
+        #[arg(
+            short = 'n',
+            long,
+            default_value = "2",
+            value_name = "SECONDS",
+            value_parser = parse_interval,
+            requires = "watch"
+        )]
+        interval: Duration,
+    },
+
+    /// Manage the paths where the configurations are searched
+    Path {
+        #[command(subcommand)]
+        action: PathCommand,
+    },
+
+    /// Manage the properties of the profiles
+    Profile {
+        #[command(subcommand)]
+        action: ProfileCommand,
+    },
+
+    /// Manage the TOTP seeds used to answer the 2FA step
+    Totp {
+        #[command(subcommand)]
+        action: TotpCommand,
+    },
+
+    /// Install, update or uninstall wgb
+    #[command(name = "self")]
+    Setup {
+        #[command(subcommand)]
+        action: SetupCommand,
+    },
+
+    /// Print the completion script of a shell
+    Completions {
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+
+    /// Print the candidates completing a command line, for the completion
+    /// scripts
+    #[command(name = "__complete", hide = true)]
+    Complete {
+        #[arg(value_enum)]
+        shell: Shell,
+
+        /// Position of the word to complete in `words`
+        index: usize,
+
+        /// The command line, starting with wgb
+        #[arg(last = true)]
+        words: Vec<OsString>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum PathCommand {
+    /// Add paths in the configuration file
+    Add,
+
+    /// Remove a path from configuration file
+    Delete,
+
+    /// List all paths
+    List,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ProfileCommand {
+    /// Set the properties of a profile, without asking anything
+    #[command(group = ArgGroup::new("change").required(true).multiple(true))]
+    Set {
+        /// Name of the profile: its alias, the name of its file or its path
+        #[arg(add = ArgValueCandidates::new(complete::profile_names))]
+        name: String,
+
+        /// The connection requires a 2FA step
+        #[arg(long, group = "change", conflicts_with = "no_token")]
+        token: bool,
+
+        /// The connection does not require a 2FA step
+        #[arg(long, group = "change")]
+        no_token: bool,
+
+        /// URI of the 2FA page
+        #[arg(long, group = "change")]
+        uri: Option<String>,
+
+        /// Name the profile is also given, to tell apart profiles whose
+        /// files have the same name
+        #[arg(long, group = "change", conflicts_with = "no_alias")]
+        alias: Option<String>,
+
+        /// Remove the alias of the profile
+        #[arg(long, group = "change")]
+        no_alias: bool,
+    },
+
+    /// Move the keys of a profile to a secret store, leaving references to
+    /// them in the file
+    Seal {
+        /// Name of the profile: its alias, the name of its file or its path
+        #[arg(add = ArgValueCandidates::new(complete::profile_names))]
+        name: String,
+
+        /// Where the keys are kept
+        #[arg(long, value_enum, default_value_t)]
+        store: Store,
+    },
+
+    /// Put the keys of a profile back in the file, removing them from their
+    /// store
+    Unseal {
+        /// Name of the profile: its alias, the name of its file or its path
+        #[arg(add = ArgValueCandidates::new(complete::profile_names))]
+        name: String,
+    },
+
+    /// Encrypt a profile with age, to the identity of the user unless
+    /// recipients are given
+    Encrypt {
+        /// Name of the profile: its alias, the name of its file or its path
+        #[arg(add = ArgValueCandidates::new(complete::profile_names))]
+        name: String,
+
+        /// Public key of an age recipient, may be repeated
+        #[arg(short, long = "recipient", value_name = "RECIPIENT")]
+        recipients: Vec<String>,
+
+        /// File listing the public keys of the recipients, one per line
+        #[arg(short = 'R', long, value_name = "PATH")]
+        recipients_file: Option<PathBuf>,
+
+        /// Keep the plain profile
+        #[arg(long)]
+        keep: bool,
+    },
+
+    /// Decrypt a profile encrypted with age
+    Decrypt {
+        /// Name of the encrypted profile: its alias, the name of its file or
+        /// its path
+        #[arg(add = ArgValueCandidates::new(complete::profile_names))]
+        name: String,
+
+        /// Keep the encrypted profile
+        #[arg(long)]
+        keep: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum TotpCommand {
+    /// Store the TOTP seed of a profile, read from the standard input
+    Set {
+        /// Name of the profile: its alias, the name of its file or its path
+        #[arg(add = ArgValueCandidates::new(complete::profile_names))]
+        profile: String,
+
+        /// URI of the 2FA page the codes are submitted to
+        #[arg(long)]
+        uri: Option<String>,
+
+        /// Hash function of the codes
+        #[arg(long, value_enum, default_value_t)]
+        algorithm: TotpAlgorithm,
+
+        /// Number of digits of the codes
+        #[arg(long, default_value_t = 6, value_parser = clap::value_parser!(u32).range(6..=8))]
+        digits: u32,
+
+        /// Validity of a code, in seconds
+        #[arg(long, default_value_t = 30, value_parser = clap::value_parser!(u64).range(1..))]
+        period: u64,
+
+        /// Name of the form field holding the code on the 2FA page
+        #[arg(long, default_value = "token")]
+        field: String,
+    },
+
+    /// Forget the TOTP seed of a profile
+    Remove {
+        /// Name of the profile: its alias, the name of its file or its path
+        #[arg(add = ArgValueCandidates::new(complete::profile_names))]
+        profile: String,
+    },
+
+    /// Print the current code of a profile
+    Code {
+        /// Name of the profile: its alias, the name of its file or its path
+        #[arg(add = ArgValueCandidates::new(complete::profile_names))]
+        profile: String,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum SetupCommand {
+    /// Install this binary, the log directory and the configuration file
+    Install {
+        #[command(flatten)]
+        target: SetupArgs,
+    },
+
+    /// Replace the installed binary with this one and upgrade the
+    /// configuration file
+    Update {
+        #[command(flatten)]
+        target: SetupArgs,
+    },
+
+    /// Remove the installed binary
+    Uninstall {
+        #[command(flatten)]
+        target: SetupArgs,
+
+        /// Also remove the configuration file and the log directory
+        #[arg(long)]
+        purge: bool,
+    },
+}
+
+/// Where `wgb self` installs the tool.
+#[derive(Debug, Args)]
+pub struct SetupArgs {
+    /// Directory whose bin directory receives the binary
+    #[arg(long, value_name = "DIR", default_value = "/usr/local")]
+    pub prefix: PathBuf,
+
+    /// Staging directory prepended to the installed paths, for packages;
+    /// the configuration file is left alone
+    #[arg(long, value_name = "DIR")]
+    pub destdir: Option<PathBuf>,
+
+    /// Print the steps without changing anything
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+/// Format of the output of `list`, `status` and `path list`, see
+/// [`crate::output`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// Text for humans
+    #[default]
+    Table,
+    /// JSON document
+    Json,
+    /// YAML document
+    Yaml,
+}
+
+/// How the profiles are chosen, see [`crate::picker`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Picker {
+    /// yad when there is a display and yad is installed, the terminal
+    /// otherwise
+    #[default]
+    Auto,
+    /// The picker drawn in the terminal
+    Tui,
+    /// The yad dialog
+    Yad,
+}
+
+/// Shells completions are provided for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    Elvish,
+}
+
+/// Hash functions of the TOTP codes, see [`Algorithm`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum TotpAlgorithm {
+    #[default]
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl From<TotpAlgorithm> for Algorithm {
+    fn from(algorithm: TotpAlgorithm) -> Self {
+        match algorithm {
+            TotpAlgorithm::Sha1 => Algorithm::Sha1,
+            TotpAlgorithm::Sha256 => Algorithm::Sha256,
+            TotpAlgorithm::Sha512 => Algorithm::Sha512,
+        }
+    }
+}
+
+/// Backend choices, see [`BackendKind`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Backend {
+    /// Run wg-quick and wg
+    #[default]
+    WgQuick,
+    /// Configure the interfaces through netlink
+    Netlink,
+    /// Keep the tunnels in memory, for testing
+    Mock,
+}
+
+impl From<Backend> for BackendKind {
+    fn from(backend: Backend) -> Self {
+        match backend {
+            Backend::WgQuick => BackendKind::WgQuick,
+            Backend::Netlink => BackendKind::Netlink,
+            Backend::Mock => BackendKind::Mock,
+        }
+    }
+}
+
+/// Where and how the events are logged.
+#[derive(Debug, Args)]
+#[command(next_help_heading = "Logging")]
+pub struct LogArgs {
+    /// Layout of the log lines
+    #[arg(
+        long = "log-format",
+        global = true,
+        env = "WGB_LOG_FORMAT",
+        value_enum,
+        default_value_t
+    )]
+    pub format: LogFormat,
+
+    /// Log file [default: /var/log/wg-bridge/wgb.log]
+    #[arg(
+        long = "log-file",
+        global = true,
+        env = "WGB_LOG_FILE",
+        value_name = "FILE"
+    )]
+    pub file: Option<PathBuf>,
+
+    /// Size beyond which the log file is rotated, in MiB
+    #[arg(
+        long = "log-max-size",
+        global = true,
+        env = "WGB_LOG_MAX_SIZE",
+        value_name = "MIB",
+        default_value_t = log::DEFAULT_MAX_SIZE / MIB,
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub max_size: u64,
+
+    /// Number of rotated log files kept
+    #[arg(
+        long = "log-max-files",
+        global = true,
+        env = "WGB_LOG_MAX_FILES",
+        value_name = "COUNT",
+        default_value_t = log::DEFAULT_MAX_FILES
+    )]
+    pub max_files: u32,
+
+    /// Also log to the systemd journal
+    #[arg(
+        long,
+        global = true,
+        env = "WGB_JOURNALD",
+        value_parser = BoolishValueParser::new()
+    )]
+    pub journald: bool,
+}
+
+/// Bytes in a MiB.
+const MIB: u64 = 1024 * 1024;
+
+impl LogArgs {
+    /// Returns the logging settings of `identifier`, at the level selected
+    /// by `-v` given `verbose` times or by `-q`. With `-v` the events are
+    /// also written to the standard error.
+    pub fn settings(&self, identifier: &str, verbose: u8, quiet: bool) -> log::Settings {
+        let mut settings = log::Settings::new(identifier);
+        settings.level = log::level(verbose, quiet);
+        settings.format = self.format.into();
+        if let Some(file) = &self.file {
+            settings.file = Some(file.clone());
+        }
+        settings.max_size = self.max_size * MIB;
+        settings.max_files = self.max_files;
+        settings.stderr = verbose > 0;
+        settings.journald = self.journald;
+        settings
+    }
+}
+
+/// Layouts of the log lines, see [`log::Format`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// One line of text per event
+    #[default]
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+impl From<LogFormat> for log::Format {
+    fn from(format: LogFormat) -> Self {
+        match format {
+            LogFormat::Text => log::Format::Text,
+            LogFormat::Json => log::Format::Json,
+        }
+    }
+}
+
+/// Secret stores, see [`StoreKind`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Store {
+    /// The Secret Service of the session (GNOME Keyring, KeePassXC...)
+    #[default]
+    Service,
+    /// A file readable by the user only, ~/.config/wg-bridge/secrets.json
+    File,
+    /// The user keyring of the kernel, emptied on reboot
+    Keyring,
+}
+
+impl From<Store> for StoreKind {
+    fn from(store: Store) -> Self {
+        match store {
+            Store::Service => StoreKind::Service,
+            Store::File => StoreKind::File,
+            Store::Keyring => StoreKind::Keyring,
+        }
+    }
+}
+
+fn parse_interval(value: &str) -> Result<Duration, String> {
+    match value.parse::<f64>() {
+        Ok(secs) if secs >= 0.1 && secs.is_finite() => Ok(Duration::from_secs_f64(secs)),
+        _ => Err("expected a number of seconds, at least 0.1".to_owned()),
+    }
+}
@@ -0,0 +1,1274 @@
+//! Implementation of the commands.
+
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use tracing::{error, info, info_span, warn};
+use wgb_core::auth::{AuthError, AuthMethod, Interaction};
+use wgb_core::backend::{Device, Tunnel, TunnelBackend};
+use wgb_core::config::{ConfEntry, Config, DEFAULT_SEARCH_PATH};
+use wgb_core::discovery::{self, ProfileInfo};
+use wgb_core::encrypted;
+use wgb_core::ipc::{Client, IpcError, Request, Response};
+use wgb_core::probe;
+use wgb_core::profile::Profile;
+use wgb_core::secret::{self, StoreKind};
+use wgb_core::state::State;
+use wgb_core::status::{self, Changes, InterfaceStatus};
+use wgb_core::totp::{self, Seed, Seeds, TotpError};
+use zeroize::Zeroizing;
+
+use crate::cli::{Format, Picker};
+use crate::error::Error;
+use crate::names::Names;
+use crate::output::{
+    self, Body, EventRecord, InterfaceRecord, ProfileRecord, SampleRecord, SearchPathRecord,
+};
+use crate::picker::{self, Candidate};
+use crate::ui;
+
+/// Number of events kept on screen by `status --watch`.
+const EVENTS_SHOWN: usize = 10;
+
+/// Time between two checks of the 2FA authorization.
+const PROBE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Longest run of a `probe` command.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Everything a command needs: the configuration, the runtime state and the
+/// backend.
+pub struct Context {
+    pub config_path: PathBuf,
+    pub config: Config,
+    pub state_path: PathBuf,
+    pub state: State,
+    pub backend: Box<dyn TunnelBackend>,
+    /// Socket of the daemon, when the commands go through it.
+    pub daemon: Option<PathBuf>,
+    pub picker: Picker,
+    pub output: Format,
+    pub verbose: bool,
+    /// Whether the user may be asked for missing answers.
+    pub interactive: bool,
+    /// Names of the live interfaces, once known.
+    pub live: Vec<String>,
+}
+
+impl Context {
+    /// Reads the live interfaces and reconciles the recorded connection
+    /// status with them. When they cannot be read, the recorded status is
+    /// used as is.
+    pub fn refresh(&mut self) {
+        match self.backend.show() {
+            Ok(devices) => self.reconcile(&devices),
+            Err(err) => warn!("unable to read the interfaces: {err}"),
+        }
+    }
+
+    /// Reconciles the recorded connection status with `devices`.
+    fn reconcile(&mut self, devices: &[Device]) {
+        self.live = devices.iter().map(|d| d.name.clone()).collect();
+        if self.state.reconcile(devices) {
+            self.save_state();
+        }
+    }
+
+    /// Returns whether the profile at `path` is connected: recorded as such
+    /// or bringing up a live interface.
+    fn is_connected(&self, path: &Path) -> bool {
+        self.state.is_connected(path) || self.live.iter().any(|name| status::brings_up(path, name))
+    }
+
+    /// Returns the name of the profile at `path` shown to the user, see
+    /// [`Names`].
+    fn name(&self, path: &Path) -> String {
+        Names::new(&self.config, []).of(path)
+    }
+
+    /// Applies `change` to the configuration file, reloaded under its lock
+    /// so that the changes of concurrent invocations are kept.
+    fn update(&mut self, change: impl FnOnce(&mut Config)) -> Result<(), Error> {
+        self.config = Config::update(&self.config_path, change)?;
+        Ok(())
+    }
+
+    /// Writes the runtime state. It is only a cache of the live interfaces,
+    /// so a failure is logged rather than reported.
+    fn save_state(&self) {
+        if let Err(err) = self.state.save(&self.state_path) {
+            warn!("{err}");
+        }
+    }
+
+    /// Asks the user `prompt`, unless the session is not interactive.
+    fn ask(&self, prompt: &str) -> Result<String, Error> {
+        if !self.interactive {
+            return Err(Error::NonInteractive(prompt.trim_end().to_owned()));
+        }
+        ui::read_line(prompt)
+    }
+
+    /// Reads a secret like [`Context::ask`]; it may always be piped on the
+    /// standard input.
+    fn ask_secret(&self, prompt: &str) -> Result<Zeroizing<String>, Error> {
+        if !self.interactive && io::stdin().is_terminal() {
+            return Err(Error::NonInteractive(prompt.trim_end().to_owned()));
+        }
+        ui::read_secret(prompt)
+    }
+
+    /// Returns the profile called `name`: a path to a profile, the alias or
+    /// the ID of one, or the file name, with or without extensions, of one
+    /// found in the search paths. Aliases take precedence over file names.
+    fn resolve(&mut self, name: &str) -> Result<PathBuf, Error> {
+        let path = Path::new(name);
+        if name.contains('/') {
+            let path = std::path::absolute(path)?;
+            // A file that cannot be looked at, as in /etc/wireguard, is
+            // taken as it is, and so is one the tool knows about, which may
+            // be gone.
+            let is_file = match fs::metadata(&path) {
+                Ok(meta) => meta.is_file(),
+                Err(err) => err.kind() == io::ErrorKind::PermissionDenied,
+            };
+            let known = self.state.profiles.contains_key(&path)
+                || self.config.entry(&path).is_some()
+                || is_file && discovery::is_profile(&path);
+            if !known {
+                return Err(Error::NotAProfile(path));
+            }
+            return Ok(path);
+        }
+        if let Some(entry) = self.config.named(name).filter(|e| !is_gone(&e.path)) {
+            return Ok(entry.path.clone());
+        }
+        let found = self.discover()?;
+        if let Some(entry) = self.config.named(name) {
+            return Ok(entry.path.clone());
+        }
+        let stem = discovery::profile_name(path).unwrap_or(name);
+        let mut found: Vec<PathBuf> = found
+            .into_iter()
+            .map(|info| info.path)
+            .filter(|path| discovery::profile_name(path) == Some(stem))
+            .collect();
+        match found.len() {
+            0 => Err(Error::UnknownProfile(name.to_owned())),
+            1 => Ok(found.remove(0)),
+            _ => Err(Error::AmbiguousProfile {
+                name: name.to_owned(),
+                paths: found,
+            }),
+        }
+    }
+
+    /// Returns the profiles found in the search paths like
+    /// [`Context::describe_configs`], once the settings of those that moved
+    /// follow them.
+    fn discover(&mut self) -> Result<Vec<ProfileInfo>, Error> {
+        let found = self.describe_configs()?;
+        self.follow_moves(&found)?;
+        Ok(found)
+    }
+
+    /// Points the settings of the profiles whose file is gone to the one
+    /// profile in `found` with the same file name and no settings, where it
+    /// was moved to.
+    fn follow_moves(&mut self, found: &[ProfileInfo]) -> Result<(), Error> {
+        let moves: Vec<(PathBuf, PathBuf)> = self
+            .config
+            .confs
+            .iter()
+            .filter(|entry| is_gone(&entry.path))
+            .filter_map(|entry| {
+                let name = entry.path.file_name()?;
+                let mut targets = found
+                    .iter()
+                    .map(|info| &info.path)
+                    .filter(|path| path.file_name() == Some(name))
+                    .filter(|path| self.config.entry(path).is_none());
+                match (targets.next(), targets.next()) {
+                    (Some(target), None) => Some((entry.path.clone(), target.clone())),
+                    _ => None,
+                }
+            })
+            .collect();
+        if moves.is_empty() {
+            return Ok(());
+        }
+        for (from, to) in &moves {
+            info!(
+                "'{}' moved to '{}', its settings follow it",
+                from.display(),
+                to.display()
+            );
+        }
+        self.update(|config| {
+            for (from, to) in moves {
+                if config.entry(&to).is_none() {
+                    if let Some(entry) = config.entry_mut(&from) {
+                        entry.path = to;
+                    }
+                }
+            }
+        })
+    }
+
+    /// Returns the TOTP seeds, first moving those still stored by profile
+    /// path to the ID of the profile, see [`Seeds::upgrade`].
+    fn seeds(&self) -> Result<(PathBuf, Seeds), TotpError> {
+        let path = Seeds::default_path()?;
+        let mut seeds = Seeds::load(&path)?;
+        if seeds.upgrade(|profile| self.config.id_of(profile)) {
+            seeds.save(&path)?;
+        }
+        Ok((path, seeds))
+    }
+
+    /// Returns the profiles found in the search paths with their details.
+    /// With a daemon, the directories the user cannot read are listed by the
+    /// daemon, which only lists its own search paths; the encrypted profiles
+    /// are read here, as only the user can decrypt them.
+    fn describe_configs(&self) -> Result<Vec<ProfileInfo>, Error> {
+        let Some(socket) = &self.daemon else {
+            let paths = discovery::find_profiles(self.config.search_paths())?;
+            return Ok(discovery::describe(&paths));
+        };
+        let mut profiles =
+            discovery::describe(&discovery::find_readable(self.config.search_paths()));
+        match Client::connect(socket)?.call(&Request::List)? {
+            Response::Profiles { profiles: listed } => {
+                for info in listed {
+                    if profiles.iter().any(|known| known.path == info.path) {
+                        continue;
+                    }
+                    profiles.push(if encrypted::is_encrypted(&info.path) {
+                        discovery::describe(&[info.path]).remove(0)
+                    } else {
+                        info
+                    });
+                }
+            }
+            other => return Err(IpcError::Protocol(format!("unexpected answer {other:?}")).into()),
+        }
+        profiles.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(profiles)
+    }
+
+    /// Lets the user choose among the profiles in the search paths and the
+    /// connected ones; only among the connected ones if `connected_only`.
+    fn pick(&mut self, connected_only: bool) -> Result<Vec<PathBuf>, Error> {
+        if !self.interactive {
+            return Err(Error::NoPicker);
+        }
+        let connected = self.connected();
+        let profiles = self.discover()?;
+        let names = Names::new(&self.config, profiles.iter().map(|info| info.path.clone()));
+        let mut candidates: Vec<Candidate> = profiles
+            .into_iter()
+            .map(|info| Candidate {
+                connected: self.is_connected(&info.path),
+                name: names.of(&info.path),
+                path: info.path.clone(),
+                info: Some(info),
+            })
+            .filter(|candidate| candidate.connected || !connected_only)
+            .collect();
+        for path in connected {
+            if !candidates.iter().any(|candidate| candidate.path == path) {
+                candidates.push(Candidate {
+                    name: names.of(&path),
+                    path,
+                    connected: true,
+                    info: None,
+                });
+            }
+        }
+        picker::choose(self.picker, &candidates)
+    }
+
+    /// Returns the status of `devices`, tied to the profiles that brought
+    /// them up: the connected ones, then the other recorded ones, then those
+    /// found in the search paths.
+    fn statuses(&self, devices: Vec<Device>) -> Vec<InterfaceStatus> {
+        let mut profiles = self.connected();
+        profiles.extend(
+            self.state
+                .profiles
+                .keys()
+                .chain(self.config.confs.iter().map(|entry| &entry.path))
+                .filter(|path| !profiles.contains(path))
+                .cloned()
+                .collect::<Vec<_>>(),
+        );
+        let unknown = devices
+            .iter()
+            .any(|d| !profiles.iter().any(|p| status::brings_up(p, &d.name)));
+        if unknown {
+            match self.describe_configs() {
+                Ok(infos) => profiles.extend(infos.into_iter().map(|info| info.path)),
+                Err(err) => warn!("{err}"),
+            }
+        }
+        status::build(devices, &profiles, SystemTime::now())
+    }
+
+    /// Returns the profiles recorded as connected.
+    fn connected(&self) -> Vec<PathBuf> {
+        self.state.connected().map(Path::to_path_buf).collect()
+    }
+}
+
+/// Establishes a connection to the profile called `profile`, or to the ones
+/// chosen among those not connected yet.
+///
+/// After the 2FA step, waits for the authorization for `wait` seconds, or
+/// the timeout of the profile when `Some(None)`; not at all when `None`.
+pub fn connect(
+    ctx: &mut Context,
+    profile: Option<&str>,
+    wait: Option<Option<u64>>,
+) -> Result<(), Error> {
+    let profiles = match profile {
+        Some(profile) => vec![ctx.resolve(profile)?],
+        None => ctx.pick(false)?,
+    };
+
+    for path in profiles {
+        if ctx.is_connected(&path) {
+            ui::print_warn(&format!("'{}' is already connected", ctx.name(&path)));
+            continue;
+        }
+        let token = handle_token(ctx, &path)?;
+        let mut tunnel = Tunnel::new(&path)?;
+        let _span = info_span!(
+            "connect",
+            profile = %path.display(),
+            interface = tunnel.interface()
+        )
+        .entered();
+        let since = SystemTime::now();
+        if let Err(source) = tunnel.load_secrets().and_then(|()| ctx.backend.up(&tunnel)) {
+            error!("connection failed: {source}");
+            ctx.state.set_error(&path, &source.to_string());
+            ctx.save_state();
+            return Err(Error::Connect { path, source });
+        }
+        if token {
+            if let Err(source) = authenticate(ctx, &path) {
+                abort(ctx, &tunnel, &source.to_string());
+                return Err(Error::TwoFactor { path, source });
+            }
+            if let Some(timeout) = wait {
+                let timeout = Duration::from_secs(
+                    timeout
+                        .or_else(|| ctx.config.entry(&path).and_then(|e| e.timeout))
+                        .unwrap_or(probe::DEFAULT_TIMEOUT),
+                );
+                if !wait_authorized(ctx, &path, tunnel.interface(), since, timeout) {
+                    let err = Error::Unauthorized { path, timeout };
+                    abort(ctx, &tunnel, &err.to_string());
+                    return Err(err);
+                }
+            }
+        }
+        ctx.state.set_connected(&path, tunnel.interface());
+        ctx.save_state();
+        info!("connected");
+        ui::print_info("Connected");
+    }
+    Ok(())
+}
+
+/// Closes the connection to the profile called `profile`, or to the only
+/// connected one, or to the ones chosen among those connected.
+pub fn disconnect(ctx: &mut Context, profile: Option<&str>) -> Result<(), Error> {
+    let profiles = match profile {
+        Some(profile) => vec![ctx.resolve(profile)?],
+        None => match ctx.connected().as_slice() {
+            [only] => vec![only.clone()],
+            _ => ctx.pick(true)?,
+        },
+    };
+
+    for path in profiles {
+        let mut tunnel = Tunnel::new(&path)?;
+        let _span = info_span!(
+            "disconnect",
+            profile = %path.display(),
+            interface = tunnel.interface()
+        )
+        .entered();
+        if let Err(source) = tunnel.decrypt().and_then(|()| ctx.backend.down(&tunnel)) {
+            error!("disconnection failed: {source}");
+            ctx.state.set_error(&path, &source.to_string());
+            ctx.save_state();
+            return Err(Error::Disconnect { path, source });
+        }
+        ctx.state.set_disconnected(&path);
+        ctx.save_state();
+        info!("disconnected");
+        ui::print_info("Disconnected");
+    }
+    Ok(())
+}
+
+/// Prints the profiles available in the search paths, with their addresses
+/// and endpoints, and warns about the names several of them share.
+pub fn list(ctx: &mut Context) -> Result<(), Error> {
+    let profiles = ctx.discover()?;
+    let names = Names::new(&ctx.config, profiles.iter().map(|info| info.path.clone()));
+    if ctx.output != Format::Table {
+        let records = profiles
+            .into_iter()
+            .map(|info| {
+                let entry = ctx.config.entry(&info.path);
+                ProfileRecord {
+                    name: names.of(&info.path),
+                    id: entry.map(|e| e.id.clone()),
+                    alias: entry.and_then(|e| e.alias.clone()),
+                    token: entry.is_some_and(|e| e.token),
+                    connected: ctx.is_connected(&info.path),
+                    addresses: info.addresses.iter().map(ToString::to_string).collect(),
+                    endpoints: info.endpoints,
+                    error: info.error,
+                    path: info.path,
+                }
+            })
+            .collect();
+        return output::print(ctx.output, &Body::Profiles(records));
+    }
+    if profiles.is_empty() {
+        ui::print_warn("No configurations available");
+        return Ok(());
+    }
+
+    let rows: Vec<[String; 5]> = profiles
+        .iter()
+        .map(|info| {
+            let (address, endpoint) = match &info.error {
+                None => (join(&info.addresses), join(&info.endpoints)),
+                Some(err) => {
+                    warn!(profile = %info.path.display(), "{err}");
+                    ("?".to_owned(), "?".to_owned())
+                }
+            };
+            [
+                names.file_name(&info.path),
+                names.alias(&info.path).unwrap_or("-").to_owned(),
+                address,
+                endpoint,
+                info.path.display().to_string(),
+            ]
+        })
+        .collect();
+    print_table(["NAME", "ALIAS", "ADDRESS", "ENDPOINT", "PATH"], &rows);
+    let mut names: Vec<&str> = rows.iter().map(|row| row[0].as_str()).collect();
+    names.sort_unstable();
+    for shared in names
+        .chunk_by(|a, b| a == b)
+        .filter(|names| names.len() > 1)
+    {
+        ui::print_warn(&format!(
+            "{} profiles are named '{}', give them an alias with: wgb profile set <path> --alias <alias>",
+            shared.len(),
+            shared[0]
+        ));
+    }
+    Ok(())
+}
+
+/// Shows the status of the WireGuard interfaces and warns about the stale
+/// handshakes.
+pub fn status(ctx: &mut Context) -> Result<(), Error> {
+    let devices = ctx.backend.show()?;
+    ctx.reconcile(&devices);
+    let statuses = ctx.statuses(devices);
+    let names = Names::new(&ctx.config, []);
+    if ctx.output != Format::Table {
+        let records = statuses
+            .iter()
+            .map(|status| InterfaceRecord::new(status, &names))
+            .collect();
+        return output::print(ctx.output, &Body::Interfaces(records));
+    }
+    if ctx.verbose {
+        for (i, status) in statuses.iter().enumerate() {
+            if i > 0 {
+                println!();
+            }
+            print_device(status);
+        }
+    } else if !statuses.is_empty() {
+        print_status_table(&statuses, &names);
+    }
+    for warning in statuses.iter().flat_map(InterfaceStatus::warnings) {
+        ui::print_warn(&warning);
+    }
+    Ok(())
+}
+
+/// Refreshes the status every `interval` until interrupted, with the
+/// transfer rates of the peers and the events between two refreshes.
+pub fn watch(ctx: &mut Context, interval: Duration) -> Result<(), Error> {
+    let clear = ctx.output == Format::Table && io::stdout().is_terminal();
+    let mut previous: Option<(Instant, Vec<InterfaceStatus>)> = None;
+    let mut events = VecDeque::new();
+    loop {
+        let devices = ctx.backend.show()?;
+        ctx.reconcile(&devices);
+        let statuses = ctx.statuses(devices);
+        let names = Names::new(&ctx.config, []);
+        let now = Instant::now();
+        let changes = match &previous {
+            Some((then, old)) => status::compare(old, &statuses, now - *then),
+            None => Changes::default(),
+        };
+
+        if ctx.output != Format::Table {
+            let sample = SampleRecord {
+                time: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |d| d.as_secs()),
+                interfaces: statuses
+                    .iter()
+                    .map(|status| {
+                        let mut record = InterfaceRecord::new(status, &names);
+                        for peer in &mut record.peers {
+                            let key = (status.device.name.clone(), peer.public_key.clone());
+                            if let Some(rates) = changes.rates.get(&key) {
+                                peer.rx_rate = Some(rates.rx);
+                                peer.tx_rate = Some(rates.tx);
+                            }
+                        }
+                        record
+                    })
+                    .collect(),
+                events: changes.events.iter().map(EventRecord::from).collect(),
+            };
+            output::print_streamed(ctx.output, &Body::Sample(sample))?;
+        } else {
+            for event in &changes.events {
+                events.push_back(format!("[{}] {event}", wgb_core::log::timestamp()));
+                if events.len() > EVENTS_SHOWN {
+                    events.pop_front();
+                }
+            }
+            if clear {
+                print!("\x1b[2J\x1b[H");
+            }
+            println!(
+                "Every {}s: wgb status    {}",
+                interval.as_secs_f64(),
+                wgb_core::log::timestamp()
+            );
+            println!();
+            print_watch_table(&statuses, &changes, &names);
+            for warning in statuses.iter().flat_map(InterfaceStatus::warnings) {
+                ui::print_warn(&warning);
+            }
+            if !events.is_empty() {
+                println!();
+                println!("Events:");
+                for event in &events {
+                    println!("  {event}");
+                }
+            }
+            if !clear {
+                println!();
+            }
+            io::stdout().flush()?;
+        }
+
+        previous = Some((now, statuses));
+        thread::sleep(interval);
+    }
+}
+
+/// Adds the paths entered by the user to the search paths.
+pub fn add_path(ctx: &mut Context) -> Result<(), Error> {
+    ui::print_warn("Enter the path to configuration files (or empty line to finish)");
+    let cwd = std::env::current_dir()?;
+    let mut dirs = Vec::new();
+    loop {
+        let dir = ctx.ask("Path: ")?;
+        if dir.is_empty() {
+            break;
+        }
+        dirs.push(cwd.join(dir));
+    }
+    ctx.update(|config| {
+        for dir in dirs {
+            if !config.conf_path.contains(&dir) {
+                config.conf_path.push(dir);
+            }
+        }
+    })
+}
+
+/// Removes a path, chosen by number, from the search paths.
+pub fn remove_path(ctx: &mut Context) -> Result<(), Error> {
+    if ctx.config.conf_path.is_empty() {
+        ui::print_warn("No paths available");
+        return Ok(());
+    }
+    print_paths(&ctx.config.conf_path);
+    println!();
+    let selection = ctx.ask("Enter the number of the path you want delete: ")?;
+    match selection.trim().parse::<usize>() {
+        Ok(n) if (1..=ctx.config.conf_path.len()).contains(&n) => {
+            let removed = ctx.config.conf_path[n - 1].clone();
+            ctx.update(|config| config.conf_path.retain(|dir| *dir != removed))?;
+            ui::print_info(&format!(
+                "Item '{}' has been removed from configuration file.",
+                removed.display()
+            ));
+        }
+        _ => ui::print_error("Invalid selection."),
+    }
+    Ok(())
+}
+
+/// Prints the search paths.
+pub fn list_path(ctx: &Context) -> Result<(), Error> {
+    if ctx.output != Format::Table {
+        let records = ctx
+            .config
+            .search_paths()
+            .map(|path| SearchPathRecord {
+                path: path.to_path_buf(),
+                builtin: path == Path::new(DEFAULT_SEARCH_PATH),
+                exists: path.is_dir(),
+            })
+            .collect();
+        return output::print(ctx.output, &Body::SearchPaths(records));
+    }
+    if ctx.config.conf_path.is_empty() {
+        println!("No paths available");
+        return Ok(());
+    }
+    print_paths(&ctx.config.conf_path);
+    Ok(())
+}
+
+/// Returns whether the profile at `path` requires a 2FA step, asking the
+/// user the first time it is used.
+fn handle_token(ctx: &mut Context, path: &Path) -> Result<bool, Error> {
+    if let Some(entry) = ctx.config.entry(path) {
+        return Ok(entry.token);
+    }
+    if !ctx.interactive {
+        return Err(Error::Unconfigured(path.to_path_buf()));
+    }
+    let answer = ctx.ask("Is it necessary to enter a token to connect? [y/N] ")?;
+    let mut entry = ConfEntry::new(path);
+    if matches!(answer.to_lowercase().as_str(), "y" | "yes") {
+        entry.token = true;
+        while entry.uri.trim().is_empty() {
+            entry.uri = ctx.ask("Insert URI of 2FA: ")?;
+        }
+    }
+    let path = entry.path.clone();
+    ctx.update(|config| {
+        // Keep the answer of a concurrent invocation, if any.
+        if config.entry(&entry.path).is_none() {
+            config.confs.push(entry);
+        }
+    })?;
+    Ok(ctx.config.entry(&path).is_some_and(|e| e.token))
+}
+
+/// Brings down a tunnel whose 2FA step failed, recording `error`.
+fn abort(ctx: &mut Context, tunnel: &Tunnel, error: &str) {
+    error!("{error}");
+    let path = tunnel.path();
+    if let Err(err) = ctx.backend.down(tunnel) {
+        error!("unable to bring the tunnel down: {err}");
+    }
+    ctx.state.set_error(path, error);
+    ctx.save_state();
+}
+
+/// Waits up to `timeout` for the tunnel of the profile at `path`, brought up
+/// as `interface` at `since`, to let traffic through, showing a countdown.
+/// Returns whether it does.
+fn wait_authorized(
+    ctx: &Context,
+    path: &Path,
+    interface: &str,
+    since: SystemTime,
+    timeout: Duration,
+) -> bool {
+    let command = ctx.config.entry(path).and_then(|e| e.probe.as_deref());
+    let deadline = Instant::now() + timeout;
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        let authorized = match command {
+            Some(command) => probe::run_command(command, left.min(PROBE_TIMEOUT)),
+            None => match ctx.backend.show() {
+                Ok(devices) => probe::handshake_since(&devices, interface, since),
+                Err(err) => {
+                    warn!("{err}");
+                    false
+                }
+            },
+        };
+        let left = deadline.saturating_duration_since(Instant::now());
+        if authorized || left.is_zero() {
+            ui::end_progress();
+            return authorized;
+        }
+        ui::print_progress(&format!(
+            "Waiting for the 2FA authorization... {}s",
+            left.as_secs_f64().ceil()
+        ));
+        thread::sleep(left.min(PROBE_INTERVAL));
+    }
+}
+
+/// Answers the 2FA step of the profile at `path` with its authenticator.
+fn authenticate(ctx: &Context, path: &Path) -> Result<(), AuthError> {
+    let Some(entry) = ctx.config.entry(path) else {
+        return Ok(());
+    };
+    let method = entry.auth.clone().unwrap_or_default();
+    let mut seed = None;
+    if method.totp().is_some() {
+        let (_, seeds) = ctx.seeds()?;
+        if seeds.contains(&entry.id) {
+            seed = Some(seeds.get(&entry.id)?);
+        } else {
+            ui::print_warn(&format!(
+                "No TOTP seed stored for '{}', asking for the PIN",
+                ctx.name(path)
+            ));
+        }
+    }
+    method
+        .build(&entry.uri, seed)
+        .authenticate(&mut Terminal { ctx })
+}
+
+/// Interaction with the user of the authenticators, in the terminal.
+struct Terminal<'a> {
+    ctx: &'a Context,
+}
+
+impl Interaction for Terminal<'_> {
+    fn open(&mut self, uri: &str) {
+        open_uri(uri);
+    }
+
+    fn ask_secret(&mut self, prompt: &str) -> io::Result<Zeroizing<String>> {
+        self.ctx.ask_secret(prompt).map_err(|err| match err {
+            Error::Io(err) => err,
+            err => io::Error::other(err.to_string()),
+        })
+    }
+
+    fn notify(&mut self, message: &str) {
+        ui::print_info(message);
+    }
+}
+
+/// Sets whether the profile called `name` requires a token, its 2FA page
+/// and its alias, removed when `Some(None)`, without asking anything.
+pub fn set_profile(
+    ctx: &mut Context,
+    name: &str,
+    token: Option<bool>,
+    uri: Option<String>,
+    alias: Option<Option<String>>,
+) -> Result<(), Error> {
+    let path = ctx.resolve(name)?;
+    if let Some(alias) = alias.as_ref().and_then(Option::as_deref) {
+        let taken = ctx
+            .config
+            .named(alias)
+            .filter(|entry| entry.path != path)
+            .map(|entry| entry.path.clone());
+        if let Some(other) = taken {
+            return Err(Error::AliasTaken {
+                alias: alias.to_owned(),
+                path: other,
+            });
+        }
+    }
+    let entry = ctx.config.entry(&path);
+    let requires_token = token.unwrap_or_else(|| entry.is_some_and(|e| e.token));
+    let has_uri = uri
+        .as_deref()
+        .or(entry.map(|e| e.uri.as_str()))
+        .is_some_and(|uri| !uri.trim().is_empty());
+    let needs_uri = entry
+        .and_then(|e| e.auth.as_ref())
+        .is_none_or(|auth| auth.needs_uri());
+    if requires_token && needs_uri && !has_uri {
+        return Err(Error::NoUri(path));
+    }
+    ctx.update(|config| {
+        if config.entry(&path).is_none() {
+            config.confs.push(ConfEntry::new(&path));
+        }
+        if let Some(entry) = config.entry_mut(&path) {
+            if let Some(token) = token {
+                entry.token = token;
+                if !token {
+                    entry.auth = None;
+                }
+            }
+            if let Some(uri) = uri {
+                entry.uri = uri;
+            }
+            if let Some(alias) = alias {
+                entry.alias = alias;
+            }
+        }
+    })?;
+    ui::print_info(&format!("Profile '{}' updated", ctx.name(&path)));
+    Ok(())
+}
+
+/// Moves the keys of the profile `name` to `store`, leaving references to
+/// them in the file.
+pub fn seal_profile(ctx: &mut Context, name: &str, store: StoreKind) -> Result<(), Error> {
+    let path = ctx.resolve(name)?;
+    let mut profile = Profile::load(&path)?;
+    let mut opened = store.open()?;
+    let sealed = secret::seal(&mut profile, &path, opened.as_mut())?;
+    if sealed.is_empty() {
+        ui::print_info(&format!("'{}' holds no plain key", ctx.name(&path)));
+        return Ok(());
+    }
+    if let Err(err) = profile.save(&path) {
+        // The file still holds the keys: forget the copies just stored.
+        for reference in &sealed {
+            let _ = opened.delete(&reference.name);
+        }
+        return Err(err.into());
+    }
+    ui::print_info(&format!(
+        "{} key(s) of '{}' moved to the {store} store",
+        sealed.len(),
+        ctx.name(&path)
+    ));
+    Ok(())
+}
+
+/// Puts the keys of the profile `name` back in the file and removes them
+/// from their store.
+pub fn unseal_profile(ctx: &mut Context, name: &str) -> Result<(), Error> {
+    let path = ctx.resolve(name)?;
+    let mut profile = Profile::load(&path)?;
+    let references = secret::unseal(&mut profile)?;
+    if references.is_empty() {
+        ui::print_info(&format!("'{}' holds no sealed key", ctx.name(&path)));
+        return Ok(());
+    }
+    profile.save(&path)?;
+    secret::delete(&references)?;
+    ui::print_info(&format!(
+        "Keys of '{}' put back in the file",
+        ctx.name(&path)
+    ));
+    Ok(())
+}
+
+/// Encrypts the profile `name` to `recipients`, and those listed in
+/// `recipients_file`, or to the identity of the user when there is none.
+/// The plain profile is removed unless `keep`.
+pub fn encrypt_profile(
+    ctx: &mut Context,
+    name: &str,
+    mut recipients: Vec<String>,
+    recipients_file: Option<&Path>,
+    keep: bool,
+) -> Result<(), Error> {
+    let path = ctx.resolve(name)?;
+    if encrypted::is_encrypted(&path) {
+        ui::print_info(&format!("'{}' is already encrypted", ctx.name(&path)));
+        return Ok(());
+    }
+    if let Some(file) = recipients_file {
+        recipients.extend(encrypted::read_recipients(file)?);
+    }
+    let text = Zeroizing::new(Profile::load(&path)?.to_string());
+    let target = encrypted::encrypted_path(&path);
+    let data = encrypted::encrypt(&text, &path, &recipients)?;
+    replace_profile(ctx, &path, &target, keep, || {
+        Ok(encrypted::write(&target, &data)?)
+    })?;
+    ui::print_info(&format!("Encrypted to '{}'", target.display()));
+    Ok(())
+}
+
+/// Decrypts the encrypted profile `name` next to it. The encrypted profile
+/// is removed unless `keep`.
+pub fn decrypt_profile(ctx: &mut Context, name: &str, keep: bool) -> Result<(), Error> {
+    let path = ctx.resolve(name)?;
+    if !encrypted::is_encrypted(&path) {
+        ui::print_info(&format!("'{}' is not encrypted", ctx.name(&path)));
+        return Ok(());
+    }
+    let text = encrypted::decrypt(&path)?;
+    let target = encrypted::decrypted_path(&path);
+    replace_profile(ctx, &path, &target, keep, || {
+        Ok(Profile::parse(text.as_str(), &target)?.save(&target)?)
+    })?;
+    ui::print_info(&format!("Decrypted to '{}'", target.display()));
+    Ok(())
+}
+
+/// Writes with `write` the profile at `path`, in another form, to `target`,
+/// then moves the settings of the profile, and so its TOTP seed, to it and
+/// removes `path` unless `keep`.
+fn replace_profile(
+    ctx: &mut Context,
+    path: &Path,
+    target: &Path,
+    keep: bool,
+    write: impl FnOnce() -> Result<(), Error>,
+) -> Result<(), Error> {
+    if ctx.is_connected(path) {
+        return Err(Error::Connected(path.to_path_buf()));
+    }
+    if target.exists() {
+        return Err(Error::Exists(target.to_path_buf()));
+    }
+    write()?;
+    if keep {
+        return Ok(());
+    }
+    fs::remove_file(path)?;
+    ctx.update(|config| {
+        if let Some(entry) = config.entry_mut(path) {
+            entry.path = target.to_path_buf();
+        }
+    })
+}
+
+/// Stores the TOTP seed of the profile called `name`, read from the
+/// standard input, and records how its codes are computed and submitted.
+pub fn set_totp(
+    ctx: &mut Context,
+    name: &str,
+    uri: Option<String>,
+    field: String,
+    settings: totp::Settings,
+) -> Result<(), Error> {
+    let path = ctx.resolve(name)?;
+    let uri = uri
+        .or_else(|| ctx.config.entry(&path).map(|e| e.uri.clone()))
+        .filter(|uri| !uri.trim().is_empty())
+        .ok_or_else(|| Error::NoUri(path.clone()))?;
+    let seed = ctx.ask_secret("TOTP seed (base32): ")?;
+    Seed::from_base32(&seed)?;
+    ctx.update(|config| {
+        if config.entry(&path).is_none() {
+            config.confs.push(ConfEntry::new(&path));
+        }
+        if let Some(entry) = config.entry_mut(&path) {
+            entry.token = true;
+            entry.uri = uri;
+            entry.auth = Some(AuthMethod::Form {
+                field,
+                totp: Some(settings),
+            });
+        }
+    })?;
+    let (seeds_path, mut seeds) = ctx.seeds()?;
+    seeds.set(entry_id(ctx, &path)?, &seed)?;
+    seeds.save(&seeds_path)?;
+    ui::print_info("TOTP seed stored");
+    Ok(())
+}
+
+/// Forgets the TOTP seed of the profile called `name`; its 2FA page is
+/// opened in the browser again.
+pub fn remove_totp(ctx: &mut Context, name: &str) -> Result<(), Error> {
+    let path = ctx.resolve(name)?;
+    let (seeds_path, mut seeds) = ctx.seeds()?;
+    if seeds.remove(entry_id(ctx, &path)?) {
+        seeds.save(&seeds_path)?;
+    }
+    ctx.update(|config| {
+        let entry = config.entry_mut(&path);
+        if let Some(entry) = entry.filter(|e| e.auth.as_ref().is_some_and(|a| a.totp().is_some())) {
+            entry.auth = None;
+        }
+    })?;
+    ui::print_info("TOTP seed removed");
+    Ok(())
+}
+
+/// Prints the current TOTP code of the profile called `name` and how long
+/// it remains valid.
+pub fn print_totp(ctx: &mut Context, name: &str) -> Result<(), Error> {
+    let path = ctx.resolve(name)?;
+    let seed = ctx.seeds()?.1.get(entry_id(ctx, &path)?)?;
+    let settings = ctx
+        .config
+        .entry(&path)
+        .and_then(|e| e.auth.as_ref()?.totp().cloned())
+        .unwrap_or_default();
+    let now = SystemTime::now();
+    println!(
+        "{} (valid for {}s)",
+        settings.code(&seed, now),
+        settings.remaining(now).as_secs()
+    );
+    Ok(())
+}
+
+/// Returns the ID of the profile at `path`, which has no TOTP seed when it
+/// has no settings.
+fn entry_id<'a>(ctx: &'a Context, path: &Path) -> Result<&'a str, Error> {
+    ctx.config
+        .id_of(path)
+        .ok_or_else(|| Error::Unconfigured(path.to_path_buf()))
+}
+
+/// Returns whether the file at `path` is known not to exist anymore; it
+/// may exist when it cannot be looked at, as in `/etc/wireguard`.
+fn is_gone(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_err_and(|err| err.kind() == io::ErrorKind::NotFound)
+}
+
+/// Opens `uri` in the browser, in background.
+fn open_uri(uri: &str) {
+    let spawned = Command::new("xdg-open")
+        .arg(uri)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+    if let Err(err) = spawned {
+        warn!("xdg-open {uri}: {err}");
+    }
+}
+
+fn print_paths(paths: &[PathBuf]) {
+    println!("Available paths:");
+    for (i, path) in paths.iter().enumerate() {
+        println!("  {}. {}", i + 1, path.display());
+    }
+}
+
+/// Prints one row per peer of the interfaces.
+fn print_status_table(statuses: &[InterfaceStatus], names: &Names) {
+    let mut rows = Vec::new();
+    for status in statuses {
+        let profile = status
+            .profile
+            .as_deref()
+            .map_or_else(|| "-".to_owned(), |path| names.of(path));
+        if status.peers.is_empty() {
+            let row = [&status.device.name, &profile, "-", "-", "-", "-"];
+            rows.push(row.map(str::to_owned));
+        }
+        for peer in &status.peers {
+            rows.push([
+                status.device.name.clone(),
+                profile.clone(),
+                peer.state
+                    .endpoint
+                    .map_or_else(|| "-".to_owned(), |e| e.to_string()),
+                peer.handshake_age.map_or_else(|| "never".to_owned(), ago),
+                bytes(peer.state.rx_bytes),
+                bytes(peer.state.tx_bytes),
+            ]);
+        }
+    }
+    print_table(
+        [
+            "INTERFACE",
+            "PROFILE",
+            "ENDPOINT",
+            "HANDSHAKE",
+            "RECEIVED",
+            "SENT",
+        ],
+        &rows,
+    );
+}
+
+/// Prints one row per peer of the interfaces, with the rates computed
+/// since the previous refresh.
+fn print_watch_table(statuses: &[InterfaceStatus], changes: &Changes, names: &Names) {
+    let mut rows = Vec::new();
+    for status in statuses {
+        let name = &status.device.name;
+        let profile = status
+            .profile
+            .as_deref()
+            .map_or_else(|| "-".to_owned(), |path| names.of(path));
+        if status.peers.is_empty() {
+            let row = [name, &profile, "-", "-", "-", "-", "-", "-"];
+            rows.push(row.map(str::to_owned));
+        }
+        for peer in &status.peers {
+            let key = (name.clone(), peer.state.public_key.clone());
+            let rates = changes.rates.get(&key);
+            let rate = |value: Option<f64>| {
+                value.map_or_else(|| "-".to_owned(), |v| format!("{}/s", bytes(v as u64)))
+            };
+            let mut handshake = peer.handshake_age.map_or_else(|| "never".to_owned(), ago);
+            if changes.renewed.contains(&key) {
+                handshake.push_str(" (renewed)");
+            } else if peer.is_stale() {
+                handshake.push_str(" (stale)");
+            }
+            rows.push([
+                name.clone(),
+                profile.clone(),
+                peer.state
+                    .endpoint
+                    .map_or_else(|| "-".to_owned(), |e| e.to_string()),
+                handshake,
+                rate(rates.map(|r| r.rx)),
+                rate(rates.map(|r| r.tx)),
+                bytes(peer.state.rx_bytes),
+                bytes(peer.state.tx_bytes),
+            ]);
+        }
+    }
+    print_table(
+        [
+            "INTERFACE",
+            "PROFILE",
+            "ENDPOINT",
+            "HANDSHAKE",
+            "RX/S",
+            "TX/S",
+            "RECEIVED",
+            "SENT",
+        ],
+        &rows,
+    );
+}
+
+/// Prints `status` in the format of `wg show`, with the profile.
+fn print_device(status: &InterfaceStatus) {
+    let device = &status.device;
+    println!("interface: {}", device.name);
+    if let Some(profile) = &status.profile {
+        println!("  profile: {}", profile.display());
+    }
+    if let Some(key) = &device.public_key {
+        println!("  public key: {key}");
+    }
+    if let Some(port) = device.listen_port {
+        println!("  listening port: {port}");
+    }
+    if let Some(mark) = device.fwmark {
+        println!("  fwmark: 0x{mark:x}");
+    }
+    for peer in &status.peers {
+        let state = &peer.state;
+        println!();
+        println!("peer: {}", state.public_key);
+        if let Some(endpoint) = state.endpoint {
+            println!("  endpoint: {endpoint}");
+        }
+        let allowed = join(state.allowed_ips.iter());
+        println!(
+            "  allowed ips: {}",
+            if allowed.is_empty() {
+                "(none)"
+            } else {
+                &allowed
+            }
+        );
+        if let Some(age) = peer.handshake_age {
+            println!("  latest handshake: {}", ago(age));
+        }
+        if state.rx_bytes > 0 || state.tx_bytes > 0 {
+            println!(
+                "  transfer: {} received, {} sent",
+                bytes(state.rx_bytes),
+                bytes(state.tx_bytes)
+            );
+        }
+        if let Some(keepalive) = state.persistent_keepalive {
+            println!("  persistent keepalive: every {keepalive} seconds");
+        }
+    }
+}
+
+/// Prints `rows` in aligned columns under `header`.
+fn print_table<const N: usize>(header: [&str; N], rows: &[[String; N]]) {
+    let mut widths = header.map(str::len);
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+    let line = |cells: [&str; N]| {
+        let mut out = String::new();
+        for (i, cell) in cells.iter().enumerate() {
+            if i + 1 == N {
+                out.push_str(cell);
+            } else {
+                out.push_str(&format!("{cell:<width$}  ", width = widths[i]));
+            }
+        }
+        out
+    };
+    println!("{}", line(header));
+    for row in rows {
+        println!("{}", line(row.each_ref().map(String::as_str)));
+    }
+}
+
+fn join<T: ToString>(items: impl IntoIterator<Item = T>) -> String {
+    items
+        .into_iter()
+        .map(|item| item.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Formats an age, e.g. `1 minute, 5 seconds ago`.
+fn ago(age: Duration) -> String {
+    let secs = age.as_secs();
+    if secs == 0 {
+        return "Now".to_owned();
+    }
+    let units = [
+        ("day", 86400),
+        ("hour", 3600),
+        ("minute", 60),
+        ("second", 1),
+    ];
+    let mut rest = secs;
+    let mut parts = Vec::new();
+    for (name, size) in units {
+        let n = rest / size;
+        rest %= size;
+        if n > 0 {
+            parts.push(format!("{n} {name}{}", if n == 1 { "" } else { "s" }));
+        }
+    }
+    format!("{} ago", parts.join(", "))
+}
+
+/// Formats a number of bytes with binary units, e.g. `1.50 KiB`.
+fn bytes(count: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = count as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < units.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{count} B")
+    } else {
+        format!("{value:.2} {}", units[unit])
+    }
+}
@@ -0,0 +1,188 @@
+//! Shell completion.
+//!
+//! `wgb completions <shell>` prints a script registering a completion
+//! function that runs `wgb __complete` on every tab press. The candidates
+//! are computed from the definition of the command line, see [`Cli`], and
+//! from the profiles the user can see without privileges.
+
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use clap::CommandFactory;
+use clap_complete::engine::{self, CompletionCandidate};
+use wgb_core::config::Config;
+use wgb_core::migration;
+use wgb_core::state::State;
+
+use crate::cli::{Cli, Shell};
+use crate::error::Error;
+use crate::names::{self, Names};
+
+/// Returns the script registering the completion of `wgb` in `shell`.
+pub fn script(shell: Shell) -> &'static str {
+    match shell {
+        Shell::Bash => BASH,
+        Shell::Zsh => ZSH,
+        Shell::Fish => FISH,
+        Shell::Elvish => ELVISH,
+    }
+}
+
+const BASH: &str = r#"# bash completion for wgb
+_wgb() {
+    local IFS=$'\n'
+    COMPREPLY=($(wgb __complete bash "$COMP_CWORD" -- "${COMP_WORDS[@]}" 2>/dev/null))
+}
+complete -o default -o nosort -F _wgb wgb
+"#;
+
+const ZSH: &str = r#"#compdef wgb
+
+_wgb() {
+    local -a candidates
+    candidates=("${(@f)$(wgb __complete zsh $((CURRENT - 1)) -- "${words[@]}" 2>/dev/null)}")
+    _describe -V wgb candidates
+}
+
+if [ "$funcstack[1]" = "_wgb" ]; then
+    _wgb "$@"
+else
+    compdef _wgb wgb
+fi
+"#;
+
+const FISH: &str = r#"# fish completion for wgb
+function __wgb_complete
+    set -l words (commandline -opc) (commandline -ct)
+    wgb __complete fish (math (count $words) - 1) -- $words 2>/dev/null
+end
+complete -c wgb -f -k -a '(__wgb_complete)'
+"#;
+
+const ELVISH: &str = r#"# elvish completion for wgb
+set edit:completion:arg-completer[wgb] = {|@words|
+    wgb __complete elvish (- (count $words) 1) -- $@words 2>/dev/null
+}
+"#;
+
+/// Configuration file given with `--config` on the command line being
+/// completed, read by the completers, which take no argument.
+static CONFIG: OnceLock<PathBuf> = OnceLock::new();
+
+/// Prints the completion script of `shell`.
+pub fn print_script(shell: Shell) -> Result<(), Error> {
+    print!("{}", script(shell));
+    Ok(())
+}
+
+/// Prints the candidates for the word at `index` of `words`, the command
+/// line being completed, one per line in the format `shell` expects.
+pub fn complete(shell: Shell, index: usize, words: Vec<OsString>) -> Result<(), Error> {
+    if let Some(path) = config_arg(&words[..index.min(words.len())]) {
+        let _ = CONFIG.set(path);
+    }
+    let current_dir = env::current_dir().ok();
+    let candidates = engine::complete(&mut Cli::command(), words, index, current_dir.as_deref())?;
+    let mut stdout = io::stdout().lock();
+    for candidate in candidates.iter().filter(|c| !c.is_hide_set()) {
+        let value = candidate.get_value().to_string_lossy();
+        let help = candidate
+            .get_help()
+            .map(|help| help.to_string())
+            .and_then(|help| help.lines().next().map(str::to_owned))
+            .filter(|help| !help.is_empty());
+        match (shell, help) {
+            (Shell::Zsh, Some(help)) => writeln!(stdout, "{}:{help}", value.replace(':', "\\:"))?,
+            (Shell::Zsh, None) => writeln!(stdout, "{}", value.replace(':', "\\:"))?,
+            (Shell::Fish, Some(help)) => writeln!(stdout, "{value}\t{help}")?,
+            _ => writeln!(stdout, "{value}")?,
+        }
+    }
+    Ok(())
+}
+
+/// Completes the names of the profiles: their alias and the name of their
+/// file, or their path when other profiles have a file of the same name.
+pub fn profile_names() -> Vec<CompletionCandidate> {
+    let config = read_config();
+    let names = Names::new(&config, []);
+    let profiles = names::known_profiles(&config);
+    candidates(&names, &profiles)
+}
+
+/// Completes the names of the connected profiles, like [`profile_names`].
+pub fn connected_names() -> Vec<CompletionCandidate> {
+    let config = read_config();
+    let state = State::default_path()
+        .and_then(|path| State::load(&path))
+        .unwrap_or_default();
+    let connected: Vec<PathBuf> = state.connected().map(PathBuf::from).collect();
+    candidates(&Names::new(&config, []), &connected)
+}
+
+/// Returns the candidates naming `paths`, with the path as help.
+fn candidates(names: &Names, paths: &[PathBuf]) -> Vec<CompletionCandidate> {
+    let mut found: Vec<(String, &Path)> = Vec::new();
+    for path in paths {
+        if let Some(alias) = names.alias(path) {
+            found.push((alias.to_owned(), path));
+        }
+        found.push((names.file_name(path), path));
+    }
+    found.sort();
+    found.dedup();
+    found
+        .into_iter()
+        .map(|(name, path)| {
+            CompletionCandidate::new(name).help(Some(path.display().to_string().into()))
+        })
+        .collect()
+}
+
+/// Reads the configuration file of `--config`, in `WGB_CONFIG`, or the
+/// default one, upgraded in memory only: it is neither locked nor written.
+fn read_config() -> Config {
+    let config_path = CONFIG
+        .get()
+        .cloned()
+        .or_else(|| {
+            env::var_os("WGB_CONFIG")
+                .filter(|path| !path.is_empty())
+                .map(PathBuf::from)
+        })
+        .or_else(|| Config::default_path().ok());
+    config_path
+        .and_then(|path| {
+            let mut value = serde_json::from_str(&fs::read_to_string(path).ok()?).ok()?;
+            migration::migrate(&mut value);
+            serde_json::from_value(value).ok()
+        })
+        .unwrap_or_default()
+}
+
+/// Returns the file of the last `--config FILE` or `--config=FILE` among
+/// `words`. The shells pass the words unexpanded: a leading `~/` is
+/// expanded here.
+fn config_arg(words: &[OsString]) -> Option<PathBuf> {
+    let mut config = None;
+    let mut words = words.iter().filter_map(|word| word.to_str());
+    while let Some(word) = words.next() {
+        if word == "--" {
+            break;
+        }
+        if word == "--config" {
+            config = words.next();
+        } else if let Some(path) = word.strip_prefix("--config=") {
+            config = Some(path);
+        }
+    }
+    let config = config.filter(|path| !path.is_empty())?;
+    match (config.strip_prefix("~/"), env::var_os("HOME")) {
+        (Some(rest), Some(home)) => Some(Path::new(&home).join(rest)),
+        _ => Some(PathBuf::from(config)),
+    }
+}
@@ -0,0 +1,259 @@
+//! Errors reported by the command line tool and their exit status.
+
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use thiserror::Error;
+use wgb_core::auth::AuthError;
+use wgb_core::backend::BackendError;
+use wgb_core::catalog::ErrorCode;
+use wgb_core::config::ConfigError;
+use wgb_core::encrypted::EncryptionError;
+use wgb_core::exec::ExecError;
+use wgb_core::ipc::IpcError;
+use wgb_core::profile::ProfileError;
+use wgb_core::secret::SecretError;
+use wgb_core::state::StateError;
+use wgb_core::totp::TotpError;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+
+    #[error(transparent)]
+    Backend(#[from] BackendError),
+
+    #[error(transparent)]
+    Exec(#[from] ExecError),
+
+    #[error(transparent)]
+    Ipc(#[from] IpcError),
+
+    #[error(transparent)]
+    State(#[from] StateError),
+
+    #[error(transparent)]
+    Totp(#[from] TotpError),
+
+    #[error(transparent)]
+    Profile(#[from] ProfileError),
+
+    #[error(transparent)]
+    Secret(#[from] SecretError),
+
+    #[error(transparent)]
+    Encryption(#[from] EncryptionError),
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+
+    /// The selection dialog could not be opened.
+    #[error("unable to open the selection dialog: {source}")]
+    Dialog {
+        #[source]
+        source: io::Error,
+    },
+
+    /// The output document could not be produced.
+    #[error("unable to format the output: {0}")]
+    Output(String),
+
+    /// There is neither a display nor a terminal to choose a profile, or
+    /// the session is not interactive.
+    #[error("no display or terminal to choose a profile, give it as argument")]
+    NoPicker,
+
+    /// An answer is missing and the session is not interactive.
+    #[error("'{0}' needs an answer but --non-interactive is set")]
+    NonInteractive(String),
+
+    /// The 2FA settings of the profile are unknown and the session is not
+    /// interactive.
+    #[error(
+        "'{path}' has no settings, set them with: wgb profile set '{path}' --no-token (or --token --uri <URI>)",
+        path = .0.display()
+    )]
+    Unconfigured(PathBuf),
+
+    /// No profile has the name as alias or ID, nor in the search paths.
+    #[error("no profile named '{0}' by an alias or in the search paths")]
+    UnknownProfile(String),
+
+    /// The path does not name a profile file.
+    #[error("'{}' is not a WireGuard profile, expected an existing *.conf or *.conf.age file", .0.display())]
+    NotAProfile(PathBuf),
+
+    /// Several profiles in the search paths have the name.
+    #[error(
+        "'{name}' names several profiles, give the full path or an alias: {}",
+        join(paths)
+    )]
+    AmbiguousProfile { name: String, paths: Vec<PathBuf> },
+
+    /// The alias already names another profile.
+    #[error("'{alias}' already names '{}'", path.display())]
+    AliasTaken { alias: String, path: PathBuf },
+
+    /// The profile must not be connected for the operation.
+    #[error("'{}' is connected, disconnect it first", .0.display())]
+    Connected(PathBuf),
+
+    /// The file to write already exists.
+    #[error("'{}' already exists", .0.display())]
+    Exists(PathBuf),
+
+    #[error("Connection to '{}' failed: {source}", path.display())]
+    Connect {
+        path: PathBuf,
+        #[source]
+        source: BackendError,
+    },
+
+    /// The profile requires a 2FA step but no page to submit it to.
+    #[error("'{}' has no 2FA uri, give it with --uri", .0.display())]
+    NoUri(PathBuf),
+
+    #[error("2FA for '{}' failed: {source}", path.display())]
+    TwoFactor {
+        path: PathBuf,
+        #[source]
+        source: AuthError,
+    },
+
+    /// The 2FA authorization did not complete in time.
+    #[error("'{}' was not authorized within {}s, the tunnel was brought down", path.display(), timeout.as_secs())]
+    Unauthorized { path: PathBuf, timeout: Duration },
+
+    /// wgb is already installed, see `wgb self update`.
+    #[error("wgb is already installed as '{}', update it with: wgb self update", .0.display())]
+    Installed(PathBuf),
+
+    /// wgb is not installed.
+    #[error("wgb is not installed as '{}', give its --prefix or install it with: wgb self install", .0.display())]
+    NotInstalled(PathBuf),
+
+    /// A file could not be installed.
+    #[error("unable to install '{}': {source}", path.display())]
+    Install {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    /// A file could not be removed.
+    #[error("unable to remove '{}': {source}", path.display())]
+    Uninstall {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("Disconnection from '{}' failed: {source}", path.display())]
+    Disconnect {
+        path: PathBuf,
+        #[source]
+        source: BackendError,
+    },
+}
+
+impl Error {
+    /// Returns the kind of the error in the catalog.
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::Config(ConfigError::NotFound(_) | ConfigError::NoHome) => {
+                ErrorCode::ConfigMissing
+            }
+            Self::Config(ConfigError::Io { source, .. }) if denied(source) => {
+                ErrorCode::PermissionDenied
+            }
+            Self::Config(_) => ErrorCode::ConfigInvalid,
+            Self::Backend(err)
+            | Self::Connect { source: err, .. }
+            | Self::Disconnect { source: err, .. } => backend_code(err),
+            Self::Exec(_) => ErrorCode::BackendFailure,
+            Self::Ipc(err) => ipc_code(err),
+            Self::Profile(err) => profile_code(err),
+            Self::Secret(_) | Self::Encryption(_) | Self::Totp(_) => ErrorCode::SecretUnavailable,
+            Self::Io(err)
+            | Self::Install { source: err, .. }
+            | Self::Uninstall { source: err, .. }
+                if denied(err) =>
+            {
+                ErrorCode::PermissionDenied
+            }
+            Self::UnknownProfile(_) | Self::NotAProfile(_) => ErrorCode::ProfileNotFound,
+            Self::AmbiguousProfile { .. } | Self::AliasTaken { .. } => ErrorCode::ProfileAmbiguous,
+            Self::NoPicker | Self::NonInteractive(_) | Self::Unconfigured(_) | Self::NoUri(_) => {
+                ErrorCode::MissingInput
+            }
+            Self::TwoFactor {
+                source: AuthError::Expired,
+                ..
+            }
+            | Self::Unauthorized { .. } => ErrorCode::TwoFactorTimeout,
+            Self::TwoFactor { .. } => ErrorCode::TwoFactorFailed,
+            Self::State(_)
+            | Self::Io(_)
+            | Self::Dialog { .. }
+            | Self::Output(_)
+            | Self::Connected(_)
+            | Self::Exists(_)
+            | Self::Installed(_)
+            | Self::NotInstalled(_)
+            | Self::Install { .. }
+            | Self::Uninstall { .. } => ErrorCode::Other,
+        }
+    }
+
+    /// Returns the exit status of the process for this error.
+    pub fn exit_code(&self) -> u8 {
+        self.code().exit_code()
+    }
+}
+
+fn backend_code(err: &BackendError) -> ErrorCode {
+    match err {
+        BackendError::Ipc(err) => ipc_code(err),
+        BackendError::Profile(err) => profile_code(err),
+        BackendError::Secret(_) => ErrorCode::SecretUnavailable,
+        BackendError::Netlink { source, .. } | BackendError::Io { source, .. }
+            if denied(source) =>
+        {
+            ErrorCode::PermissionDenied
+        }
+        _ => ErrorCode::BackendFailure,
+    }
+}
+
+fn ipc_code(err: &IpcError) -> ErrorCode {
+    match err {
+        IpcError::Denied(_) => ErrorCode::PermissionDenied,
+        IpcError::Connect { source, .. } if denied(source) => ErrorCode::PermissionDenied,
+        _ => ErrorCode::BackendFailure,
+    }
+}
+
+fn profile_code(err: &ProfileError) -> ErrorCode {
+    match err {
+        ProfileError::Io { source, .. } if source.kind() == io::ErrorKind::NotFound => {
+            ErrorCode::ProfileNotFound
+        }
+        ProfileError::Encryption(_) => ErrorCode::SecretUnavailable,
+        ProfileError::Io { source, .. } if denied(source) => ErrorCode::PermissionDenied,
+        _ => ErrorCode::ProfileInvalid,
+    }
+}
+
+fn denied(err: &io::Error) -> bool {
+    err.kind() == io::ErrorKind::PermissionDenied
+}
+
+fn join(paths: &[PathBuf]) -> String {
+    paths
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -0,0 +1,225 @@
+//! `wgb`, a tool to manage WireGuard VPN connections.
+
+mod cli;
+mod commands;
+mod complete;
+mod error;
+mod man;
+mod names;
+mod output;
+mod picker;
+mod setup;
+mod ui;
+
+use std::collections::BTreeMap;
+use std::process::ExitCode;
+
+use clap::Parser;
+use wgb_core::backend::{BackendKind, DaemonBackend, TunnelBackend};
+use wgb_core::config::Config;
+use wgb_core::state::State;
+use wgb_core::{exec, ipc, totp};
+
+use crate::cli::{Cli, Command, Format, PathCommand, ProfileCommand, TotpCommand};
+use crate::commands::Context;
+use crate::error::Error;
+use crate::output::{Body, ErrorRecord};
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let format = cli.output;
+    let mut messages = BTreeMap::new();
+    match run(cli, &mut messages) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            report(&err, format, &messages);
+            ExitCode::from(err.exit_code())
+        }
+    }
+}
+
+/// Reports `err` in `format`: as a document on the standard output, or as
+/// text on the standard error. `messages` are the `error_codes` of the
+/// configuration, replacing the default messages of the catalog.
+fn report(err: &Error, format: Format, messages: &BTreeMap<String, String>) {
+    let record = ErrorRecord::new(err, messages.get(err.code().code()).map(String::as_str));
+    if format != Format::Table {
+        if output::print(format, &Body::Error(record)).is_err() {
+            ui::print_error(&err.to_string());
+        }
+        return;
+    }
+    ui::print_error(&format!(
+        "[{}] {}: {}",
+        record.code, record.message, record.detail
+    ));
+    if let Some(hint) = record.hint {
+        ui::print_hint(hint);
+    }
+}
+
+/// Runs the command of `cli`, keeping the `error_codes` of the
+/// configuration in `messages` once it is read.
+fn run(cli: Cli, messages: &mut BTreeMap<String, String>) -> Result<(), Error> {
+    // Completion runs on every tab press: it logs nothing and reads the
+    // configuration file only when it needs the profiles.
+    let command = match cli.command {
+        Command::Completions { shell } => return complete::print_script(shell),
+        Command::Complete {
+            shell,
+            index,
+            words,
+        } => return complete::complete(shell, index, words),
+        command => command,
+    };
+    wgb_core::log::init(&cli.log.settings("wgb", cli.verbose, cli.quiet));
+    // Installing must not need a configuration file.
+    let command = match command {
+        Command::Setup { action } => return setup::run(action, cli.config),
+        command => command,
+    };
+    let config_path = match cli.config {
+        Some(path) => path,
+        None => Config::default_path()?,
+    };
+    let config = Config::load(&config_path)?;
+    messages.clone_from(&config.error_codes);
+    let state_path = State::default_path()?;
+    // The state is a cache of the live interfaces: start afresh when it is
+    // unreadable.
+    let state = State::load(&state_path).unwrap_or_else(|err| {
+        tracing::warn!("{err}");
+        State::default()
+    });
+    // Unprivileged users go through the daemon when it is running, unless
+    // they ask for a backend explicitly.
+    let daemon = match cli.backend {
+        Some(_) => None,
+        None => Some(ipc::socket_path()).filter(|socket| !exec::is_root() && socket.exists()),
+    };
+    if daemon.is_some() {
+        // What the user cannot read is left to the daemon, which decides
+        // what they may see.
+        exec::forbid_sudo();
+    }
+    let backend: Box<dyn TunnelBackend> = match &daemon {
+        Some(socket) => Box::new(DaemonBackend::new(socket)),
+        None => BackendKind::from(cli.backend.unwrap_or_default()).build(),
+    };
+    let mut ctx = Context {
+        config_path,
+        config,
+        state_path,
+        state,
+        backend,
+        daemon,
+        picker: cli.picker,
+        output: cli.output,
+        verbose: cli.verbose > 0,
+        interactive: !cli.non_interactive,
+        live: Vec::new(),
+    };
+    // The recorded connections are only a cache of the live interfaces,
+    // refreshed by the commands relying on them. `status` reconciles with
+    // the interfaces it reads itself, the table of `list` shows no
+    // connection, and `complete` only offers candidates, where a stale
+    // cache costs a wrong suggestion rather than a wrong action. The other
+    // commands never look at the connections.
+    let reads_status = match &command {
+        Command::Connect { .. } | Command::Disconnect { .. } => true,
+        Command::List => ctx.output != Format::Table,
+        // Refuses to replace a connected profile.
+        Command::Profile {
+            action: ProfileCommand::Encrypt { .. } | ProfileCommand::Decrypt { .. },
+        } => true,
+        Command::Status { .. }
+        | Command::Path { .. }
+        | Command::Profile { .. }
+        | Command::Totp { .. }
+        | Command::Setup { .. }
+        | Command::Completions { .. }
+        | Command::Complete { .. } => false,
+    };
+    if reads_status {
+        ctx.refresh();
+    }
+
+    match command {
+        Command::Connect {
+            profile,
+            timeout,
+            no_wait,
+        } => commands::connect(&mut ctx, profile.as_deref(), (!no_wait).then_some(timeout)),
+        Command::Disconnect { profile } => commands::disconnect(&mut ctx, profile.as_deref()),
+        Command::List => commands::list(&mut ctx),
+        Command::Status { watch: false, .. } => commands::status(&mut ctx),
+        Command::Status {
+            watch: true,
+            interval,
+        } => commands::watch(&mut ctx, interval),
+        Command::Path { action } => match action {
+            PathCommand::Add => commands::add_path(&mut ctx),
+            PathCommand::Delete => commands::remove_path(&mut ctx),
+            PathCommand::List => commands::list_path(&ctx),
+        },
+        Command::Profile { action } => match action {
+            ProfileCommand::Set {
+                name,
+                token,
+                no_token,
+                uri,
+                alias,
+                no_alias,
+            } => {
+                let token = (token || no_token).then_some(token);
+                let alias = if no_alias {
+                    Some(None)
+                } else {
+                    alias.map(Some)
+                };
+                commands::set_profile(&mut ctx, &name, token, uri, alias)
+            }
+            ProfileCommand::Seal { name, store } => {
+                commands::seal_profile(&mut ctx, &name, store.into())
+            }
+            ProfileCommand::Unseal { name } => commands::unseal_profile(&mut ctx, &name),
+            ProfileCommand::Encrypt {
+                name,
+                recipients,
+                recipients_file,
+                keep,
+            } => commands::encrypt_profile(
+                &mut ctx,
+                &name,
+                recipients,
+                recipients_file.as_deref(),
+                keep,
+            ),
+            ProfileCommand::Decrypt { name, keep } => {
+                commands::decrypt_profile(&mut ctx, &name, keep)
+            }
+        },
+        Command::Totp { action } => match action {
+            TotpCommand::Set {
+                profile,
+                uri,
+                algorithm,
+                digits,
+                period,
+                field,
+            } => {
+                let settings = totp::Settings {
+                    algorithm: algorithm.into(),
+                    digits,
+                    period,
+                };
+                commands::set_totp(&mut ctx, &profile, uri, field, settings)
+            }
+            TotpCommand::Remove { profile } => commands::remove_totp(&mut ctx, &profile),
+            TotpCommand::Code { profile } => commands::print_totp(&mut ctx, &profile),
+        },
+        Command::Setup { .. } | Command::Completions { .. } | Command::Complete { .. } => {
+            unreachable!("run without configuration")
+        }
+    }
+}
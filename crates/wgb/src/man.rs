@@ -0,0 +1,8 @@
+//! Man pages of wgb, generated by the build script.
+
+include!(concat!(env!("OUT_DIR"), "/man.rs"));
+
+/// Returns the section of the page named `name`, such as `wgb.1`.
+pub fn section(name: &str) -> &str {
+    name.rsplit_once('.').map_or("1", |(_, section)| section)
+}
@@ -0,0 +1,69 @@
+//! Names shown for the profiles, the same in every command.
+
+use std::path::{Path, PathBuf};
+
+use wgb_core::config::Config;
+use wgb_core::discovery;
+use wgb_core::state::State;
+
+/// Names of the profiles: their alias, else the name of their file, or
+/// their path when another known profile has a file of the same name.
+pub struct Names<'a> {
+    config: &'a Config,
+    profiles: Vec<PathBuf>,
+}
+
+impl<'a> Names<'a> {
+    /// Names the profiles known without privileges, see [`known_profiles`],
+    /// and those `found` besides.
+    pub fn new(config: &'a Config, found: impl IntoIterator<Item = PathBuf>) -> Self {
+        let mut profiles = known_profiles(config);
+        profiles.extend(found);
+        profiles.sort();
+        profiles.dedup();
+        Self { config, profiles }
+    }
+
+    /// Returns the name of the profile at `path`.
+    pub fn of(&self, path: &Path) -> String {
+        self.alias(path)
+            .map(str::to_owned)
+            .unwrap_or_else(|| self.file_name(path))
+    }
+
+    /// Returns the alias of the profile at `path`, if it has one.
+    pub fn alias(&self, path: &Path) -> Option<&'a str> {
+        self.config.entry(path)?.alias.as_deref()
+    }
+
+    /// Returns the name of the file of the profile at `path`, without its
+    /// extension, or its path when the name is shared.
+    pub fn file_name(&self, path: &Path) -> String {
+        let stem = discovery::profile_name(path).unwrap_or_default();
+        let shared = self
+            .profiles
+            .iter()
+            .filter(|p| *p != path && discovery::profile_name(p) == Some(stem))
+            .count()
+            > 0;
+        if shared || stem.is_empty() {
+            path.display().to_string()
+        } else {
+            stem.to_owned()
+        }
+    }
+}
+
+/// Returns the profiles of the search paths the user can read, with those
+/// the configuration file and the state file know about. Nothing is
+/// written, and no privilege is asked for.
+pub fn known_profiles(config: &Config) -> Vec<PathBuf> {
+    let mut found = discovery::find_readable(config.search_paths());
+    found.extend(config.confs.iter().map(|entry| entry.path.clone()));
+    if let Ok(state) = State::default_path().and_then(|path| State::load(&path)) {
+        found.extend(state.profiles.into_keys());
+    }
+    found.sort();
+    found.dedup();
+    found
+}
@@ -0,0 +1,239 @@
+//! Machine-readable output of the commands.
+//!
+//! The documents are versioned with [`VERSION`]: fields may be added within a
+//! version, but never renamed, removed or changed in meaning.
+
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::UNIX_EPOCH;
+
+use serde::Serialize;
+use wgb_core::catalog::ErrorCode;
+use wgb_core::status::{Event, InterfaceStatus, PeerStatus};
+
+use crate::cli::Format;
+use crate::error::Error;
+use crate::names::Names;
+
+/// Version of the documents.
+pub const VERSION: u32 = 1;
+
+/// What a document holds.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Body {
+    Profiles(Vec<ProfileRecord>),
+    Interfaces(Vec<InterfaceRecord>),
+    SearchPaths(Vec<SearchPathRecord>),
+    Sample(SampleRecord),
+    Error(ErrorRecord),
+}
+
+#[derive(Debug, Serialize)]
+struct Document<'a> {
+    version: u32,
+    #[serde(flatten)]
+    body: &'a Body,
+}
+
+/// A profile found in the search paths.
+#[derive(Debug, Serialize)]
+pub struct ProfileRecord {
+    pub name: String,
+    pub path: PathBuf,
+    /// ID of the profile, once it has settings.
+    pub id: Option<String>,
+    /// Name the profile is also given on the command line.
+    pub alias: Option<String>,
+    /// Whether connecting requires a 2FA step.
+    pub token: bool,
+    pub connected: bool,
+    pub addresses: Vec<String>,
+    pub endpoints: Vec<String>,
+    /// Why the profile could not be read, if it could not.
+    pub error: Option<String>,
+}
+
+/// A WireGuard interface.
+#[derive(Debug, Serialize)]
+pub struct InterfaceRecord {
+    pub name: String,
+    /// Profile that brought the interface up, if known.
+    pub profile: Option<PathBuf>,
+    pub profile_name: Option<String>,
+    pub public_key: Option<String>,
+    pub listen_port: Option<u16>,
+    pub fwmark: Option<u32>,
+    pub peers: Vec<PeerRecord>,
+}
+
+/// A peer of an interface.
+#[derive(Debug, Serialize)]
+pub struct PeerRecord {
+    pub public_key: String,
+    pub endpoint: Option<String>,
+    pub allowed_ips: Vec<String>,
+    /// Seconds since the epoch; `null` if there was no handshake yet.
+    pub latest_handshake: Option<u64>,
+    /// Seconds elapsed since the latest handshake.
+    pub handshake_age: Option<u64>,
+    /// Whether the handshake is too old, or missing, for a live session.
+    pub stale: bool,
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    /// Bytes received per second since the previous sample of `status
+    /// --watch`; absent otherwise.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rx_rate: Option<f64>,
+    /// Bytes sent per second, like `rx_rate`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tx_rate: Option<f64>,
+    pub persistent_keepalive: Option<u16>,
+}
+
+/// A refresh of `status --watch`.
+#[derive(Debug, Serialize)]
+pub struct SampleRecord {
+    /// Seconds since the epoch.
+    pub time: u64,
+    pub interfaces: Vec<InterfaceRecord>,
+    /// What happened since the previous sample.
+    pub events: Vec<EventRecord>,
+}
+
+/// Something that happened to an interface.
+#[derive(Debug, Serialize)]
+pub struct EventRecord {
+    /// `up`, `down`, `handshake`, `roamed` or `reset`.
+    pub event: &'static str,
+    pub interface: String,
+    pub message: String,
+}
+
+/// A directory where the profiles are searched.
+#[derive(Debug, Serialize)]
+pub struct SearchPathRecord {
+    pub path: PathBuf,
+    /// Whether the path is searched by default rather than configured.
+    pub builtin: bool,
+    pub exists: bool,
+}
+
+/// An error that stopped the command.
+#[derive(Debug, Serialize)]
+pub struct ErrorRecord {
+    /// Three digit code, see [`ErrorCode`].
+    pub code: ErrorCode,
+    pub name: &'static str,
+    /// Message of the code, from the `error_codes` of the configuration
+    /// when set there.
+    pub message: String,
+    /// What went wrong in this instance.
+    pub detail: String,
+    pub hint: Option<&'static str>,
+    pub exit_code: u8,
+}
+
+impl ErrorRecord {
+    /// Returns the record of `err`, described by `message` rather than the
+    /// default message of its code when given.
+    pub fn new(err: &Error, message: Option<&str>) -> Self {
+        let code = err.code();
+        Self {
+            code,
+            name: code.name(),
+            message: message.unwrap_or(code.message()).to_owned(),
+            detail: err.to_string(),
+            hint: code.hint(),
+            exit_code: code.exit_code(),
+        }
+    }
+}
+
+/// Prints `body` in `format`, which must not be [`Format::Table`].
+pub fn print(format: Format, body: &Body) -> Result<(), Error> {
+    let document = Document {
+        version: VERSION,
+        body,
+    };
+    let text = match format {
+        Format::Json => serde_json::to_string_pretty(&document)
+            .map(|json| json + "\n")
+            .map_err(|e| Error::Output(e.to_string()))?,
+        Format::Yaml => {
+            serde_yaml::to_string(&document).map_err(|e| Error::Output(e.to_string()))?
+        }
+        Format::Table => unreachable!("tables are printed by the commands"),
+    };
+    print!("{text}");
+    Ok(())
+}
+
+/// Prints `body` as one document of a stream: a line of JSON, or a YAML
+/// document starting with `---`.
+pub fn print_streamed(format: Format, body: &Body) -> Result<(), Error> {
+    let document = Document {
+        version: VERSION,
+        body,
+    };
+    let text = match format {
+        Format::Json => serde_json::to_string(&document)
+            .map(|json| json + "\n")
+            .map_err(|e| Error::Output(e.to_string()))?,
+        Format::Yaml => serde_yaml::to_string(&document)
+            .map(|yaml| format!("---\n{yaml}"))
+            .map_err(|e| Error::Output(e.to_string()))?,
+        Format::Table => unreachable!("tables are printed by the commands"),
+    };
+    print!("{text}");
+    io::stdout().flush()?;
+    Ok(())
+}
+
+impl InterfaceRecord {
+    /// Describes the interface of `status`, its profile named by `names`.
+    pub fn new(status: &InterfaceStatus, names: &Names) -> Self {
+        let device = &status.device;
+        Self {
+            name: device.name.clone(),
+            profile: status.profile.clone(),
+            profile_name: status.profile.as_deref().map(|path| names.of(path)),
+            public_key: device.public_key.clone(),
+            listen_port: device.listen_port,
+            fwmark: device.fwmark,
+            peers: status.peers.iter().map(PeerRecord::from).collect(),
+        }
+    }
+}
+
+impl From<&PeerStatus> for PeerRecord {
+    fn from(peer: &PeerStatus) -> Self {
+        let state = &peer.state;
+        Self {
+            public_key: state.public_key.clone(),
+            endpoint: state.endpoint.map(|e| e.to_string()),
+            allowed_ips: state.allowed_ips.iter().map(ToString::to_string).collect(),
+            latest_handshake: state
+                .latest_handshake
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_secs()),
+            handshake_age: peer.handshake_age.map(|age| age.as_secs()),
+            stale: peer.is_stale(),
+            rx_bytes: state.rx_bytes,
+            tx_bytes: state.tx_bytes,
+            rx_rate: None,
+            tx_rate: None,
+            persistent_keepalive: state.persistent_keepalive,
+        }
+    }
+}
+
+impl From<&Event> for EventRecord {
+    fn from(event: &Event) -> Self {
+        Self {
+            event: event.kind(),
+            interface: event.interface().to_owned(),
+            message: event.to_string(),
+        }
+    }
+}
@@ -0,0 +1,48 @@
+//! Selection of profiles when none is given on the command line.
+//!
+//! Two pickers are available: a yad dialog, for desktop sessions, and a
+//! picker drawn in the terminal, which works over SSH and in containers.
+
+mod tui;
+mod yad;
+
+use std::env;
+use std::io::{self, IsTerminal};
+use std::path::PathBuf;
+
+use wgb_core::discovery::ProfileInfo;
+use wgb_core::exec;
+
+use crate::cli::Picker;
+use crate::error::Error;
+
+/// A profile offered to the user.
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub path: PathBuf,
+    /// Name shown for the profile, see [`Names`](crate::names::Names).
+    pub name: String,
+    pub connected: bool,
+    /// What is shown in the preview, when the profile could be read.
+    pub info: Option<ProfileInfo>,
+}
+
+/// Lets the user choose among `candidates`; nothing when cancelled.
+pub fn choose(picker: Picker, candidates: &[Candidate]) -> Result<Vec<PathBuf>, Error> {
+    let picker = match picker {
+        Picker::Auto if has_display() && exec::in_path("yad") => Picker::Yad,
+        Picker::Auto => Picker::Tui,
+        picker => picker,
+    };
+    match picker {
+        Picker::Yad => yad::choose(candidates),
+        _ if !(io::stdin().is_terminal() && io::stdout().is_terminal()) => Err(Error::NoPicker),
+        _ => tui::choose(candidates).map_err(|source| Error::Dialog { source }),
+    }
+}
+
+fn has_display() -> bool {
+    ["DISPLAY", "WAYLAND_DISPLAY"]
+        .iter()
+        .any(|var| env::var_os(var).is_some_and(|value| !value.is_empty()))
+}
@@ -0,0 +1,315 @@
+//! Picker drawn in the terminal, with fuzzy search and multiple selection.
+
+use std::collections::BTreeSet;
+use std::io;
+use std::path::PathBuf;
+
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+
+use super::Candidate;
+
+const HELP: &str = "↑/↓ move  tab select  enter confirm  esc cancel";
+
+/// Lets the user choose among `candidates` in the terminal; nothing when
+/// cancelled.
+pub fn choose(candidates: &[Candidate]) -> io::Result<Vec<PathBuf>> {
+    let mut terminal = ratatui::try_init()?;
+    let result = State::new(candidates).run(&mut terminal);
+    ratatui::try_restore()?;
+    result
+}
+
+struct State<'a> {
+    candidates: &'a [Candidate],
+    matcher: SkimMatcherV2,
+    query: String,
+    /// Indexes of the candidates matching the query, best match first.
+    matches: Vec<usize>,
+    selected: BTreeSet<usize>,
+    list: ListState,
+}
+
+impl<'a> State<'a> {
+    fn new(candidates: &'a [Candidate]) -> Self {
+        let mut state = Self {
+            candidates,
+            matcher: SkimMatcherV2::default(),
+            query: String::new(),
+            matches: Vec::new(),
+            selected: BTreeSet::new(),
+            list: ListState::default(),
+        };
+        state.filter();
+        state
+    }
+
+    fn run(mut self, terminal: &mut DefaultTerminal) -> io::Result<Vec<PathBuf>> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+            match key.code {
+                KeyCode::Esc => return Ok(Vec::new()),
+                KeyCode::Char('c') if ctrl => return Ok(Vec::new()),
+                KeyCode::Enter => return Ok(self.chosen()),
+                KeyCode::Up => self.list.select_previous(),
+                KeyCode::Char('p') if ctrl => self.list.select_previous(),
+                KeyCode::Down => self.list.select_next(),
+                KeyCode::Char('n') if ctrl => self.list.select_next(),
+                KeyCode::Tab => {
+                    self.toggle();
+                    self.list.select_next();
+                }
+                KeyCode::Backspace => {
+                    self.query.pop();
+                    self.filter();
+                }
+                KeyCode::Char(c) if !ctrl => {
+                    self.query.push(c);
+                    self.filter();
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Recomputes the candidates matching the query.
+    fn filter(&mut self) {
+        let mut scored: Vec<(i64, usize)> = self
+            .candidates
+            .iter()
+            .enumerate()
+            .filter_map(|(i, candidate)| {
+                let score = self.matcher.fuzzy_match(&candidate.name, &self.query)?;
+                Some((score, i))
+            })
+            .collect();
+        if !self.query.is_empty() {
+            scored.sort_by_key(|&(score, i)| (std::cmp::Reverse(score), i));
+        }
+        self.matches = scored.into_iter().map(|(_, i)| i).collect();
+        self.list.select((!self.matches.is_empty()).then_some(0));
+    }
+
+    fn current(&self) -> Option<usize> {
+        self.list
+            .selected()
+            .and_then(|row| self.matches.get(row))
+            .copied()
+    }
+
+    fn toggle(&mut self) {
+        if let Some(i) = self.current() {
+            if !self.selected.remove(&i) {
+                self.selected.insert(i);
+            }
+        }
+    }
+
+    /// Returns the selected profiles, or the highlighted one when none is.
+    fn chosen(&self) -> Vec<PathBuf> {
+        let indexes: Vec<usize> = if self.selected.is_empty() {
+            self.current().into_iter().collect()
+        } else {
+            self.selected.iter().copied().collect()
+        };
+        indexes
+            .into_iter()
+            .map(|i| self.candidates[i].path.clone())
+            .collect()
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [search, body, help] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Min(3),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [list, preview] =
+            Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)])
+                .areas(body);
+
+        let title = format!(
+            "Select a Wireguard configuration ({}/{})",
+            self.matches.len(),
+            self.candidates.len()
+        );
+        frame.render_widget(
+            Paragraph::new(format!("> {}", self.query)).block(Block::bordered().title(title)),
+            search,
+        );
+
+        let items: Vec<ListItem> = self
+            .matches
+            .iter()
+            .map(|&i| {
+                let candidate = &self.candidates[i];
+                let mark = if self.selected.contains(&i) {
+                    "[x] "
+                } else {
+                    "[ ] "
+                };
+                let badge = if candidate.connected {
+                    Span::styled("● ", Style::new().fg(Color::Green))
+                } else {
+                    Span::styled("○ ", Style::new().fg(Color::DarkGray))
+                };
+                ListItem::new(Line::from(vec![
+                    Span::raw(mark),
+                    badge,
+                    Span::raw(candidate.name.as_str()),
+                ]))
+            })
+            .collect();
+        frame.render_stateful_widget(
+            List::new(items)
+                .block(Block::bordered().title("Profiles"))
+                .highlight_style(Style::new().add_modifier(Modifier::REVERSED)),
+            list,
+            &mut self.list,
+        );
+
+        let details = self
+            .current()
+            .map(|i| preview_lines(&self.candidates[i]))
+            .unwrap_or_default();
+        frame.render_widget(
+            Paragraph::new(details)
+                .wrap(Wrap { trim: false })
+                .block(Block::bordered().title("Preview")),
+            preview,
+        );
+
+        frame.render_widget(Line::from(HELP).dark_gray(), help);
+    }
+}
+
+/// Describes `candidate` in the preview pane.
+fn preview_lines(candidate: &Candidate) -> Vec<Line<'static>> {
+    let field = |name: &str, value: String| {
+        Line::from(vec![
+            Span::raw(format!("{name}: ")).bold(),
+            Span::raw(value),
+        ])
+    };
+    let mut lines = vec![
+        field("path", candidate.path.display().to_string()),
+        field(
+            "status",
+            if candidate.connected {
+                "connected"
+            } else {
+                "disconnected"
+            }
+            .to_owned(),
+        ),
+    ];
+    match &candidate.info {
+        Some(info) => match &info.error {
+            Some(err) => lines.push(Line::from(err.clone()).red()),
+            None => {
+                for address in &info.addresses {
+                    lines.push(field("address", address.to_string()));
+                }
+                for endpoint in &info.endpoints {
+                    lines.push(field("endpoint", endpoint.clone()));
+                }
+            }
+        },
+        None => lines.push(Line::from("not in the search paths").dark_gray()),
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidates(names: &[&str]) -> Vec<Candidate> {
+        names
+            .iter()
+            .map(|name| Candidate {
+                path: PathBuf::from(format!("/etc/wireguard/{name}.conf")),
+                name: (*name).to_owned(),
+                connected: false,
+                info: None,
+            })
+            .collect()
+    }
+
+    fn paths(names: &[&str]) -> Vec<PathBuf> {
+        candidates(names).into_iter().map(|c| c.path).collect()
+    }
+
+    fn search(state: &mut State, query: &str) {
+        state.query = query.to_owned();
+        state.filter();
+    }
+
+    #[test]
+    fn lists_every_candidate_in_order_without_query() {
+        let candidates = candidates(&["office", "home", "lab"]);
+        let state = State::new(&candidates);
+        assert_eq!(state.matches, [0, 1, 2]);
+        assert_eq!(state.current(), Some(0));
+    }
+
+    #[test]
+    fn ranks_the_best_matches_first() {
+        let candidates = candidates(&["backoffice", "home", "office"]);
+        let mut state = State::new(&candidates);
+        search(&mut state, "off");
+        assert_eq!(state.matches, [2, 0]);
+        assert_eq!(state.current(), Some(2));
+        search(&mut state, "xyz");
+        assert!(state.matches.is_empty());
+        assert_eq!(state.current(), None);
+        assert!(state.chosen().is_empty());
+    }
+
+    #[test]
+    fn chooses_the_highlighted_one_when_none_is_selected() {
+        let candidates = candidates(&["office", "home", "lab"]);
+        let mut state = State::new(&candidates);
+        state.list.select_next();
+        assert_eq!(state.chosen(), paths(&["home"]));
+    }
+
+    #[test]
+    fn chooses_every_selected_one() {
+        let candidates = candidates(&["office", "home", "lab"]);
+        let mut state = State::new(&candidates);
+        state.toggle();
+        state.list.select(Some(2));
+        state.toggle();
+        assert_eq!(state.chosen(), paths(&["office", "lab"]));
+
+        state.toggle();
+        state.list.select(Some(1));
+        assert_eq!(state.chosen(), paths(&["office"]));
+    }
+
+    #[test]
+    fn keeps_the_selection_while_searching() {
+        let candidates = candidates(&["office", "home", "lab"]);
+        let mut state = State::new(&candidates);
+        search(&mut state, "lab");
+        state.toggle();
+        search(&mut state, "");
+        assert_eq!(state.current(), Some(0));
+        assert_eq!(state.chosen(), paths(&["lab"]));
+    }
+}
@@ -0,0 +1,42 @@
+//! Picker opening a yad dialog.
+
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use super::Candidate;
+use crate::error::Error;
+
+/// Opens a yad window listing `candidates` and returns the ones the user
+/// selected; nothing when the dialog is cancelled.
+pub fn choose(candidates: &[Candidate]) -> Result<Vec<PathBuf>, Error> {
+    let mut cmd = Command::new("yad");
+    cmd.args([
+        "--list",
+        "--title=Select a Wireguard configuration",
+        "--column=Name",
+        "--column=Path",
+        "--column=Status",
+        "--width=500",
+        "--height=400",
+        "--multiple",
+    ]);
+    for candidate in candidates {
+        cmd.arg(&candidate.name)
+            .arg(&candidate.path)
+            .arg(if candidate.connected { "connected" } else { "" });
+    }
+    let output = cmd
+        .stderr(Stdio::null())
+        .output()
+        .map_err(|source| Error::Dialog { source })?;
+    if !output.status.success() {
+        return Ok(Vec::new());
+    }
+    // yad prints one "name|path|status|" line per selected row.
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|row| row.split('|').nth(1))
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+        .collect())
+}
@@ -0,0 +1,408 @@
+//! `wgb self`: installation, update and removal of the tool.
+//!
+//! Every command first plans its steps, then prints and applies them in
+//! order, or only prints them with `--dry-run`.
+
+use std::env;
+use std::ffi::{CStr, OsStr};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{chown, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+use wgb_core::config::{Config, CONFIG_FILE_NAME};
+use wgb_core::migration::{self, CURRENT_VERSION};
+use wgb_core::{exec, log};
+
+use crate::cli::{SetupArgs, SetupCommand, Shell};
+use crate::complete;
+use crate::error::Error;
+use crate::{man, ui};
+
+/// Programs the backends rely on, looked up once installed.
+const REQUIRED_PROGRAMS: [&str; 2] = ["wg", "wg-quick"];
+
+/// Runs `action`; `config` is the configuration file given with
+/// `--config`, if any.
+pub fn run(action: SetupCommand, config: Option<PathBuf>) -> Result<(), Error> {
+    match action {
+        SetupCommand::Install { target } => install(&target, config, false),
+        SetupCommand::Update { target } => install(&target, config, true),
+        SetupCommand::Uninstall { target, purge } => uninstall(&target, config, purge),
+    }
+}
+
+/// Installs the running binary, the completion scripts and the man pages,
+/// or replaces the installed ones when `update`, then creates the log
+/// directory and creates or upgrades the configuration file.
+fn install(target: &SetupArgs, config: Option<PathBuf>, update: bool) -> Result<(), Error> {
+    let binary = binary_path(target);
+    let installed = binary.exists();
+    if update && !installed {
+        return Err(Error::NotInstalled(binary));
+    }
+    if !update && installed {
+        return Err(Error::Installed(binary));
+    }
+    let owner = User::invoking();
+    let mut steps = Vec::new();
+    let exe = env::current_exe()?;
+    if !same_file(&exe, &binary) {
+        steps.push(Step::Copy {
+            from: exe,
+            to: binary,
+        });
+    }
+    for (shell, path) in completion_paths(target) {
+        steps.push(Step::Write {
+            path,
+            contents: complete::script(shell),
+        });
+    }
+    for (path, contents) in man_paths(target) {
+        steps.push(Step::Write { path, contents });
+    }
+    let log_dir = staged(target, log_dir());
+    if !log_dir.exists() {
+        steps.push(Step::CreateDir {
+            path: log_dir,
+            owner: owner.ids,
+        });
+    }
+    // Packages are built for every user: their configuration is created by
+    // the first run of wgb self install, or by hand.
+    if target.destdir.is_none() {
+        let path = config_path(config, &owner)?;
+        if !path.exists() {
+            steps.push(Step::CreateConfig {
+                path,
+                owner: owner.ids,
+            });
+        } else if let Some(from) = outdated(&path) {
+            steps.push(Step::UpgradeConfig {
+                path,
+                from,
+                owner: owner.ids,
+            });
+        }
+    }
+    apply(&steps, target.dry_run)?;
+    if !target.dry_run {
+        for program in REQUIRED_PROGRAMS.iter().filter(|p| !exec::in_path(p)) {
+            ui::print_warn(&format!(
+                "'{program}' was not found, install the WireGuard tools (wireguard-tools)"
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Removes the installed binary, completion scripts and man pages and, when
+/// `purge`, the configuration file and the log directory.
+fn uninstall(target: &SetupArgs, config: Option<PathBuf>, purge: bool) -> Result<(), Error> {
+    let binary = binary_path(target);
+    if !binary.exists() && !purge {
+        return Err(Error::NotInstalled(binary));
+    }
+    let mut steps = Vec::new();
+    if binary.exists() {
+        steps.push(Step::Remove { path: binary });
+    }
+    let installed = completion_paths(target)
+        .into_iter()
+        .map(|(_, path)| path)
+        .chain(man_paths(target).map(|(path, _)| path));
+    for path in installed {
+        if path.exists() {
+            steps.push(Step::Remove { path });
+        }
+    }
+    if purge {
+        if target.destdir.is_none() {
+            let path = config_path(config, &User::invoking())?;
+            if path.exists() {
+                steps.push(Step::Remove { path });
+            }
+        }
+        let log_dir = staged(target, log_dir());
+        if log_dir.exists() {
+            steps.push(Step::RemoveDir { path: log_dir });
+        }
+    }
+    apply(&steps, target.dry_run)
+}
+
+/// Prints `steps` and applies them unless `dry_run`.
+fn apply(steps: &[Step], dry_run: bool) -> Result<(), Error> {
+    if steps.is_empty() {
+        ui::print_info("Nothing to do");
+        return Ok(());
+    }
+    for step in steps {
+        ui::print_info(&step.to_string());
+        if !dry_run {
+            step.apply()?;
+        }
+    }
+    if dry_run {
+        ui::print_warn("Dry run, nothing was changed");
+    } else {
+        ui::print_info("Done");
+    }
+    Ok(())
+}
+
+/// A change made to the system.
+#[derive(Debug)]
+enum Step {
+    /// Installs the binary `from` as `to`.
+    Copy {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    /// Writes a file readable by everyone.
+    Write {
+        path: PathBuf,
+        contents: &'static str,
+    },
+    /// Creates a directory, given to `owner` when set.
+    CreateDir {
+        path: PathBuf,
+        owner: Option<(u32, u32)>,
+    },
+    /// Writes an empty configuration file, given to `owner` when set.
+    CreateConfig {
+        path: PathBuf,
+        owner: Option<(u32, u32)>,
+    },
+    /// Upgrades a configuration file of version `from`.
+    UpgradeConfig {
+        path: PathBuf,
+        from: u32,
+        owner: Option<(u32, u32)>,
+    },
+    Remove {
+        path: PathBuf,
+    },
+    RemoveDir {
+        path: PathBuf,
+    },
+}
+
+impl Step {
+    fn apply(&self) -> Result<(), Error> {
+        let install_err = |path: &Path| {
+            let path = path.to_path_buf();
+            move |source| Error::Install { path, source }
+        };
+        match self {
+            Self::Copy { from, to } => copy_binary(from, to).map_err(install_err(to)),
+            Self::Write { path, contents } => write_file(path, contents).map_err(install_err(path)),
+            Self::CreateDir { path, owner } => fs::create_dir_all(path)
+                .and_then(|()| fs::set_permissions(path, fs::Permissions::from_mode(0o770)))
+                .and_then(|()| give(path, *owner))
+                .map_err(install_err(path)),
+            Self::CreateConfig { path, owner } => {
+                Config::default().save(path)?;
+                give(path, *owner).map_err(install_err(path))
+            }
+            Self::UpgradeConfig { path, from, owner } => {
+                Config::load(path)?;
+                // The upgraded file, its copy and the lock file are written
+                // anew, as root under sudo; the copy is missing if the
+                // upgrade failed.
+                for path in [
+                    path.clone(),
+                    Config::backup_path(path, *from),
+                    Config::lock_path(path),
+                ] {
+                    match give(&path, *owner) {
+                        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                        result => result.map_err(install_err(&path))?,
+                    }
+                }
+                Ok(())
+            }
+            Self::Remove { path } => fs::remove_file(path).map_err(|source| Error::Uninstall {
+                path: path.clone(),
+                source,
+            }),
+            Self::RemoveDir { path } => {
+                fs::remove_dir_all(path).map_err(|source| Error::Uninstall {
+                    path: path.clone(),
+                    source,
+                })
+            }
+        }
+    }
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Copy { from, to } => {
+                write!(f, "Install '{}' as '{}'", from.display(), to.display())
+            }
+            Self::Write { path, .. } => write!(f, "Write '{}'", path.display()),
+            Self::CreateDir { path, .. } => write!(f, "Create '{}'", path.display()),
+            Self::CreateConfig { path, .. } => {
+                write!(f, "Create the configuration file '{}'", path.display())
+            }
+            Self::UpgradeConfig { path, from, .. } => write!(
+                f,
+                "Upgrade '{}' from version {from} to {CURRENT_VERSION}",
+                path.display()
+            ),
+            Self::Remove { path } | Self::RemoveDir { path } => {
+                write!(f, "Remove '{}'", path.display())
+            }
+        }
+    }
+}
+
+/// Copies the binary `from` to a temporary file next to `to`, then renames
+/// it over `to`, so that a running `wgb` is not overwritten.
+fn copy_binary(from: &Path, to: &Path) -> io::Result<()> {
+    if let Some(dir) = to.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut tmp = to.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    let result = fs::copy(from, &tmp)
+        .and_then(|_| fs::set_permissions(&tmp, fs::Permissions::from_mode(0o755)))
+        .and_then(|()| fs::rename(&tmp, to));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+fn write_file(path: &Path, contents: &str) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, contents)?;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o644))
+}
+
+/// Gives `path` to `owner`, a user and group ID, when set.
+fn give(path: &Path, owner: Option<(u32, u32)>) -> io::Result<()> {
+    match owner {
+        Some((uid, gid)) => chown(path, Some(uid), Some(gid)),
+        None => Ok(()),
+    }
+}
+
+/// Returns the installed binary: `<destdir>/<prefix>/bin/wgb`.
+fn binary_path(target: &SetupArgs) -> PathBuf {
+    staged(target, target.prefix.join("bin").join("wgb"))
+}
+
+/// Returns where the completion scripts are installed, in the directories
+/// the shells load them from.
+fn completion_paths(target: &SetupArgs) -> [(Shell, PathBuf); 3] {
+    let share = target.prefix.join("share");
+    [
+        (Shell::Bash, "bash-completion/completions/wgb"),
+        (Shell::Zsh, "zsh/site-functions/_wgb"),
+        (Shell::Fish, "fish/vendor_completions.d/wgb.fish"),
+    ]
+    .map(|(shell, path)| (shell, staged(target, share.join(path))))
+}
+
+/// Returns where the man pages are installed, `<prefix>/share/man/man<section>`,
+/// with their content.
+fn man_paths(target: &SetupArgs) -> impl Iterator<Item = (PathBuf, &'static str)> + '_ {
+    let man = target.prefix.join("share").join("man");
+    man::PAGES.iter().map(move |(name, contents)| {
+        let dir = format!("man{}", man::section(name));
+        (staged(target, man.join(dir).join(name)), *contents)
+    })
+}
+
+/// Returns the directory of the default log file.
+fn log_dir() -> PathBuf {
+    Path::new(log::LOG_FILE)
+        .parent()
+        .unwrap_or(Path::new("/"))
+        .to_path_buf()
+}
+
+/// Returns `path` inside the staging directory of `target`, if any.
+fn staged(target: &SetupArgs, path: PathBuf) -> PathBuf {
+    match &target.destdir {
+        Some(destdir) => destdir.join(path.strip_prefix("/").unwrap_or(&path)),
+        None => path,
+    }
+}
+
+/// Returns the configuration file of `user`, unless another one is given.
+fn config_path(config: Option<PathBuf>, user: &User) -> Result<PathBuf, Error> {
+    match (config, &user.home) {
+        (Some(path), _) => Ok(path),
+        (None, Some(home)) => Ok(home.join(CONFIG_FILE_NAME)),
+        (None, None) => Ok(Config::default_path()?),
+    }
+}
+
+/// Returns the version of the configuration file at `path` when it is older
+/// than [`CURRENT_VERSION`]. An unreadable file is left to
+/// [`Config::load`] to report.
+fn outdated(path: &Path) -> Option<u32> {
+    let text = fs::read_to_string(path).ok()?;
+    let value = serde_json::from_str(&text).ok()?;
+    migration::version_of(&value).filter(|&version| version < CURRENT_VERSION)
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// The user the tool is installed for: the one who ran `sudo`, if it did,
+/// else the current user.
+#[derive(Debug)]
+struct User {
+    /// User and group ID, set when they differ from those of the process.
+    ids: Option<(u32, u32)>,
+    home: Option<PathBuf>,
+}
+
+impl User {
+    fn invoking() -> Self {
+        let id = |name| env::var(name).ok().and_then(|id| id.parse::<u32>().ok());
+        match (exec::is_root(), id("SUDO_UID"), id("SUDO_GID")) {
+            (true, Some(uid), Some(gid)) if uid != 0 => Self {
+                ids: Some((uid, gid)),
+                home: home_of(uid),
+            },
+            _ => Self {
+                ids: None,
+                home: None,
+            },
+        }
+    }
+}
+
+/// Returns the home directory of the user `uid`, from the password
+/// database.
+fn home_of(uid: u32) -> Option<PathBuf> {
+    let mut buf = vec![0; 4096];
+    // SAFETY: `passwd` is plain data; `getpwuid_r` fills it with pointers
+    // into `buf`, which outlives their use below.
+    unsafe {
+        let mut pwd: libc::passwd = std::mem::zeroed();
+        let mut result = std::ptr::null_mut();
+        let rc = libc::getpwuid_r(uid, &mut pwd, buf.as_mut_ptr(), buf.len(), &mut result);
+        if rc != 0 || result.is_null() || pwd.pw_dir.is_null() {
+            return None;
+        }
+        let dir = CStr::from_ptr(pwd.pw_dir);
+        Some(PathBuf::from(OsStr::from_bytes(dir.to_bytes())))
+    }
+}
@@ -0,0 +1,91 @@
+//! Interaction with the user: colored messages and prompts.
+
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::os::fd::AsRawFd;
+
+use zeroize::Zeroizing;
+
+use crate::error::Error;
+
+const CYAN: &str = "\x1b[36m";
+const YELLOW: &str = "\x1b[33m";
+const RED: &str = "\x1b[31m";
+const NC: &str = "\x1b[0m";
+
+/// Prints an information message.
+pub fn print_info(msg: &str) {
+    println!("{CYAN}{msg}{NC}");
+}
+
+/// Prints a warning message.
+pub fn print_warn(msg: &str) {
+    println!("{YELLOW}{msg}{NC}");
+}
+
+/// Prints an error message on the standard error.
+pub fn print_error(msg: &str) {
+    eprintln!("{RED}{msg}{NC}");
+}
+
+/// Prints what the user can do about an error on the standard error.
+pub fn print_hint(msg: &str) {
+    eprintln!("{YELLOW}hint: {msg}{NC}");
+}
+
+/// Prints a progress message over the previous one, when the standard
+/// output is a terminal.
+pub fn print_progress(msg: &str) {
+    let mut stdout = io::stdout();
+    if stdout.is_terminal() {
+        let _ = write!(stdout, "\r{CYAN}{msg}{NC}\x1b[K");
+        let _ = stdout.flush();
+    }
+}
+
+/// Clears the progress message, see [`print_progress`].
+pub fn end_progress() {
+    let mut stdout = io::stdout();
+    if stdout.is_terminal() {
+        let _ = write!(stdout, "\r\x1b[K");
+        let _ = stdout.flush();
+    }
+}
+
+/// Prints `prompt` and reads a line, without its line terminator. End of
+/// input reads as an empty line.
+pub fn read_line(prompt: &str) -> Result<String, Error> {
+    print!("{prompt}");
+    io::stdout().flush()?;
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line)?;
+    Ok(line.trim_end_matches(['\n', '\r']).to_owned())
+}
+
+/// Reads a secret like [`read_line`], without echoing it when the input is
+/// a terminal.
+pub fn read_secret(prompt: &str) -> Result<Zeroizing<String>, Error> {
+    let stdin = io::stdin();
+    if !stdin.is_terminal() {
+        let mut line = Zeroizing::new(String::new());
+        stdin.lock().read_line(&mut line)?;
+        let len = line.trim_end_matches(['\n', '\r']).len();
+        line.truncate(len);
+        return Ok(line);
+    }
+    let fd = stdin.as_raw_fd();
+    // SAFETY: termios is plain data, filled by tcgetattr before being used.
+    let mut saved: libc::termios = unsafe { std::mem::zeroed() };
+    // SAFETY: `fd` is the standard input, open for the whole process.
+    if unsafe { libc::tcgetattr(fd, &mut saved) } != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    let mut silent = saved;
+    silent.c_lflag &= !libc::ECHO;
+    silent.c_lflag |= libc::ECHONL;
+    // SAFETY: as above, with a termios obtained from tcgetattr.
+    unsafe { libc::tcsetattr(fd, libc::TCSANOW, &silent) };
+    let line = read_line(prompt).map(Zeroizing::new);
+    // SAFETY: as above.
+    unsafe { libc::tcsetattr(fd, libc::TCSANOW, &saved) };
+    line
+}
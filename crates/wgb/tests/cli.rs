@@ -0,0 +1,313 @@
+//! Runs `wgb` through the mock backend, with a configuration, a state file
+//! and profiles of its own in a temporary directory.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+use serde_json::Value;
+use tempfile::TempDir;
+
+const OFFICE: &str = include_str!("../../../tests/fixtures/office.conf");
+
+const HOME: &str = "[Interface]\n\
+    PrivateKey = 4DJbuxgh5hCTvyXsbRcSPwWpp4UwPLhU6ENV+6iFUmA=\n\
+    Address = 10.1.0.2/32\n\
+    \n\
+    [Peer]\n\
+    PublicKey = 9yyrdRs/rPTKvAB8MmEaPqGgMJZRc0UoH/NHzlqSAV0=\n\
+    AllowedIPs = 10.1.0.0/16\n\
+    Endpoint = home.example.com:51820\n";
+
+/// A configuration searching the profiles `office` and `home`, both without
+/// a 2FA step, and an empty state.
+struct Sandbox {
+    dir: TempDir,
+}
+
+impl Sandbox {
+    fn new() -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        let profiles = dir.path().join("profiles");
+        fs::create_dir(&profiles).unwrap();
+        fs::write(profiles.join("office.conf"), OFFICE).unwrap();
+        fs::write(profiles.join("home.conf"), HOME).unwrap();
+        let config = serde_json::json!({
+            "version": 3,
+            "conf_path": [profiles],
+            "confs": [],
+        });
+        fs::write(dir.path().join("wgbconf.json"), config.to_string()).unwrap();
+        let sandbox = Self { dir };
+        for name in ["office", "home"] {
+            sandbox.success(&["profile", "set", name, "--no-token"]);
+        }
+        sandbox
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.path().join(name)
+    }
+
+    fn profile(&self, name: &str) -> PathBuf {
+        self.path("profiles").join(format!("{name}.conf"))
+    }
+
+    /// Creates the directory `name` and adds it to the search paths.
+    fn search(&self, name: &str) -> PathBuf {
+        let dir = self.path(name);
+        fs::create_dir(&dir).unwrap();
+        let path = self.path("wgbconf.json");
+        let mut config: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        config["conf_path"]
+            .as_array_mut()
+            .unwrap()
+            .push(display(&dir).into());
+        fs::write(&path, config.to_string()).unwrap();
+        dir
+    }
+
+    /// Runs `wgb args`, isolated from the environment of the user.
+    fn run(&self, args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_wgb"))
+            .args(["--backend", "mock", "--non-interactive"])
+            .args(args)
+            .env_clear()
+            .env("PATH", std::env::var_os("PATH").unwrap_or_default())
+            .env("HOME", self.dir.path())
+            .env("XDG_CONFIG_HOME", self.path("xdg"))
+            .env("WGB_CONFIG", self.path("wgbconf.json"))
+            .env("WGB_STATE", self.path("state.json"))
+            .env("WGB_MOCK_STATE", self.path("mock.json"))
+            .env("WGB_LOG_FILE", self.path("wgb.log"))
+            .output()
+            .unwrap()
+    }
+
+    /// Runs `wgb args`, which must succeed.
+    fn success(&self, args: &[&str]) -> Output {
+        let output = self.run(args);
+        assert!(
+            output.status.success(),
+            "wgb {args:?} failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        output
+    }
+
+    /// Runs `wgb --output json args` and returns its document.
+    fn json(&self, args: &[&str]) -> Value {
+        let args = [&["--output", "json"], args].concat();
+        serde_json::from_slice(&self.success(&args).stdout).unwrap()
+    }
+
+    /// Returns the paths of the profiles `wgb status` reports.
+    fn connected(&self) -> Vec<String> {
+        self.json(&["status"])["interfaces"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|interface| interface["profile"].as_str().unwrap().to_owned())
+            .collect()
+    }
+
+    /// Returns the interfaces the mock backend holds.
+    fn devices(&self) -> Vec<String> {
+        match fs::read_to_string(self.path("mock.json")) {
+            Ok(text) => {
+                let devices: serde_json::Map<String, Value> = serde_json::from_str(&text).unwrap();
+                devices.keys().cloned().collect()
+            }
+            Err(_) => Vec::new(),
+        }
+    }
+}
+
+fn display(path: &Path) -> String {
+    path.display().to_string()
+}
+
+#[test]
+fn connects_reports_and_disconnects_a_profile() {
+    let sandbox = Sandbox::new();
+    assert!(sandbox.connected().is_empty());
+
+    sandbox.success(&["connect", "office"]);
+    assert_eq!(sandbox.devices(), ["office"]);
+    assert_eq!(sandbox.connected(), [display(&sandbox.profile("office"))]);
+    let status = sandbox.json(&["status"]);
+    let interface = &status["interfaces"][0];
+    assert_eq!(interface["name"], "office");
+    assert_eq!(interface["profile_name"], "office");
+    assert_eq!(
+        interface["peers"][0]["public_key"],
+        "xTIBA5rboUvnH4htodjb6e697QjLERt1NAB4mZqp8Dg="
+    );
+
+    let table = String::from_utf8(sandbox.success(&["status"]).stdout).unwrap();
+    assert!(table.starts_with("INTERFACE"), "{table}");
+    assert!(table.contains("office"), "{table}");
+
+    sandbox.success(&["disconnect", "office"]);
+    assert!(sandbox.devices().is_empty());
+    assert!(sandbox.connected().is_empty());
+}
+
+#[test]
+fn disconnects_the_only_connected_profile_without_a_name() {
+    let sandbox = Sandbox::new();
+    sandbox.success(&["connect", "home"]);
+    sandbox.success(&["disconnect"]);
+    assert!(sandbox.devices().is_empty());
+
+    sandbox.success(&["connect", "home"]);
+    sandbox.success(&["connect", "office"]);
+    let output = sandbox.run(&["disconnect"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(sandbox.devices(), ["home", "office"]);
+}
+
+#[test]
+fn connecting_twice_keeps_one_tunnel() {
+    let sandbox = Sandbox::new();
+    sandbox.success(&["connect", "office"]);
+    sandbox.success(&["connect", "office"]);
+    assert_eq!(sandbox.devices(), ["office"]);
+}
+
+#[test]
+fn lists_the_profiles_of_the_search_paths() {
+    let sandbox = Sandbox::new();
+    sandbox.success(&["profile", "set", "office", "--alias", "work"]);
+    sandbox.success(&["connect", "work"]);
+
+    let list = sandbox.json(&["list"]);
+    let profiles = list["profiles"].as_array().unwrap();
+    let names: Vec<&str> = profiles
+        .iter()
+        .map(|p| p["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, ["home", "work"]);
+    let office = &profiles[1];
+    assert_eq!(office["path"], display(&sandbox.profile("office")).as_str());
+    assert_eq!(office["alias"], "work");
+    assert_eq!(office["connected"], true);
+    assert_eq!(office["token"], false);
+    assert_eq!(office["addresses"][0], "10.0.0.2/32");
+    assert_eq!(office["endpoints"][0], "vpn.example.com:51820");
+    assert_eq!(profiles[0]["connected"], false);
+
+    let table = String::from_utf8(sandbox.success(&["list"]).stdout).unwrap();
+    let header = table.lines().next().unwrap();
+    assert!(header.starts_with("NAME"), "{table}");
+    assert!(table.contains("work"), "{table}");
+}
+
+#[test]
+fn refuses_an_unknown_profile() {
+    let sandbox = Sandbox::new();
+    let output = sandbox.run(&["connect", "nowhere"]);
+    assert_eq!(output.status.code(), Some(6));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("[010]"));
+    assert!(sandbox.devices().is_empty());
+}
+
+#[test]
+fn asks_for_the_settings_of_a_new_profile() {
+    let sandbox = Sandbox::new();
+    let path = sandbox.path("profiles").join("lab.conf");
+    fs::write(&path, OFFICE.replace("10.0.0.2", "10.2.0.2")).unwrap();
+    let output = sandbox.run(&["connect", "lab"]);
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("[060]") && stderr.contains("--no-token"),
+        "{stderr}"
+    );
+    assert!(sandbox.devices().is_empty());
+}
+
+#[test]
+fn refuses_a_name_shared_by_two_profiles() {
+    let sandbox = Sandbox::new();
+    let other = sandbox.search("other");
+    fs::write(
+        other.join("office.conf"),
+        OFFICE.replace("10.0.0.2", "10.2.0.2"),
+    )
+    .unwrap();
+    let output = sandbox.run(&["connect", "office"]);
+    assert_eq!(output.status.code(), Some(6));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("[011]"), "{stderr}");
+    assert!(sandbox.devices().is_empty());
+
+    let list = sandbox.json(&["list"]);
+    let names: Vec<&str> = list["profiles"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|p| p["name"].as_str().unwrap())
+        .collect();
+    let office = display(&sandbox.profile("office"));
+    let copy = display(&other.join("office.conf"));
+    assert_eq!(names, [copy.as_str(), "home", office.as_str()]);
+}
+
+#[test]
+fn the_settings_follow_a_moved_profile() {
+    let sandbox = Sandbox::new();
+    let output = sandbox.success(&["profile", "set", "office", "--alias", "work"]);
+    let shown = [output.stdout, output.stderr].concat();
+    assert!(String::from_utf8_lossy(&shown).contains("Profile 'work' updated"));
+    let id = sandbox.json(&["list"])["profiles"][1]["id"].clone();
+    assert!(id.is_string());
+
+    let moved = sandbox.search("moved").join("office.conf");
+    fs::rename(sandbox.profile("office"), &moved).unwrap();
+    sandbox.success(&["connect", "work"]);
+    assert_eq!(sandbox.devices(), ["office"]);
+
+    let list = sandbox.json(&["list"]);
+    let profiles = list["profiles"].as_array().unwrap();
+    let office = profiles.iter().find(|p| p["name"] == "work").unwrap();
+    assert_eq!(office["path"], display(&moved).as_str());
+    assert_eq!(office["id"], id);
+    assert_eq!(office["connected"], true);
+}
+
+#[test]
+fn a_dry_run_installs_nothing() {
+    let sandbox = Sandbox::new();
+    let prefix = sandbox.path("prefix");
+    fs::create_dir(&prefix).unwrap();
+    let prefix = display(&prefix);
+    sandbox.success(&["self", "install", "--prefix", &prefix, "--dry-run"]);
+    assert_eq!(fs::read_dir(&prefix).unwrap().count(), 0);
+}
+
+#[test]
+fn installs_the_binary_the_man_pages_and_the_completions() {
+    let sandbox = Sandbox::new();
+    let root = sandbox.path("root");
+    let destdir = display(&root);
+    // The staging directory keeps the log directory out of the system.
+    sandbox.success(&["self", "install", "--prefix", "/usr", "--destdir", &destdir]);
+    let usr = root.join("usr");
+    let binary = fs::read(usr.join("bin/wgb")).unwrap();
+    assert_eq!(binary, fs::read(env!("CARGO_BIN_EXE_wgb")).unwrap());
+    for page in ["man1/wgb.1", "man1/wgb-connect.1", "man5/wgbconf.5"] {
+        assert!(usr.join("share/man").join(page).is_file(), "{page}");
+    }
+    for script in [
+        "bash-completion/completions/wgb",
+        "zsh/site-functions/_wgb",
+        "fish/vendor_completions.d/wgb.fish",
+    ] {
+        assert!(usr.join("share").join(script).is_file(), "{script}");
+    }
+    assert!(root.join("var/log/wg-bridge").is_dir());
+
+    let output = sandbox.run(&["self", "install", "--prefix", "/usr", "--destdir", &destdir]);
+    assert!(!output.status.success());
+}
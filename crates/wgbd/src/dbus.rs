@@ -0,0 +1,274 @@
+//! D-Bus interface of the daemon, for the desktop applets.
+//!
+//! The daemon owns [`BUS_NAME`] and serves the interface of the same name at
+//! [`OBJECT_PATH`]. Callers are subject to the same policy as the clients of
+//! the socket.
+
+use std::sync::Arc;
+use std::thread;
+
+use clap::ValueEnum;
+use serde::Serialize;
+use tracing::warn;
+use wgb_core::backend::Device;
+use wgb_core::discovery::ProfileInfo;
+use wgb_core::ipc::{Request, Response};
+use wgb_core::secret::Secrets;
+use zbus::blocking::connection::Builder;
+use zbus::blocking::Connection;
+use zbus::fdo::DBusProxy;
+use zbus::message::Header;
+use zbus::object_server::SignalEmitter;
+use zbus::zvariant::Type;
+use zbus::{interface, DBusError};
+
+use crate::policy::Peer;
+use crate::server::Server;
+
+/// Well-known name of the service.
+pub const BUS_NAME: &str = "org.lunaticfringers.WgBridge";
+
+/// Path of the object implementing the interface.
+pub const OBJECT_PATH: &str = "/org/lunaticfringers/WgBridge";
+
+/// Message bus to register on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Bus {
+    /// The system bus, for a daemon run by systemd
+    System,
+    /// The session bus of the user, for testing
+    Session,
+}
+
+/// Errors returned to the callers.
+#[derive(Debug, DBusError)]
+#[zbus(prefix = "org.lunaticfringers.WgBridge.Error")]
+pub enum Error {
+    #[zbus(error)]
+    ZBus(zbus::Error),
+    /// The policy refused the request.
+    Denied(String),
+    /// The request failed.
+    Failed(String),
+}
+
+/// A profile, as returned by `ListProfiles`.
+#[derive(Debug, Serialize, Type)]
+pub struct ProfileRecord {
+    path: String,
+    addresses: Vec<String>,
+    endpoints: Vec<String>,
+    /// Why the profile could not be read, empty if it could.
+    error: String,
+}
+
+/// An interface, as returned by `Status`.
+#[derive(Debug, Serialize, Type)]
+pub struct DeviceRecord {
+    name: String,
+    public_key: String,
+    listen_port: u16,
+    peers: Vec<PeerRecord>,
+}
+
+/// A peer of an interface; empty strings and zeros stand for unknown values.
+#[derive(Debug, Serialize, Type)]
+pub struct PeerRecord {
+    public_key: String,
+    endpoint: String,
+    allowed_ips: Vec<String>,
+    /// Seconds since the epoch.
+    latest_handshake: u64,
+    rx_bytes: u64,
+    tx_bytes: u64,
+}
+
+struct WgBridge {
+    server: Arc<Server>,
+}
+
+#[interface(name = "org.lunaticfringers.WgBridge")]
+impl WgBridge {
+    /// Brings up the profile at `path`. Sealed and encrypted profiles are
+    /// brought up by `wgb`, which fetches their secrets and decrypts them as
+    /// the user.
+    async fn connect(
+        &self,
+        path: String,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] conn: &zbus::Connection,
+    ) -> Result<(), Error> {
+        let request = Request::Connect {
+            path: path.into(),
+            secrets: Secrets::default(),
+            plaintext: None,
+        };
+        self.call(&header, conn, request).await.map(drop)
+    }
+
+    /// Tears down the profile at `path`.
+    async fn disconnect(
+        &self,
+        path: String,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] conn: &zbus::Connection,
+    ) -> Result<(), Error> {
+        let request = Request::Disconnect {
+            path: path.into(),
+            plaintext: None,
+        };
+        self.call(&header, conn, request).await.map(drop)
+    }
+
+    /// Lists the profiles under the search paths of the daemon.
+    async fn list_profiles(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] conn: &zbus::Connection,
+    ) -> Result<Vec<ProfileRecord>, Error> {
+        match self.call(&header, conn, Request::List).await? {
+            Response::Profiles { profiles } => Ok(profiles.into_iter().map(Into::into).collect()),
+            other => Err(unexpected(&other)),
+        }
+    }
+
+    /// Reports the state of the WireGuard interfaces.
+    async fn status(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] conn: &zbus::Connection,
+    ) -> Result<Vec<DeviceRecord>, Error> {
+        match self.call(&header, conn, Request::Status).await? {
+            Response::Devices { devices } => Ok(devices.into_iter().map(Into::into).collect()),
+            other => Err(unexpected(&other)),
+        }
+    }
+
+    /// Emitted when a tunnel is brought up or down through the daemon.
+    #[zbus(signal)]
+    async fn state_changed(
+        emitter: &SignalEmitter<'_>,
+        path: &str,
+        interface: &str,
+        connected: bool,
+    ) -> zbus::Result<()>;
+}
+
+impl WgBridge {
+    /// Performs `request` on behalf of the sender of the message.
+    async fn call(
+        &self,
+        header: &Header<'_>,
+        conn: &zbus::Connection,
+        request: Request,
+    ) -> Result<Response, Error> {
+        let peer = caller(header, conn).await?;
+        let server = Arc::clone(&self.server);
+        match blocking::unblock(move || server.answer(&peer, &request)).await {
+            Response::Denied { message } => Err(Error::Denied(message)),
+            Response::Failed { message } => Err(Error::Failed(message)),
+            response => Ok(response),
+        }
+    }
+}
+
+/// Returns the credentials of the sender of the message.
+async fn caller(header: &Header<'_>, conn: &zbus::Connection) -> Result<Peer, Error> {
+    let sender = header
+        .sender()
+        .ok_or_else(|| Error::Denied("anonymous caller".to_owned()))?;
+    let credentials = DBusProxy::new(conn)
+        .await?
+        .get_connection_credentials(sender.clone().into())
+        .await
+        .map_err(zbus::Error::from)?;
+    let (Some(pid), Some(uid)) = (credentials.process_id(), credentials.unix_user_id()) else {
+        return Err(Error::Denied("unknown caller".to_owned()));
+    };
+    let mut peer = Peer::of_process(pid, uid).map_err(|e| Error::Denied(e.to_string()))?;
+    if let Some(groups) = credentials.unix_group_ids() {
+        peer.groups.clone_from(groups);
+    }
+    Ok(peer)
+}
+
+fn unexpected(response: &Response) -> Error {
+    Error::Failed(format!("unexpected answer {response:?}"))
+}
+
+/// Registers the service on `bus` and emits the state changes of `server`.
+pub fn serve(bus: Bus, server: Arc<Server>) -> zbus::Result<()> {
+    let builder = match bus {
+        Bus::System => Builder::system()?,
+        Bus::Session => Builder::session()?,
+    };
+    let conn = builder
+        .name(BUS_NAME)?
+        .serve_at(
+            OBJECT_PATH,
+            WgBridge {
+                server: Arc::clone(&server),
+            },
+        )?
+        .build()?;
+    let changes = server.subscribe();
+    thread::spawn(move || {
+        for change in changes {
+            if let Err(err) = emit(
+                &conn,
+                &change.path.to_string_lossy(),
+                &change.interface,
+                change.connected,
+            ) {
+                warn!("unable to emit StateChanged: {err}");
+            }
+        }
+    });
+    Ok(())
+}
+
+fn emit(conn: &Connection, path: &str, interface: &str, connected: bool) -> zbus::Result<()> {
+    let iface = conn.object_server().interface::<_, WgBridge>(OBJECT_PATH)?;
+    zbus::block_on(WgBridge::state_changed(
+        iface.signal_emitter(),
+        path,
+        interface,
+        connected,
+    ))
+}
+
+impl From<ProfileInfo> for ProfileRecord {
+    fn from(info: ProfileInfo) -> Self {
+        Self {
+            path: info.path.display().to_string(),
+            addresses: info.addresses.iter().map(ToString::to_string).collect(),
+            endpoints: info.endpoints,
+            error: info.error.unwrap_or_default(),
+        }
+    }
+}
+
+impl From<Device> for DeviceRecord {
+    fn from(device: Device) -> Self {
+        Self {
+            name: device.name,
+            public_key: device.public_key.unwrap_or_default(),
+            listen_port: device.listen_port.unwrap_or_default(),
+            peers: device
+                .peers
+                .into_iter()
+                .map(|peer| PeerRecord {
+                    public_key: peer.public_key,
+                    endpoint: peer.endpoint.map(|e| e.to_string()).unwrap_or_default(),
+                    allowed_ips: peer.allowed_ips.iter().map(ToString::to_string).collect(),
+                    latest_handshake: peer
+                        .latest_handshake
+                        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                        .map_or(0, |d| d.as_secs()),
+                    rx_bytes: peer.rx_bytes,
+                    tx_bytes: peer.tx_bytes,
+                })
+                .collect(),
+        }
+    }
+}
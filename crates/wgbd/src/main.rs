@@ -0,0 +1,187 @@
+//! `wgbd`, the privileged daemon of wg-bridge.
+//!
+//! It listens on a Unix socket and brings the WireGuard tunnels up and down
+//! on behalf of the members of a group, so that `wgb` does not need sudo.
+//! The same operations can be exposed on D-Bus for the desktop applets.
+
+mod dbus;
+mod policy;
+mod server;
+
+use std::fs;
+use std::io;
+use std::os::unix::fs::{chown, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::thread;
+
+use clap::{ArgAction, Parser};
+use tracing::{error, info, warn};
+use wgb_core::backend::BackendKind;
+use wgb_core::config::DEFAULT_SEARCH_PATH;
+use wgb_core::{exec, ipc, log};
+
+use crate::dbus::Bus;
+use crate::policy::Policy;
+use crate::server::Server;
+
+/// Privileged daemon of wg-bridge
+#[derive(Debug, Parser)]
+#[command(name = "wgbd", version)]
+struct Args {
+    /// Socket to listen on
+    #[arg(long, env = ipc::SOCKET_ENV, default_value = ipc::SOCKET_PATH, value_name = "PATH")]
+    socket: PathBuf,
+
+    /// Group whose members may use the daemon
+    #[arg(long, default_value = "wgbridge")]
+    group: String,
+
+    /// Directory whose profiles are listed to the clients, if owned by root;
+    /// may be repeated
+    #[arg(long, value_name = "DIR", default_value = DEFAULT_SEARCH_PATH)]
+    search_path: Vec<PathBuf>,
+
+    /// Backend used to bring the tunnels up and down
+    #[arg(long, default_value = "wg-quick", value_parser = parse_backend)]
+    backend: BackendKind,
+
+    /// Also expose the daemon on a message bus, as
+    /// org.lunaticfringers.WgBridge
+    #[arg(long, value_enum, value_name = "BUS")]
+    dbus: Option<Bus>,
+
+    /// Log debug events, trace events with -vv
+    #[arg(short, long, action = ArgAction::Count)]
+    verbose: u8,
+
+    /// Log errors only
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Layout of the log lines
+    #[arg(long, value_name = "FORMAT", default_value_t, value_parser = parse_log_format)]
+    log_format: log::Format,
+
+    /// Also log to this file
+    #[arg(long, value_name = "FILE")]
+    log_file: Option<PathBuf>,
+
+    /// Size beyond which the log file is rotated, in MiB
+    #[arg(
+        long,
+        value_name = "MIB",
+        default_value_t = log::DEFAULT_MAX_SIZE / MIB,
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    log_max_size: u64,
+
+    /// Number of rotated log files kept
+    #[arg(long, value_name = "COUNT", default_value_t = log::DEFAULT_MAX_FILES)]
+    log_max_files: u32,
+
+    /// Log to the systemd journal instead of the standard error
+    #[arg(long)]
+    journald: bool,
+}
+
+/// Bytes in a MiB.
+const MIB: u64 = 1024 * 1024;
+
+impl Args {
+    /// Returns the logging settings: to the standard error, or the journal,
+    /// and to the log file if any.
+    fn log_settings(&self) -> log::Settings {
+        let mut settings = log::Settings::new("wgbd");
+        settings.level = log::level(self.verbose, self.quiet);
+        settings.format = self.log_format;
+        settings.file = self.log_file.clone();
+        settings.max_size = self.log_max_size * MIB;
+        settings.max_files = self.log_max_files;
+        settings.stderr = !self.journald;
+        settings.journald = self.journald;
+        settings
+    }
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    log::init(&args.log_settings());
+    match run(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            error!("{err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: Args) -> io::Result<()> {
+    if !exec::is_root() {
+        warn!("not running as root, the backend will fall back to sudo");
+    }
+    let policy = Policy::for_group(&args.group)?.with_search_paths(args.search_path.clone());
+    let listener = bind(&args.socket, policy.gid())?;
+    let server = Arc::new(Server::new(args.backend.build(), policy));
+    if let Some(bus) = args.dbus {
+        dbus::serve(bus, Arc::clone(&server)).map_err(io::Error::other)?;
+        info!("registered as {} on D-Bus", dbus::BUS_NAME);
+    }
+    info!(
+        "listening on {} with the {} backend",
+        args.socket.display(),
+        args.backend
+    );
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                warn!("accept failed: {err}");
+                continue;
+            }
+        };
+        let server = Arc::clone(&server);
+        thread::spawn(move || {
+            if let Err(err) = server.handle(stream) {
+                warn!("{err}");
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Creates the socket at `path`, readable and writable by root and `gid`.
+fn bind(path: &Path, gid: u32) -> io::Result<UnixListener> {
+    if let Some(dir) = path.parent() {
+        if !dir.exists() {
+            fs::create_dir_all(dir)?;
+            fs::set_permissions(dir, fs::Permissions::from_mode(0o755))?;
+        }
+    }
+    if path.exists() {
+        if UnixStream::connect(path).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("another daemon is listening on '{}'", path.display()),
+            ));
+        }
+        fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    if let Err(err) = chown(path, None, Some(gid)) {
+        warn!("unable to give the socket to group {gid}: {err}");
+    }
+    fs::set_permissions(path, fs::Permissions::from_mode(0o660))?;
+    Ok(listener)
+}
+
+fn parse_backend(name: &str) -> Result<BackendKind, String> {
+    name.parse()
+}
+
+fn parse_log_format(name: &str) -> Result<log::Format, String> {
+    name.parse()
+}
@@ -0,0 +1,228 @@
+//! Who may talk to the daemon, and which profiles it accepts to use.
+
+use std::ffi::CString;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Read};
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+
+use wgb_core::discovery;
+use wgb_core::encrypted::{self, Plaintext};
+use wgb_core::profile::Profile;
+
+/// Credentials of the process on the other end of a connection.
+#[derive(Debug, Clone)]
+pub struct Peer {
+    pub pid: i32,
+    pub uid: u32,
+    pub gid: u32,
+    /// Supplementary groups, read from `/proc/<pid>/status`.
+    pub groups: Vec<u32>,
+}
+
+impl Peer {
+    /// Returns the credentials of the process connected to `stream`.
+    pub fn of(stream: &UnixStream) -> io::Result<Self> {
+        let mut cred = libc::ucred {
+            pid: 0,
+            uid: 0,
+            gid: 0,
+        };
+        let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+        // SAFETY: `cred` is a valid `ucred` and `len` holds its size.
+        let ret = unsafe {
+            libc::getsockopt(
+                stream.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_PEERCRED,
+                (&mut cred as *mut libc::ucred).cast(),
+                &mut len,
+            )
+        };
+        if ret != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            pid: cred.pid,
+            uid: cred.uid,
+            gid: cred.gid,
+            groups: supplementary_groups(cred.pid),
+        })
+    }
+
+    /// Returns the credentials of the process `pid` run by `uid`, as
+    /// reported by the message bus.
+    pub fn of_process(pid: u32, uid: u32) -> io::Result<Self> {
+        let pid = i32::try_from(pid)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid process id"))?;
+        let status = fs::read_to_string(format!("/proc/{pid}/status"))?;
+        let gid = status_field(&status, "Gid:")
+            .and_then(|gid| gid.split_whitespace().next()?.parse().ok())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "no group id"))?;
+        Ok(Self {
+            pid,
+            uid,
+            gid,
+            groups: parse_groups(&status),
+        })
+    }
+}
+
+/// Access policy of the daemon.
+#[derive(Debug, Clone)]
+pub struct Policy {
+    gid: u32,
+    search_paths: Vec<PathBuf>,
+}
+
+impl Policy {
+    /// Grants access to root and to the members of the group `name`.
+    pub fn for_group(name: &str) -> io::Result<Self> {
+        let c_name = CString::new(name)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid group name"))?;
+        // SAFETY: `c_name` is a valid C string; the entry is only read
+        // before any other call to the group database.
+        let group = unsafe { libc::getgrnam(c_name.as_ptr()) };
+        if group.is_null() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("group '{name}' does not exist"),
+            ));
+        }
+        // SAFETY: `group` is not null.
+        Ok(Self {
+            gid: unsafe { (*group).gr_gid },
+            search_paths: Vec::new(),
+        })
+    }
+
+    /// Sets the directories whose profiles are listed to the clients.
+    pub fn with_search_paths(mut self, search_paths: Vec<PathBuf>) -> Self {
+        self.search_paths = search_paths;
+        self
+    }
+
+    /// Returns the search paths that may be listed: those owned by root and
+    /// not writable by others. The clients list their own directories.
+    pub fn search_paths(&self) -> impl Iterator<Item = &Path> {
+        self.search_paths
+            .iter()
+            .map(PathBuf::as_path)
+            .filter(|dir| match fs::symlink_metadata(dir) {
+                Ok(meta) => meta.is_dir() && meta.uid() == 0 && meta.mode() & 0o022 == 0,
+                Err(_) => false,
+            })
+    }
+
+    /// Returns the group granted access.
+    pub fn gid(&self) -> u32 {
+        self.gid
+    }
+
+    /// Returns whether `peer` may use the daemon at all.
+    pub fn allows(&self, peer: &Peer) -> bool {
+        peer.uid == 0 || peer.gid == self.gid || peer.groups.contains(&self.gid)
+    }
+
+    /// Reads the profile at `path` on behalf of `peer` and returns the text
+    /// the tunnel is brought up from, so that root never opens the path
+    /// again.
+    ///
+    /// The file is opened once, without following a symbolic link, and the
+    /// open file must be owned by root or by the peer and must not be
+    /// writable by others. For an encrypted profile the text is the one the
+    /// peer sent. Profiles not owned by root, and encrypted ones, must not
+    /// carry `PreUp`/`PostUp`/`PreDown`/`PostDown` hooks, which would run as
+    /// root.
+    pub fn read_profile(
+        &self,
+        path: &Path,
+        plaintext: Option<Plaintext>,
+        peer: &Peer,
+    ) -> Result<Plaintext, String> {
+        let denied = |reason: &str| format!("'{}' {reason}", path.display());
+        if !path.is_absolute() {
+            return Err(denied("is not an absolute path"));
+        }
+        if !discovery::is_profile(path) {
+            return Err(denied("is not a WireGuard profile"));
+        }
+        // O_NONBLOCK keeps a FIFO from blocking the open; it is refused below.
+        let mut file = OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NOFOLLOW | libc::O_NONBLOCK)
+            .open(path)
+            .map_err(|e| match e.raw_os_error() {
+                Some(libc::ELOOP) => denied("is a symbolic link"),
+                _ => format!("{}: {e}", path.display()),
+            })?;
+        let meta = file
+            .metadata()
+            .map_err(|e| format!("{}: {e}", path.display()))?;
+        if !meta.is_file() {
+            return Err(denied("is not a regular file"));
+        }
+        if meta.uid() != 0 && meta.uid() != peer.uid {
+            return Err(denied("is owned by another user"));
+        }
+        if meta.mode() & 0o022 != 0 {
+            return Err(denied("is writable by group or others"));
+        }
+        let encrypted = encrypted::is_encrypted(path);
+        let text = match plaintext {
+            Some(text) if encrypted => text,
+            Some(_) => return Err(denied("is not encrypted but its text was sent")),
+            None if encrypted => return Err(denied("is encrypted but its text was not sent")),
+            None => {
+                let mut text = String::new();
+                file.read_to_string(&mut text)
+                    .map_err(|e| format!("{}: {e}", path.display()))?;
+                Plaintext::from(text)
+            }
+        };
+        if meta.uid() != 0 || encrypted {
+            let profile =
+                Profile::parse(text.as_str(), path).map_err(|e| e.redact().to_string())?;
+            let interface = profile.interface();
+            let hooks = [
+                interface.pre_up(),
+                interface.post_up(),
+                interface.pre_down(),
+                interface.post_down(),
+            ];
+            if hooks.iter().any(|hook| !hook.is_empty()) {
+                return Err(denied(if meta.uid() != 0 {
+                    "has hooks and is not owned by root"
+                } else {
+                    "has hooks and was decrypted by the client"
+                }));
+            }
+        }
+        Ok(text)
+    }
+}
+
+/// Reads the supplementary groups of the process `pid`.
+fn supplementary_groups(pid: i32) -> Vec<u32> {
+    fs::read_to_string(format!("/proc/{pid}/status"))
+        .map(|status| parse_groups(&status))
+        .unwrap_or_default()
+}
+
+fn parse_groups(status: &str) -> Vec<u32> {
+    status_field(status, "Groups:")
+        .map(|groups| {
+            groups
+                .split_whitespace()
+                .filter_map(|gid| gid.parse().ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Returns the value of the line starting with `name` in a process status.
+fn status_field<'a>(status: &'a str, name: &str) -> Option<&'a str> {
+    status.lines().find_map(|line| line.strip_prefix(name))
+}
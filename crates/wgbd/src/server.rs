@@ -0,0 +1,195 @@
+//! Handling of the client connections.
+
+use std::io::BufReader;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Mutex;
+
+use tracing::{error, info, info_span, warn};
+use wgb_core::backend::{self, BackendError, Tunnel, TunnelBackend};
+use wgb_core::discovery;
+use wgb_core::encrypted::Plaintext;
+use wgb_core::ipc::{self, IpcError, Request, Response};
+use wgb_core::secret::Secrets;
+
+use crate::policy::{Peer, Policy};
+
+/// A tunnel brought up or down through the daemon.
+#[derive(Debug, Clone)]
+pub struct StateChange {
+    pub path: PathBuf,
+    pub interface: String,
+    pub connected: bool,
+}
+
+/// State shared by the connections.
+pub struct Server {
+    backend: Box<dyn TunnelBackend>,
+    policy: Policy,
+    /// Serializes the operations changing the interfaces.
+    lock: Mutex<()>,
+    subscribers: Mutex<Vec<Sender<StateChange>>>,
+}
+
+impl Server {
+    pub fn new(backend: Box<dyn TunnelBackend>, policy: Policy) -> Self {
+        Self {
+            backend,
+            policy,
+            lock: Mutex::new(()),
+            subscribers: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Returns a channel receiving the tunnels brought up or down from now
+    /// on, whoever asked for it.
+    pub fn subscribe(&self) -> Receiver<StateChange> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(sender);
+        receiver
+    }
+
+    /// Serves the requests sent on `stream` until the client hangs up.
+    pub fn handle(&self, stream: UnixStream) -> Result<(), IpcError> {
+        let peer = Peer::of(&stream)?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = stream;
+        while let Some(request) = ipc::read_message::<Request>(&mut reader)? {
+            let response = self.answer(&peer, &request);
+            ipc::write_message(&mut writer, &response)?;
+        }
+        Ok(())
+    }
+
+    /// Performs `request` on behalf of `peer`.
+    pub fn answer(&self, peer: &Peer, request: &Request) -> Response {
+        let response = self.dispatch(peer, request);
+        log(peer, request, &response);
+        if let Response::Done = response {
+            match request {
+                Request::Connect { path, .. } => self.notify(path, true),
+                Request::Disconnect { path, .. } => self.notify(path, false),
+                _ => {}
+            }
+        }
+        response
+    }
+
+    fn dispatch(&self, peer: &Peer, request: &Request) -> Response {
+        if !self.policy.allows(peer) {
+            return Response::Denied {
+                message: format!("user {} is not allowed to use wgbd", peer.uid),
+            };
+        }
+        match request {
+            Request::Connect {
+                path,
+                secrets,
+                plaintext,
+            } => self.apply(peer, path, secrets, plaintext, |tunnel| {
+                self.backend.up(tunnel)
+            }),
+            Request::Disconnect { path, plaintext } => {
+                self.apply(peer, path, &Secrets::default(), plaintext, |tunnel| {
+                    self.backend.down(tunnel)
+                })
+            }
+            Request::Sync {
+                path,
+                secrets,
+                plaintext,
+            } => self.apply(peer, path, secrets, plaintext, |tunnel| {
+                self.backend.sync(tunnel)
+            }),
+            Request::Status => match self.backend.show() {
+                Ok(devices) => Response::Devices { devices },
+                Err(err) => failed(err),
+            },
+            Request::List => match discovery::find_profiles(self.policy.search_paths()) {
+                Ok(paths) => Response::Profiles {
+                    profiles: discovery::describe_redacted(&paths),
+                },
+                Err(err) => failed(err),
+            },
+        }
+    }
+
+    /// Runs `op` on the tunnel of the profile at `path`, with the `secrets`
+    /// and `plaintext` sent by the client, if the policy allows it. The
+    /// backend is given the text the policy read, never the path.
+    fn apply(
+        &self,
+        peer: &Peer,
+        path: &Path,
+        secrets: &Secrets,
+        plaintext: &Option<Plaintext>,
+        op: impl FnOnce(&Tunnel) -> Result<(), BackendError>,
+    ) -> Response {
+        let tunnel = match Tunnel::new(path) {
+            Ok(tunnel) => tunnel,
+            Err(err) => return failed(err),
+        };
+        let text = match self.policy.read_profile(path, plaintext.clone(), peer) {
+            Ok(text) => text,
+            Err(message) => return Response::Denied { message },
+        };
+        let tunnel = tunnel
+            .with_secrets(secrets.clone())
+            .with_plaintext(Some(text));
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        match op(&tunnel) {
+            Ok(()) => Response::Done,
+            Err(BackendError::Profile(err)) => failed(err.redact()),
+            Err(err) => failed(err),
+        }
+    }
+
+    fn notify(&self, path: &Path, connected: bool) {
+        let Ok(tunnel) = Tunnel::new(path) else {
+            return;
+        };
+        let change = StateChange {
+            path: path.to_path_buf(),
+            interface: tunnel.interface().to_owned(),
+            connected,
+        };
+        self.subscribers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|subscriber| subscriber.send(change.clone()).is_ok());
+    }
+}
+
+fn failed(err: impl ToString) -> Response {
+    Response::Failed {
+        message: err.to_string(),
+    }
+}
+
+/// Records the outcome of the requests changing the interfaces.
+fn log(peer: &Peer, request: &Request, response: &Response) {
+    let (op, path) = match request {
+        Request::Connect { path, .. } => ("connect", path),
+        Request::Disconnect { path, .. } => ("disconnect", path),
+        Request::Sync { path, .. } => ("sync", path),
+        Request::Status | Request::List => return,
+    };
+    let interface = backend::interface_name(path).unwrap_or_default();
+    let _span = info_span!(
+        "request",
+        peer_uid = peer.uid,
+        peer_pid = peer.pid,
+        profile = %path.display(),
+        interface
+    )
+    .entered();
+    match response {
+        Response::Denied { message } => warn!("{op} denied: {message}"),
+        Response::Failed { message } => error!("{op} failed: {message}"),
+        _ => info!("{op} done"),
+    }
+}
@@ -0,0 +1,215 @@
+//! Calls the D-Bus interface of `wgbd`, run with the mock backend on a
+//! private session bus. Skipped when `dbus-daemon` is not installed.
+
+use std::ffi::CStr;
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tempfile::TempDir;
+use zbus::blocking::connection::Builder;
+use zbus::blocking::fdo::DBusProxy;
+use zbus::blocking::Connection;
+use zbus::names::BusName;
+
+const BUS_NAME: &str = "org.lunaticfringers.WgBridge";
+const OBJECT_PATH: &str = "/org/lunaticfringers/WgBridge";
+
+const OFFICE: &str = include_str!("../../../tests/fixtures/office.conf");
+
+/// `ListProfiles` record: path, addresses, endpoints and error.
+type ProfileRecord = (String, Vec<String>, Vec<String>, String);
+
+/// `Status` record: name, public key, listen port and peers.
+type DeviceRecord = (
+    String,
+    String,
+    u16,
+    Vec<(String, String, Vec<String>, u64, u64, u64)>,
+);
+
+/// Kills the process once dropped.
+struct Killed(Child);
+
+impl Drop for Killed {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+/// A private bus with `wgbd` registered on it, listing `dir/profiles`.
+struct Daemon {
+    dir: TempDir,
+    conn: Connection,
+    _daemon: Killed,
+    _bus: Killed,
+}
+
+impl Daemon {
+    /// Starts the bus and the daemon, or returns `None` without
+    /// `dbus-daemon`.
+    fn start() -> Option<Self> {
+        let dir = tempfile::tempdir().unwrap();
+        let profiles = dir.path().join("profiles");
+        fs::create_dir(&profiles).unwrap();
+        fs::write(profiles.join("office.conf"), OFFICE).unwrap();
+
+        let socket = dir.path().join("bus");
+        let bus = Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address"])
+            .arg(format!("--address=unix:path={}", socket.display()))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn();
+        let Ok(mut bus) = bus else {
+            eprintln!("dbus-daemon is not installed, skipped");
+            return None;
+        };
+        let mut address = String::new();
+        BufReader::new(bus.stdout.take().unwrap())
+            .read_line(&mut address)
+            .unwrap();
+        let address = address.trim().to_owned();
+        let bus = Killed(bus);
+
+        let daemon = Command::new(env!("CARGO_BIN_EXE_wgbd"))
+            .args(["--backend", "mock", "--dbus", "session", "--quiet"])
+            .arg("--socket")
+            .arg(dir.path().join("wgbd.sock"))
+            .args(["--group", &primary_group()])
+            .arg("--search-path")
+            .arg(&profiles)
+            .env("DBUS_SESSION_BUS_ADDRESS", &address)
+            .env("WGB_MOCK_STATE", dir.path().join("mock.json"))
+            .spawn()
+            .unwrap();
+        let daemon = Killed(daemon);
+
+        let conn = Builder::address(address.as_str()).unwrap().build().unwrap();
+        let proxy = DBusProxy::new(&conn).unwrap();
+        let name = BusName::try_from(BUS_NAME).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !proxy.name_has_owner(name.clone()).unwrap() {
+            assert!(Instant::now() < deadline, "wgbd did not register");
+            thread::sleep(Duration::from_millis(50));
+        }
+        Some(Self {
+            dir,
+            conn,
+            _daemon: daemon,
+            _bus: bus,
+        })
+    }
+
+    fn profile(&self, name: &str) -> PathBuf {
+        self.dir
+            .path()
+            .join("profiles")
+            .join(format!("{name}.conf"))
+    }
+
+    /// Calls `method` with `body`, returning the reply or the name of the
+    /// error.
+    fn call<B, R>(&self, method: &str, body: &B) -> Result<R, String>
+    where
+        B: serde::Serialize + zbus::zvariant::DynamicType,
+        R: for<'d> zbus::zvariant::DynamicDeserialize<'d>,
+    {
+        match self
+            .conn
+            .call_method(Some(BUS_NAME), OBJECT_PATH, Some(BUS_NAME), method, body)
+        {
+            Ok(reply) => Ok(reply.body().deserialize().unwrap()),
+            Err(zbus::Error::MethodError(name, _, _)) => Err(name.to_string()),
+            Err(err) => panic!("{method} failed: {err}"),
+        }
+    }
+
+    fn connect(&self, path: &Path) -> Result<(), String> {
+        self.call("Connect", &(path.display().to_string(),))
+    }
+
+    fn disconnect(&self, path: &Path) -> Result<(), String> {
+        self.call("Disconnect", &(path.display().to_string(),))
+    }
+
+    fn interfaces(&self) -> Vec<String> {
+        let devices: Vec<DeviceRecord> = self.call("Status", &()).unwrap();
+        devices.into_iter().map(|device| device.0).collect()
+    }
+}
+
+/// Returns the name of the primary group of the tests, allowed to use the
+/// daemon.
+fn primary_group() -> String {
+    // SAFETY: the entry is copied before any other call to getgrgid.
+    unsafe {
+        let group = libc::getgrgid(libc::getegid());
+        assert!(!group.is_null());
+        CStr::from_ptr((*group).gr_name)
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+#[test]
+fn connects_and_disconnects_a_profile() {
+    let Some(daemon) = Daemon::start() else {
+        return;
+    };
+    let office = daemon.profile("office");
+    assert!(daemon.interfaces().is_empty());
+
+    daemon.connect(&office).unwrap();
+    assert_eq!(daemon.interfaces(), ["office"]);
+    let devices: Vec<DeviceRecord> = daemon.call("Status", &()).unwrap();
+    let peers = &devices[0].3;
+    assert_eq!(peers[0].0, "xTIBA5rboUvnH4htodjb6e697QjLERt1NAB4mZqp8Dg=");
+    assert_eq!(peers[0].2, ["0.0.0.0/0"]);
+
+    daemon.disconnect(&office).unwrap();
+    assert!(daemon.interfaces().is_empty());
+}
+
+#[test]
+fn refuses_the_profiles_the_policy_forbids() {
+    let Some(daemon) = Daemon::start() else {
+        return;
+    };
+    let link = daemon.dir.path().join("link.conf");
+    std::os::unix::fs::symlink(daemon.profile("office"), &link).unwrap();
+    assert_eq!(
+        daemon.connect(&link),
+        Err("org.lunaticfringers.WgBridge.Error.Denied".to_owned())
+    );
+    assert!(daemon.connect(&daemon.profile("missing")).is_err());
+    assert!(daemon.interfaces().is_empty());
+}
+
+#[test]
+fn lists_the_profiles_of_the_search_paths() {
+    let Some(daemon) = Daemon::start() else {
+        return;
+    };
+    let profiles: Vec<ProfileRecord> = daemon.call("ListProfiles", &()).unwrap();
+    // The daemon only lists the directories owned by root.
+    // SAFETY: geteuid has no precondition.
+    if unsafe { libc::geteuid() } != 0 {
+        assert!(profiles.is_empty());
+        return;
+    }
+    let office = daemon.profile("office").display().to_string();
+    assert_eq!(
+        profiles,
+        [(
+            office,
+            vec!["10.0.0.2/32".to_owned()],
+            vec!["vpn.example.com:51820".to_owned()],
+            String::new(),
+        )]
+    );
+}
@@ -88,9 +88,9 @@ function install(){
       if ([ "$(md5sum < "$tool_dir/version")" != "$(md5sum < ./version)" ] && [ "$update" == "true" ]) || [ "$force" == "true" ]; then
         _install_sw
         mv "$wgbconf" "$wgbconf.bak"
-        jq --slurpfile customer "$wgbconf.bak" '.conf_path |= (. + $customer[0].conf_path)' "$conf" > "$wgbconf"
-        jq --slurpfile customer "$wgbconf.bak" '.confs |= (. + $customer[0].confs)' "$wgbconf" > "$wgbconf.tmp"
-        mv "$wgbconf.tmp" "$wgbconf"
+        # Keep every key of the current file, only adding the ones it lacks;
+        # wgb migrates it to the new version the next time it reads it.
+        jq -s '(.[1].version // 0) as $v | .[0] * .[1] | .version = $v' "$conf" "$wgbconf.bak" > "$wgbconf"
       else
         print_warn "Software already installed"
         exit 1